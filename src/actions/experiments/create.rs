@@ -1,8 +1,11 @@
-use crate::actions::{experiments::ExperimentError, Action, ActionsCtx};
+use crate::actions::experiments::{check_target, ExperimentError};
+use crate::actions::{Action, ActionsCtx};
 use crate::db::QueryUtils;
-use crate::experiments::{Assignee, CapLints, CrateSelect, Experiment, GitHubIssue, Mode, Status};
+use crate::experiments::{
+    Assignee, CapLints, CrateSelect, Experiment, GitHubIssue, Lockfile, Mode, Status,
+};
 use crate::prelude::*;
-use crate::toolchain::Toolchain;
+use crate::toolchain::{self, CratePatch, Toolchain};
 use chrono::Utc;
 
 pub struct CreateExperiment {
@@ -16,6 +19,11 @@ pub struct CreateExperiment {
     pub ignore_blacklist: bool,
     pub assign: Option<Assignee>,
     pub requirement: Option<String>,
+    pub target: Option<String>,
+    pub patches: Vec<CratePatch>,
+    pub lockfile: Lockfile,
+    /// Replace floating channels (`stable`, `beta`, `nightly`) with the release they point to.
+    pub pin_toolchains: bool,
 }
 
 impl CreateExperiment {
@@ -34,37 +42,75 @@ impl CreateExperiment {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            target: None,
+            patches: Vec::new(),
+            lockfile: Lockfile::Keep,
+            pin_toolchains: false,
         }
     }
 }
 
 impl Action for CreateExperiment {
-    fn apply(self, ctx: &ActionsCtx) -> Fallible<()> {
+    fn apply(mut self, ctx: &ActionsCtx) -> Fallible<()> {
         // Ensure no duplicate experiments are created
         if Experiment::exists(ctx.db, &self.name)? {
             return Err(ExperimentError::AlreadyExists(self.name).into());
         }
 
+        // An empty target is the same as building for the host
+        self.target = self.target.filter(|target| !target.is_empty());
+        check_target(self.mode, self.target.as_deref())?;
+
+        // Pin floating channels, so every agent tests the same compiler for the whole experiment
+        let requested = self.toolchains;
+        let toolchains = if self.pin_toolchains {
+            [
+                requested[0].pinned(toolchain::fetch_dist_manifest)?,
+                requested[1].pinned(toolchain::fetch_dist_manifest)?,
+            ]
+        } else {
+            requested.clone()
+        };
+
         // Ensure no experiment with duplicate toolchains is created
-        if self.toolchains[0] == self.toolchains[1] {
+        if toolchains[0] == toolchains[1] {
             return Err(ExperimentError::DuplicateToolchains.into());
         }
 
-        let crates = crate::crates::lists::get_crates(&self.crates, ctx.db, ctx.config)?;
+        // Only remember the requested toolchain if it was actually pinned
+        let requested_names = [0, 1].map(|i| {
+            if requested[i] != toolchains[i] {
+                Some(requested[i].to_string())
+            } else {
+                None
+            }
+        });
+
+        let crate_select = self.crates.with_seeds();
+        let crates = crate::crates::lists::get_crates(&crate_select, ctx.db, ctx.config)?;
+
+        let patches = if self.patches.is_empty() {
+            None
+        } else {
+            let patches: Vec<_> = self.patches.iter().map(|p| p.to_string()).collect();
+            Some(serde_json::to_string(&patches)?)
+        };
 
         ctx.db.transaction(|transaction| {
             transaction.execute(
                 "INSERT INTO experiments \
                  (name, mode, cap_lints, toolchain_start, toolchain_end, priority, created_at, \
                  status, github_issue, github_issue_url, github_issue_number, ignore_blacklist, \
-                 assigned_to, requirement) \
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14);",
+                 assigned_to, requirement, requested_toolchain_start, requested_toolchain_end, \
+                 target, patches, crate_select, lockfile) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, \
+                 ?17, ?18, ?19, ?20);",
                 &[
                     &self.name,
                     &self.mode.to_str(),
                     &self.cap_lints.to_str(),
-                    &self.toolchains[0].to_string(),
-                    &self.toolchains[1].to_string(),
+                    &toolchains[0].to_string(),
+                    &toolchains[1].to_string(),
                     &self.priority,
                     &Utc::now(),
                     &Status::Queued.to_str(),
@@ -74,6 +120,12 @@ impl Action for CreateExperiment {
                     &self.ignore_blacklist,
                     &self.assign.map(|a| a.to_string()),
                     &self.requirement,
+                    &requested_names[0],
+                    &requested_names[1],
+                    &self.target,
+                    &patches,
+                    &crate_select.to_string(),
+                    &self.lockfile.to_str(),
                 ],
             )?;
 
@@ -100,7 +152,7 @@ mod tests {
     use crate::crates::Crate;
     use crate::db::{Database, QueryUtils};
     use crate::experiments::{
-        Assignee, CapLints, CrateSelect, Experiment, GitHubIssue, Mode, Status,
+        Assignee, CapLints, CrateSelect, Experiment, GitHubIssue, Lockfile, Mode, Status,
     };
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
 
@@ -130,6 +182,10 @@ mod tests {
             ignore_blacklist: true,
             assign: None,
             requirement: Some("linux".to_string()),
+            target: None,
+            patches: Vec::new(),
+            lockfile: Lockfile::Regenerate,
+            pin_toolchains: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -145,6 +201,7 @@ mod tests {
             ex.get_crates(ctx.db).unwrap(),
             crate::crates::lists::get_crates(&CrateSelect::Local, &db, &config).unwrap()
         );
+        assert_eq!(ex.crate_select, Some(CrateSelect::Local));
         assert_eq!(ex.cap_lints, CapLints::Forbid);
         assert_eq!(ex.github_issue.as_ref().unwrap().api_url.as_str(), api_url);
         assert_eq!(
@@ -157,6 +214,9 @@ mod tests {
         assert!(ex.assigned_to.is_none());
         assert!(ex.ignore_blacklist);
         assert_eq!(ex.requirement, Some("linux".to_string()));
+        assert_eq!(ex.requested_toolchains, [None, None]);
+        assert_eq!(ex.target, None);
+        assert_eq!(ex.lockfile, Lockfile::Regenerate);
     }
 
     #[test]
@@ -253,6 +313,10 @@ mod tests {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            target: None,
+            patches: Vec::new(),
+            lockfile: Lockfile::Keep,
+            pin_toolchains: false,
         }
         .apply(&ctx)
         .unwrap_err();
@@ -283,6 +347,10 @@ mod tests {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            target: None,
+            patches: Vec::new(),
+            lockfile: Lockfile::Keep,
+            pin_toolchains: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -299,6 +367,10 @@ mod tests {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            target: None,
+            patches: Vec::new(),
+            lockfile: Lockfile::Keep,
+            pin_toolchains: false,
         }
         .apply(&ctx)
         .unwrap_err();
@@ -308,4 +380,35 @@ mod tests {
             Some(&ExperimentError::AlreadyExists("foo".into()))
         );
     }
+
+    #[test]
+    fn test_target_with_unsupported_mode() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let err = CreateExperiment {
+            mode: Mode::BuildAndTest,
+            target: Some("wasm32-unknown-unknown".to_string()),
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::TargetNotSupported(Mode::BuildAndTest))
+        );
+
+        CreateExperiment {
+            mode: Mode::CheckOnly,
+            target: Some("wasm32-unknown-unknown".to_string()),
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert_eq!(ex.target, Some("wasm32-unknown-unknown".to_string()));
+    }
 }
@@ -1,8 +1,9 @@
-use crate::actions::{experiments::ExperimentError, Action, ActionsCtx};
+use crate::actions::experiments::{check_target, ExperimentError};
+use crate::actions::{Action, ActionsCtx};
 use crate::db::QueryUtils;
-use crate::experiments::{Assignee, CapLints, CrateSelect, Experiment, Mode, Status};
+use crate::experiments::{Assignee, CapLints, CrateSelect, Experiment, Lockfile, Mode, Status};
 use crate::prelude::*;
-use crate::toolchain::Toolchain;
+use crate::toolchain::{CratePatch, Toolchain};
 
 pub struct EditExperiment {
     pub name: String,
@@ -14,6 +15,10 @@ pub struct EditExperiment {
     pub ignore_blacklist: Option<bool>,
     pub assign: Option<Assignee>,
     pub requirement: Option<String>,
+    pub target: Option<String>,
+    pub lockfile: Option<Lockfile>,
+    /// The new patches of the experiment, replacing the existing ones.
+    pub patches: Option<Vec<CratePatch>>,
 }
 
 impl EditExperiment {
@@ -29,6 +34,9 @@ impl EditExperiment {
             ignore_blacklist: None,
             assign: None,
             requirement: None,
+            target: None,
+            lockfile: None,
+            patches: None,
         }
     }
 }
@@ -45,11 +53,21 @@ impl Action for EditExperiment {
             return Err(ExperimentError::CanOnlyEditQueuedExperiments.into());
         }
 
+        // An empty target clears the target of the experiment
+        let target = match self.target.as_deref() {
+            Some("") => None,
+            Some(target) => Some(target),
+            None => ex.target.as_deref(),
+        };
+        // Ensure the target is still usable with the (possibly changed) mode
+        check_target(self.mode.unwrap_or(ex.mode), target)?;
+
         ctx.db.transaction(|t| {
             // Try to update both toolchains
             for (i, col) in ["toolchain_start", "toolchain_end"].iter().enumerate() {
                 if let Some(tc) = self.toolchains[i].take() {
                     ex.toolchains[i] = tc;
+                    ex.requested_toolchains[i] = None;
 
                     // Ensure no duplicate toolchain is inserted
                     if ex.toolchains[0] == ex.toolchains[1] {
@@ -57,7 +75,11 @@ impl Action for EditExperiment {
                     }
 
                     let changes = t.execute(
-                        &format!("UPDATE experiments SET {} = ?1 WHERE name = ?2;", col),
+                        &format!(
+                            "UPDATE experiments SET {0} = ?1, requested_{0} = NULL \
+                             WHERE name = ?2;",
+                            col
+                        ),
                         &[&ex.toolchains[i].to_string(), &self.name],
                     )?;
                     assert_eq!(changes, 1);
@@ -78,9 +100,15 @@ impl Action for EditExperiment {
             // Try to update the list of crates
             // This is also done if ignore_blacklist is changed to recalculate the skipped crates
             let new_crates = if let Some(crates) = self.crates {
-                Some(crate::crates::lists::get_crates(
-                    &crates, ctx.db, ctx.config,
-                )?)
+                let crates = crates.with_seeds();
+                let changes = t.execute(
+                    "UPDATE experiments SET crate_select = ?1 WHERE name = ?2;",
+                    &[&crates.to_string(), &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                let crates_vec = crate::crates::lists::get_crates(&crates, ctx.db, ctx.config)?;
+                ex.crate_select = Some(crates);
+                Some(crates_vec)
             } else if self.ignore_blacklist.is_some() {
                 Some(ex.get_crates(ctx.db)?)
             } else {
@@ -157,6 +185,43 @@ impl Action for EditExperiment {
                 ex.requirement = Some(requirement);
             }
 
+            // Try to update the target
+            if let Some(target) = self.target {
+                let target = Some(target).filter(|target| !target.is_empty());
+                let changes = t.execute(
+                    "UPDATE experiments SET target = ?1 WHERE name = ?2;",
+                    &[&target, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.target = target;
+            }
+
+            // Try to update the lockfile handling
+            if let Some(lockfile) = self.lockfile {
+                let changes = t.execute(
+                    "UPDATE experiments SET lockfile = ?1 WHERE name = ?2;",
+                    &[&lockfile.to_str(), &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.lockfile = lockfile;
+            }
+
+            // Try to update the patches
+            if let Some(patches) = self.patches {
+                let serialized = if patches.is_empty() {
+                    None
+                } else {
+                    let patches: Vec<_> = patches.iter().map(|p| p.to_string()).collect();
+                    Some(serde_json::to_string(&patches)?)
+                };
+                let changes = t.execute(
+                    "UPDATE experiments SET patches = ?1 WHERE name = ?2;",
+                    &[&serialized, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.patches = patches;
+            }
+
             Ok(())
         })?;
         Ok(())
@@ -170,7 +235,7 @@ mod tests {
     use crate::config::{Config, CrateConfig};
     use crate::crates::Crate;
     use crate::db::{Database, QueryUtils};
-    use crate::experiments::{Assignee, CapLints, CrateSelect, Experiment, Mode, Status};
+    use crate::experiments::{Assignee, CapLints, CrateSelect, Experiment, Lockfile, Mode, Status};
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
 
     #[test]
@@ -198,13 +263,20 @@ mod tests {
             name: "foo".to_string(),
             toolchains: ["stable".parse().unwrap(), "beta".parse().unwrap()],
             mode: Mode::BuildAndTest,
-            crates: CrateSelect::Random(20),
+            crates: CrateSelect::Random {
+                count: 20,
+                seed: None,
+            },
             cap_lints: CapLints::Forbid,
             priority: 0,
             github_issue: None,
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            target: None,
+            patches: Vec::new(),
+            lockfile: Lockfile::Keep,
+            pin_toolchains: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -223,6 +295,9 @@ mod tests {
             ignore_blacklist: Some(true),
             assign: Some(Assignee::CLI),
             requirement: Some("windows".to_string()),
+            target: Some("wasm32-unknown-unknown".to_string()),
+            lockfile: Some(Lockfile::Regenerate),
+            patches: Some(vec!["libc=version:0.2.200".parse().unwrap()]),
         }
         .apply(&ctx)
         .unwrap();
@@ -238,6 +313,13 @@ mod tests {
         assert!(ex.ignore_blacklist);
         assert_eq!(ex.assigned_to, Some(Assignee::CLI));
         assert_eq!(ex.requirement, Some("windows".to_string()));
+        assert_eq!(ex.target, Some("wasm32-unknown-unknown".to_string()));
+        assert_eq!(ex.lockfile, Lockfile::Regenerate);
+        assert_eq!(
+            ex.patches,
+            vec!["libc=version:0.2.200".parse::<CratePatch>().unwrap()]
+        );
+        assert_eq!(ex.crate_select, Some(CrateSelect::Local));
 
         assert_eq!(
             ex.get_crates(ctx.db).unwrap(),
@@ -331,6 +413,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_target_with_unsupported_mode() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment {
+            mode: Mode::CheckOnly,
+            target: Some("wasm32-unknown-unknown".to_string()),
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        // Switching to a mode that runs the built code isn't possible with a target
+        let err = EditExperiment {
+            mode: Some(Mode::BuildAndTest),
+            ..EditExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::TargetNotSupported(Mode::BuildAndTest))
+        );
+
+        // Clearing the target allows switching to any mode
+        EditExperiment {
+            mode: Some(Mode::BuildAndTest),
+            target: Some(String::new()),
+            ..EditExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert_eq!(ex.mode, Mode::BuildAndTest);
+        assert_eq!(ex.target, None);
+    }
+
     #[test]
     fn test_editing_missing_experiment() {
         let db = Database::temp().unwrap();
@@ -6,6 +6,16 @@ pub use self::create::CreateExperiment;
 pub use self::delete::DeleteExperiment;
 pub use self::edit::EditExperiment;
 
+use crate::experiments::Mode;
+
+/// Only modes that don't need to run the built code can target a non-host platform.
+fn check_target(mode: Mode, target: Option<&str>) -> Result<(), ExperimentError> {
+    match (mode, target) {
+        (_, None) | (Mode::CheckOnly, Some(_)) | (Mode::BuildOnly, Some(_)) => Ok(()),
+        (mode, Some(_)) => Err(ExperimentError::TargetNotSupported(mode)),
+    }
+}
+
 #[derive(Debug, failure::Fail)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub enum ExperimentError {
@@ -17,4 +27,6 @@ pub enum ExperimentError {
     DuplicateToolchains,
     #[fail(display = "it's only possible to edit queued experiments")]
     CanOnlyEditQueuedExperiments,
+    #[fail(display = "a custom target can't be used in the {} mode", _0)]
+    TargetNotSupported(Mode),
 }
@@ -1,10 +1,13 @@
 use crate::actions::{Action, ActionsCtx};
-use crate::crates::lists::{GitHubList, List, LocalList, RegistryList};
+use crate::crates::lists::{
+    AltRegistryList, GitHubList, GitHubUnlockedList, List, LocalList, RegistryList,
+};
 use crate::prelude::*;
 
 pub struct UpdateLists {
     pub github: bool,
     pub registry: bool,
+    pub alt_registries: bool,
     pub local: bool,
 }
 
@@ -13,6 +16,7 @@ impl Default for UpdateLists {
         UpdateLists {
             github: true,
             registry: true,
+            alt_registries: true,
             local: true,
         }
     }
@@ -23,11 +27,20 @@ impl Action for UpdateLists {
         if self.github {
             info!("updating GitHub repositories list");
             GitHubList::default().update(ctx.db)?;
+            GitHubUnlockedList::default().update(ctx.db)?;
         }
 
         if self.registry {
             info!("updating crates.io crates list");
-            RegistryList.update(ctx.db)?;
+            let list = RegistryList::new(&ctx.config.registry);
+            list.update(ctx.db)?;
+            list.update_dependencies(ctx.db)?;
+            list.update_metadata(ctx.db)?;
+        }
+
+        if self.alt_registries {
+            info!("updating alternate registries crates list");
+            AltRegistryList::new(&ctx.config.alt_registries).update(ctx.db)?;
         }
 
         if self.local {
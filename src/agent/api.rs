@@ -1,10 +1,11 @@
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 use crate::agent::Capabilities;
 use crate::crates::Crate;
 use crate::experiments::Experiment;
 use crate::prelude::*;
-use crate::results::TestResult;
+use crate::results::{ResolvedDependency, TestResult};
 use crate::server::api_types::{AgentConfig, ApiResponse, CraterToken};
 use crate::toolchain::Toolchain;
 use crate::utils;
@@ -174,6 +175,9 @@ impl AgentApi {
         toolchain: &Toolchain,
         log: &[u8],
         result: &TestResult,
+        members: &BTreeMap<String, TestResult>,
+        dependencies: &[ResolvedDependency],
+        host_result: Option<&TestResult>,
         version: Option<(&Crate, &Crate)>,
     ) -> Fallible<()> {
         self.retry(|this| {
@@ -187,6 +191,9 @@ impl AgentApi {
                             "toolchain": toolchain,
                             "result": result,
                             "log": base64::encode(log),
+                            "members": members,
+                            "dependencies": dependencies,
+                            "host_result": host_result,
                         },
                     ],
                     "version": version
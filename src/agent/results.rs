@@ -3,16 +3,22 @@ use crate::config::Config;
 use crate::crates::Crate;
 use crate::experiments::Experiment;
 use crate::prelude::*;
-use crate::results::{EncodingType, TestResult, WriteResults};
+use crate::results::{EncodingType, ResolvedDependency, TestResult, WriteResults};
 use crate::toolchain::Toolchain;
 use rustwide::logging::{self, LogStorage};
-use std::collections::{hash_map::Entry::Occupied, HashMap};
+use std::collections::{hash_map::Entry::Occupied, BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 pub struct ResultsUploader<'a> {
     api: &'a AgentApi,
     versions: Arc<Mutex<HashMap<Crate, (Crate, bool)>>>,
+    /// Member results waiting to be sent along with the result of their crate.
+    members: Arc<Mutex<HashMap<(Crate, Toolchain), BTreeMap<String, TestResult>>>>,
+    /// Resolved dependencies waiting to be sent along with the result of their crate.
+    dependencies: Arc<Mutex<HashMap<(Crate, Toolchain), Vec<ResolvedDependency>>>>,
+    /// Host results waiting to be sent along with the result of their crate.
+    host_results: Arc<Mutex<HashMap<(Crate, Toolchain), TestResult>>>,
 }
 
 impl<'a> ResultsUploader<'a> {
@@ -20,6 +26,9 @@ impl<'a> ResultsUploader<'a> {
         ResultsUploader {
             api,
             versions: Arc::new(Mutex::new(HashMap::new())),
+            members: Arc::new(Mutex::new(HashMap::new())),
+            dependencies: Arc::new(Mutex::new(HashMap::new())),
+            host_results: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
@@ -57,8 +66,27 @@ impl<'a> WriteResults for ResultsUploader<'a> {
         F: FnOnce() -> Fallible<TestResult>,
     {
         let storage = existing_logs.unwrap_or_else(|| LogStorage::from(config));
-        let result = logging::capture(&storage, f)?;
+        let result = logging::capture(&storage, f);
         let output = storage.to_string();
+        // The pending records are removed before bailing, so they don't leak when the job fails
+        let members = self
+            .members
+            .lock()
+            .unwrap()
+            .remove(&(krate.clone(), toolchain.clone()))
+            .unwrap_or_default();
+        let dependencies = self
+            .dependencies
+            .lock()
+            .unwrap()
+            .remove(&(krate.clone(), toolchain.clone()))
+            .unwrap_or_default();
+        let host_result = self
+            .host_results
+            .lock()
+            .unwrap()
+            .remove(&(krate.clone(), toolchain.clone()));
+        let result = result?;
 
         let mut updated = None;
         let mut new_version = None;
@@ -87,9 +115,54 @@ impl<'a> WriteResults for ResultsUploader<'a> {
             toolchain,
             output.as_bytes(),
             &result,
+            &members,
+            &dependencies,
+            host_result.as_ref(),
             new_version.map(|new| (krate, new)),
         )?;
 
         Ok(result)
     }
+
+    fn record_member_results(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        members: BTreeMap<String, TestResult>,
+    ) -> Fallible<()> {
+        self.members
+            .lock()
+            .unwrap()
+            .insert((krate.clone(), toolchain.clone()), members);
+        Ok(())
+    }
+
+    fn record_resolved_dependencies(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        dependencies: Vec<ResolvedDependency>,
+    ) -> Fallible<()> {
+        self.dependencies
+            .lock()
+            .unwrap()
+            .insert((krate.clone(), toolchain.clone()), dependencies);
+        Ok(())
+    }
+
+    fn record_host_result(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        result: TestResult,
+    ) -> Fallible<()> {
+        self.host_results
+            .lock()
+            .unwrap()
+            .insert((krate.clone(), toolchain.clone()), result);
+        Ok(())
+    }
 }
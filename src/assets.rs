@@ -64,6 +64,7 @@ load_files! {
 
         "ui/queue.html",
         "ui/experiment.html",
+        "ui/diff.html",
 
         "ui/404.html",
         "ui/500.html",
@@ -14,14 +14,17 @@ use crater::agent::{self, Capabilities};
 use crater::config::Config;
 use crater::crates::Crate;
 use crater::db::Database;
-use crater::experiments::{Assignee, CapLints, DeferredCrateSelect, Experiment, Mode, Status};
+use crater::experiments::{
+    Assignee, CapLints, DeferredCrateSelect, Experiment, Lockfile, Mode, Status,
+};
 use crater::report;
 use crater::results::{DatabaseDB, DeleteResults};
 use crater::runner;
 use crater::server;
-use crater::toolchain::Toolchain;
-use failure::{bail, Error, Fallible};
+use crater::toolchain::{CratePatch, Toolchain};
+use failure::{bail, err_msg, Error, Fallible};
 use rustwide::{cmd::SandboxImage, Workspace, WorkspaceBuilder};
+use semver::{Version, VersionReq};
 use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -144,6 +147,34 @@ pub enum Crater {
         assign: Option<Assignee>,
         #[structopt(name = "requirement", long = "requirement")]
         requirement: Option<String>,
+        #[structopt(
+            name = "target",
+            long = "target",
+            help = "Build for the given target triple instead of the host (check and build modes)."
+        )]
+        target: Option<String>,
+        #[structopt(
+            name = "patch",
+            long = "patch",
+            help = "Patch a crate in every build, e.g. libc=version:0.2.200 (can be repeated)."
+        )]
+        patches: Vec<CratePatch>,
+        #[structopt(
+            name = "lockfile",
+            long = "lockfile",
+            help = "Whether to keep the crates' lockfiles or to resolve fresh dependencies.",
+            raw(
+                default_value = "Lockfile::Keep.to_str()",
+                possible_values = "Lockfile::possible_values()"
+            )
+        )]
+        lockfile: Lockfile,
+        #[structopt(
+            name = "no-pin-toolchains",
+            long = "no-pin-toolchains",
+            help = "Don't resolve stable, beta and nightly to the release they point to."
+        )]
+        no_pin_toolchains: bool,
     },
 
     #[structopt(name = "edit", about = "edit an experiment configuration")]
@@ -194,6 +225,18 @@ pub enum Crater {
         assign: Option<Assignee>,
         #[structopt(name = "requirement", long = "requirement")]
         requirement: Option<String>,
+        #[structopt(
+            name = "target",
+            long = "target",
+            help = "Build for the given target triple instead of the host, or for the host if empty."
+        )]
+        target: Option<String>,
+        #[structopt(
+            name = "lockfile",
+            long = "lockfile",
+            raw(possible_values = "Lockfile::possible_values()")
+        )]
+        lockfile: Option<Lockfile>,
     },
 
     #[structopt(name = "delete-ex", about = "delete shared data for experiment")]
@@ -248,6 +291,37 @@ pub enum Crater {
         output_templates: bool,
     },
 
+    #[structopt(
+        name = "find-dependents",
+        about = "list the crates of an experiment depending on a crate"
+    )]
+    FindDependents {
+        #[structopt(name = "experiment", long = "ex", default_value = "default")]
+        ex: Ex,
+        #[structopt(name = "crate")]
+        name: String,
+        #[structopt(
+            name = "version",
+            long = "version",
+            help = "Only list the crates depending on matching versions, e.g. `<1.0.60`."
+        )]
+        version: Option<VersionReq>,
+    },
+
+    #[structopt(
+        name = "diff-reports",
+        about = "compare the results of two experiments crate by crate"
+    )]
+    DiffReports {
+        #[structopt(
+            name = "ex-a",
+            help = "The earlier experiment, e.g. the previous beta run."
+        )]
+        ex_a: Ex,
+        #[structopt(name = "ex-b")]
+        ex_b: Ex,
+    },
+
     #[structopt(name = "server")]
     Server {
         #[structopt(
@@ -315,6 +389,7 @@ impl Crater {
                     actions::UpdateLists {
                         github: lists.remove("github"),
                         registry: lists.remove("registry"),
+                        alt_registries: lists.remove("alt-registry"),
                         local: lists.remove("local"),
                     }
                 };
@@ -342,6 +417,10 @@ impl Crater {
                 ref ignore_blacklist,
                 ref assign,
                 ref requirement,
+                ref target,
+                ref patches,
+                ref lockfile,
+                no_pin_toolchains,
             } => {
                 let config = Config::load()?;
                 let db = Database::open()?;
@@ -358,6 +437,10 @@ impl Crater {
                     ignore_blacklist: *ignore_blacklist,
                     assign: assign.clone(),
                     requirement: requirement.clone(),
+                    target: target.clone(),
+                    patches: patches.clone(),
+                    lockfile: *lockfile,
+                    pin_toolchains: !no_pin_toolchains,
                 }
                 .apply(&ctx)?;
             }
@@ -373,6 +456,8 @@ impl Crater {
                 ref no_ignore_blacklist,
                 ref assign,
                 ref requirement,
+                ref target,
+                ref lockfile,
             } => {
                 let config = Config::load()?;
                 let db = Database::open()?;
@@ -396,6 +481,9 @@ impl Crater {
                     ignore_blacklist,
                     assign: assign.clone(),
                     requirement: requirement.clone(),
+                    target: target.clone(),
+                    lockfile: *lockfile,
+                    patches: None,
                 }
                 .apply(&ctx)?;
             }
@@ -514,6 +602,7 @@ impl Crater {
                         &experiment.get_crates(&db)?,
                         &report::FileWriter::create(dest.0.clone())?,
                         &config,
+                        &report::Popularity::load(&db)?,
                         output_templates,
                     );
 
@@ -527,6 +616,66 @@ impl Crater {
                     bail!("missing experiment: {}", ex.0);
                 }
             }
+            Crater::FindDependents {
+                ref ex,
+                ref name,
+                ref version,
+            } => {
+                let db = Database::open()?;
+
+                if let Some(experiment) = Experiment::get(&db, &ex.0)? {
+                    let dependents = DatabaseDB::new(&db).find_dependents(&experiment, name)?;
+                    for (krate, dep_version) in dependents {
+                        let matches = match (version, Version::parse(&dep_version)) {
+                            (None, _) => true,
+                            (Some(req), Ok(dep_version)) => req.matches(&dep_version),
+                            (Some(_), Err(_)) => false,
+                        };
+                        if matches {
+                            println!("{} ({} {})", krate, name, dep_version);
+                        }
+                    }
+                } else {
+                    bail!("missing experiment: {}", ex.0);
+                }
+            }
+            Crater::DiffReports { ref ex_a, ref ex_b } => {
+                let config = Config::load()?;
+                let db = Database::open()?;
+
+                let get = |ex: &Ex| -> Fallible<(Experiment, Vec<report::CrateResults>)> {
+                    report::load_experiment(&db, &ex.0)?
+                        .ok_or_else(|| err_msg(format!("missing experiment: {}", ex.0)))
+                };
+                let (a, a_crates) = get(ex_a)?;
+                let (b, b_crates) = get(ex_b)?;
+
+                let diff = report::diff_experiments(&config, [&a_crates[..], &b_crates[..]]);
+                let sections = [
+                    (
+                        format!("regressed in {} only", b.name),
+                        &diff.new_regressions,
+                    ),
+                    (
+                        format!("regressed in {} only", a.name),
+                        &diff.gone_regressions,
+                    ),
+                    ("with a changed result".to_string(), &diff.changed),
+                ];
+                let res =
+                    |res: Option<report::Comparison>| res.map_or("not tested", |res| res.to_str());
+                for (title, crates) in &sections {
+                    println!("{} ({}):", title, crates.len());
+                    for krate in crates.iter() {
+                        println!(
+                            "  {}: {} -> {}",
+                            krate.name,
+                            res(krate.res[0]),
+                            res(krate.res[1])
+                        );
+                    }
+                }
+            }
             Crater::Server { bind } => {
                 let config = Config::load()?;
                 server::run(
@@ -67,6 +67,10 @@ pub struct DemoCrates {
     pub crates: Vec<String>,
     pub github_repos: Vec<String>,
     pub local_crates: Vec<String>,
+    #[serde(default)]
+    pub git_repos: Vec<String>,
+    #[serde(default)]
+    pub path_crates: Vec<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -77,6 +81,62 @@ pub struct SandboxConfig {
     pub build_log_max_lines: usize,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RegistryConfig {
+    /// Git URL (or path of a local git mirror) of the crates.io index.
+    #[serde(default = "default_registry_index")]
+    pub index: String,
+    /// Directory containing an already extracted copy of the index, used instead of `index`.
+    #[serde(default)]
+    pub index_path: Option<PathBuf>,
+    /// Directory containing the source of crates.io crates, in the layout created by
+    /// `cargo vendor --versioned-dirs`.
+    #[serde(default)]
+    pub vendor: Option<PathBuf>,
+    /// `crates.csv` file of an extracted crates.io database dump, used to rank the crates by
+    /// their number of downloads.
+    #[serde(default)]
+    pub db_dump: Option<PathBuf>,
+}
+
+fn default_registry_index() -> String {
+    "https://github.com/rust-lang/crates.io-index".into()
+}
+
+impl RegistryConfig {
+    /// The index replacing crates.io in the builds, if it's not the official one.
+    pub fn mirror(&self) -> Option<&str> {
+        const OFFICIAL: &[&str] = &["sparse+https://index.crates.io/"];
+        Some(self.index.as_str())
+            .filter(|index| *index != default_registry_index() && !OFFICIAL.contains(index))
+    }
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        RegistryConfig {
+            index: default_registry_index(),
+            index_path: None,
+            vendor: None,
+            db_dump: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AltRegistryConfig {
+    /// Git URL of the registry index, also used by Cargo to resolve the registry's crates.
+    pub index: String,
+    /// Directory containing an already extracted copy of the index, used instead of `index`.
+    #[serde(default)]
+    pub index_path: Option<PathBuf>,
+    /// Configuration of the registry's crates, keyed by their name.
+    #[serde(default)]
+    pub crates: HashMap<String, CrateConfig>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
@@ -84,6 +144,17 @@ pub struct Config {
     pub crates: HashMap<String, CrateConfig>,
     pub github_repos: HashMap<String, CrateConfig>,
     pub local_crates: HashMap<String, CrateConfig>,
+    /// Configuration of git repositories outside GitHub, keyed by their URL.
+    #[serde(default)]
+    pub git_repos: HashMap<String, CrateConfig>,
+    /// Configuration of crates in a local directory, keyed by their path.
+    #[serde(default)]
+    pub path_crates: HashMap<String, CrateConfig>,
+    #[serde(default)]
+    pub registry: RegistryConfig,
+    /// Alternate registries whose crates are tested too, keyed by the registry name.
+    #[serde(default)]
+    pub alt_registries: HashMap<String, AltRegistryConfig>,
     pub server: ServerConfig,
     pub sandbox: SandboxConfig,
 }
@@ -107,7 +178,12 @@ impl Config {
             Crate::Registry(ref details) => self.crates.get(&details.name),
             Crate::GitHub(ref repo) => self.github_repos.get(&repo.slug()),
             Crate::Local(ref name) => self.local_crates.get(name),
-            Crate::Git(_) | Crate::Path(_) => unimplemented!("unsupported crate"),
+            Crate::Git(ref repo) => self.git_repos.get(&repo.url),
+            Crate::Path(ref path) => self.path_crates.get(path),
+            Crate::AltRegistry(ref details) => self
+                .alt_registries
+                .get(&details.registry)
+                .and_then(|registry| registry.crates.get(&details.name)),
         }
     }
 
@@ -127,6 +203,21 @@ impl Config {
         self.crate_config(c).map(|c| c.broken).unwrap_or(false)
     }
 
+    /// Directory containing the vendored source of a crates.io crate, if available.
+    pub fn vendored_source(&self, c: &Crate) -> Option<PathBuf> {
+        match (c, &self.registry.vendor) {
+            (Crate::Registry(ref details), Some(ref vendor)) => {
+                let path = vendor.join(format!("{}-{}", details.name, details.version));
+                if path.is_dir() {
+                    Some(path)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
     pub fn demo_crates(&self) -> &DemoCrates {
         &self.demo_crates
     }
@@ -239,10 +330,16 @@ impl Default for Config {
                 crates: vec!["lazy_static".into()],
                 github_repos: vec!["brson/hello-rs".into()],
                 local_crates: vec![],
+                git_repos: vec![],
+                path_crates: vec![],
             },
             crates: HashMap::new(),
             github_repos: HashMap::new(),
             local_crates: HashMap::new(),
+            git_repos: HashMap::new(),
+            path_crates: HashMap::new(),
+            registry: RegistryConfig::default(),
+            alt_registries: HashMap::new(),
             sandbox: SandboxConfig {
                 memory_limit: Size::Gigabytes(2),
                 build_log_max_size: Size::Megabytes(1),
@@ -275,7 +372,7 @@ impl From<&'_ Config> for LogStorage {
 #[cfg(test)]
 mod tests {
     use super::Config;
-    use crate::crates::{Crate, GitHubRepo, RegistryCrate};
+    use crate::crates::{AltRegistryCrate, Crate, GitHubRepo, GitRepo, RegistryCrate};
 
     #[test]
     fn test_config() {
@@ -300,7 +397,15 @@ mod tests {
             "lazy_static = { skip = true }\n",
             "[github-repos]\n",
             "\"rust-lang/rust\" = { quiet = true }\n", // :(
-            "[local-crates]\n"
+            "[local-crates]\n",
+            "[git-repos]\n",
+            "\"https://gitlab.com/example/internal\" = { skip-tests = true }\n",
+            "[path-crates]\n",
+            "\"/opt/crates/example\" = { skip = true }\n",
+            "[alt-registries.internal]\n",
+            "index = \"https://git.example.com/index\"\n",
+            "[alt-registries.internal.crates]\n",
+            "flaky = { skip-tests = true }\n"
         );
 
         let list: Config = ::toml::from_str(config).unwrap();
@@ -318,11 +423,38 @@ mod tests {
             org: "rust-lang".into(),
             name: "rust".into(),
             sha: None,
+            subdir: None,
         })));
         assert!(!list.is_quiet(&Crate::GitHub(GitHubRepo {
             org: "rust-lang".into(),
             name: "cargo".into(),
             sha: None,
+            subdir: None,
+        })));
+
+        assert!(list.should_skip_tests(&Crate::Git(GitRepo {
+            url: "https://gitlab.com/example/internal".into(),
+            sha: Some("f00".into()),
+            subdir: None,
         })));
+        assert!(!list.should_skip_tests(&Crate::Git(GitRepo {
+            url: "https://gitlab.com/example/other".into(),
+            sha: None,
+            subdir: None,
+        })));
+
+        assert!(list.should_skip(&Crate::Path("/opt/crates/example".into())));
+        assert!(!list.should_skip(&Crate::Path("/opt/crates/other".into())));
+
+        let alt = |registry: &str, name: &str| {
+            Crate::AltRegistry(AltRegistryCrate {
+                registry: registry.into(),
+                name: name.into(),
+                version: "1.0.0".into(),
+            })
+        };
+        assert!(list.should_skip_tests(&alt("internal", "flaky")));
+        assert!(!list.should_skip_tests(&alt("internal", "stable")));
+        assert!(!list.should_skip_tests(&alt("other", "flaky")));
     }
 }
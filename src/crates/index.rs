@@ -0,0 +1,582 @@
+use crate::config::RegistryConfig;
+use crate::dirs::WORK_DIR;
+use crate::prelude::*;
+use chrono::{DateTime, Utc};
+use crates_index::Index;
+use crossbeam_utils::thread::scope;
+use reqwest::header::IF_MODIFIED_SINCE;
+use reqwest::{Method, StatusCode};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// Number of threads downloading the files of a sparse index.
+const SPARSE_THREADS: usize = 16;
+/// Attempts to download each file of a sparse index, as some of the requests needed to update it
+/// are bound to fail.
+const SPARSE_ATTEMPTS: u32 = 3;
+
+/// A copy of a registry index, either cloned from a git repository, read from a directory where
+/// it was already extracted, or downloaded from a sparse HTTP index.
+pub(crate) enum RegistryIndex {
+    Git {
+        index: Index,
+    },
+    Directory(PathBuf),
+    /// The files of the sparse index at `url` are downloaded to `cache`, in the same layout as
+    /// the git index. Sparse indexes can't be enumerated, so the names of the crates to download
+    /// are read from the `crates.csv` file of a database dump.
+    Sparse {
+        url: String,
+        cache: PathBuf,
+        crates_csv: Option<PathBuf>,
+    },
+}
+
+pub(crate) struct IndexCrate {
+    pub(crate) name: String,
+    /// Published versions, starting from the oldest one.
+    pub(crate) versions: Vec<IndexVersion>,
+}
+
+pub(crate) struct IndexVersion {
+    pub(crate) version: String,
+    pub(crate) yanked: bool,
+    /// Names of the crates this version depends on, regardless of how they're renamed.
+    pub(crate) dependencies: Vec<String>,
+    /// Day the version was published on, like `2020-01-01`, if the index records it.
+    pub(crate) published_on: Option<String>,
+}
+
+impl IndexCrate {
+    pub(crate) fn latest_version(&self) -> Option<&IndexVersion> {
+        self.versions.last()
+    }
+}
+
+#[derive(Deserialize)]
+struct DumpCrateName {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RawConfig {
+    dl: String,
+}
+
+#[derive(Deserialize)]
+struct RawVersion {
+    name: String,
+    vers: String,
+    deps: Vec<RawDependency>,
+    yanked: bool,
+    /// Publication time, only recorded for versions published since Cargo added the field.
+    #[serde(default)]
+    pubtime: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawDependency {
+    name: String,
+    /// Name of the crate on the registry, if the dependency is renamed.
+    package: Option<String>,
+}
+
+impl RegistryIndex {
+    /// Open the index configured in `[registry]`, without fetching updates.
+    pub(crate) fn open(config: &RegistryConfig) -> Fallible<Self> {
+        if let Some(ref path) = config.index_path {
+            return Self::open_directory(path);
+        }
+        if let Some(url) = config.index.strip_prefix("sparse+") {
+            return Ok(RegistryIndex::Sparse {
+                url: url.trim_end_matches('/').to_string(),
+                cache: WORK_DIR.join("crates.io-index-sparse"),
+                crates_csv: config.db_dump.clone(),
+            });
+        }
+
+        Self::open_git(WORK_DIR.join("crates.io-index"), &config.index)
+    }
+
+    /// Open the git index cloned in `path`, cloning it from `url` if it's missing.
+    pub(crate) fn open_git(path: PathBuf, url: &str) -> Fallible<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(RegistryIndex::Git {
+            index: Index::with_path(&path, url).to_failure()?,
+        })
+    }
+
+    pub(crate) fn open_directory(path: &Path) -> Fallible<Self> {
+        if !path.is_dir() {
+            bail!("the registry index at {} doesn't exist", path.display());
+        }
+        Ok(RegistryIndex::Directory(path.into()))
+    }
+
+    pub(crate) fn update(&mut self) -> Fallible<()> {
+        match self {
+            RegistryIndex::Git { index, .. } => index.update().to_failure(),
+            // Extracted indexes are kept up to date by whoever extracted them
+            RegistryIndex::Directory(_) => Ok(()),
+            RegistryIndex::Sparse {
+                url,
+                cache,
+                crates_csv,
+            } => {
+                let crates_csv = crates_csv.as_ref().ok_or_else(|| {
+                    err_msg("listing the crates of a sparse index requires a database dump")
+                })?;
+                update_sparse(url, cache, crates_csv)
+            }
+        }
+    }
+
+    pub(crate) fn crates(&self) -> Fallible<Vec<IndexCrate>> {
+        match self {
+            RegistryIndex::Git { index, .. } => Ok(index.crates().map(convert_crate).collect()),
+            RegistryIndex::Directory(path) | RegistryIndex::Sparse { cache: path, .. } => {
+                let mut crates = Vec::new();
+                for file in crate_files(path) {
+                    crates.push(read_crate_file(&file?)?);
+                }
+                Ok(crates)
+            }
+        }
+    }
+
+    pub(crate) fn crate_(&self, name: &str) -> Fallible<Option<IndexCrate>> {
+        match self {
+            RegistryIndex::Git { index, .. } => Ok(index.crate_(name).map(convert_crate)),
+            RegistryIndex::Directory(path) => {
+                let file = path.join(crate_file_path(name));
+                if file.is_file() {
+                    Ok(Some(read_crate_file(&file)?))
+                } else {
+                    Ok(None)
+                }
+            }
+            RegistryIndex::Sparse { url, cache, .. } => {
+                let file = cache.join(crate_file_path(name));
+                // Crates missing from the database dump are downloaded when they're needed
+                if file.is_file() || fetch_sparse_file(url, cache, &crate_file_path(name))? {
+                    Ok(Some(read_crate_file(&file)?))
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    /// URL the `.crate` file of a version can be downloaded from, following the `dl` template
+    /// in the index's `config.json`.
+    pub(crate) fn download_url(&self, name: &str, version: &str) -> Fallible<String> {
+        Ok(format_download_url(&self.dl_template()?, name, version))
+    }
+
+    /// The `dl` template of the index config, which [`format_download_url`] expands into the
+    /// download URL of each crate.
+    pub(crate) fn dl_template(&self) -> Fallible<String> {
+        Ok(match self {
+            // Git indexes are bare clones, so the config isn't checked out
+            RegistryIndex::Git { index, .. } => index.index_config().to_failure()?.dl,
+            RegistryIndex::Directory(path) => read_config(path)?.dl,
+            RegistryIndex::Sparse { url, cache, .. } => {
+                if !cache.join("config.json").is_file() {
+                    fetch_sparse_file(url, cache, Path::new("config.json"))?;
+                }
+                read_config(cache)?.dl
+            }
+        })
+    }
+}
+
+pub(crate) fn format_download_url(dl: &str, name: &str, version: &str) -> String {
+    const MARKERS: &[&str] = &["{crate}", "{version}", "{prefix}", "{lowerprefix}"];
+    if !MARKERS.iter().any(|marker| dl.contains(marker)) {
+        return format!("{}/{}/{}/download", dl.trim_end_matches('/'), name, version);
+    }
+
+    let prefix = crate_prefix(name);
+    dl.replace("{crate}", name)
+        .replace("{version}", version)
+        .replace("{prefix}", &prefix)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+}
+
+fn read_config(path: &Path) -> Fallible<RawConfig> {
+    let config_path = path.join("config.json");
+    Ok(serde_json::from_slice(&fs::read(&config_path)?)
+        .with_context(|_| format!("invalid index config {}", config_path.display()))?)
+}
+
+/// Files of the crates in an extracted index, skipping `config.json`, `.git` and other hidden
+/// files.
+fn crate_files(path: &Path) -> impl Iterator<Item = Fallible<PathBuf>> {
+    WalkDir::new(path)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| match entry {
+            Ok(entry) if entry.file_type().is_file() && entry.file_name() != "config.json" => {
+                Some(Ok(entry.into_path()))
+            }
+            Ok(_) => None,
+            Err(err) => Some(Err(err.into())),
+        })
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "request to {} returned status code {}", _0, _1)]
+struct TransientStatus(String, StatusCode);
+
+/// Download a file of a sparse index to the same path inside `cache`, returning whether it
+/// exists. The cached copy of files removed from the index is deleted. Requests failing because
+/// of network or server errors are retried.
+fn fetch_sparse_file(url: &str, cache: &Path, file: &Path) -> Fallible<bool> {
+    let url = format!("{}/{}", url, file.to_string_lossy().replace('\\', "/"));
+    let dest = cache.join(file);
+    let mut attempt = 1;
+    loop {
+        match fetch_sparse_file_once(&url, cache, &dest) {
+            Err(err)
+                if attempt < SPARSE_ATTEMPTS
+                    && (err.downcast_ref::<reqwest::Error>().is_some()
+                        || err.downcast_ref::<TransientStatus>().is_some()) =>
+            {
+                warn!("failed to download {} (attempt {}): {}", url, attempt, err);
+                thread::sleep(Duration::from_secs(attempt.into()));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn fetch_sparse_file_once(url: &str, cache: &Path, dest: &Path) -> Fallible<bool> {
+    let mut req = crate::utils::http::prepare_sync(Method::GET, url);
+    // The cached copy was written when it was last downloaded, so only newer versions of the
+    // file have to be downloaded again
+    if let Ok(modified) = fs::metadata(dest).and_then(|metadata| metadata.modified()) {
+        let modified = DateTime::<Utc>::from(modified)
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        req = req.header(IF_MODIFIED_SINCE, modified);
+    }
+
+    let mut resp = req.send()?;
+    match resp.status() {
+        StatusCode::NOT_MODIFIED => Ok(true),
+        StatusCode::OK => {
+            fs::create_dir_all(dest.parent().unwrap())?;
+            let mut tmp = tempfile::NamedTempFile::new_in(cache)?;
+            resp.copy_to(&mut tmp)?;
+            tmp.persist(&dest)?;
+            Ok(true)
+        }
+        StatusCode::NOT_FOUND | StatusCode::GONE => {
+            if dest.exists() {
+                fs::remove_file(&dest)?;
+            }
+            Ok(false)
+        }
+        status if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+            Err(TransientStatus(url.into(), status).into())
+        }
+        status => bail!("request to {} returned status code {}", url, status),
+    }
+}
+
+/// Download the files of every crate named in the database dump that changed since they were
+/// cached, removing the ones left from crates that don't exist anymore. Crates whose file can't
+/// be downloaded keep their cached copy.
+fn update_sparse(url: &str, cache: &Path, crates_csv: &Path) -> Fallible<()> {
+    let mut names = Vec::new();
+    for record in ::csv::Reader::from_path(crates_csv)
+        .with_context(|_| format!("failed to open {}", crates_csv.display()))?
+        .deserialize()
+    {
+        let record: DumpCrateName = record?;
+        names.push(record.name);
+    }
+
+    fs::create_dir_all(cache)?;
+    fetch_sparse_file(url, cache, Path::new("config.json"))?;
+
+    info!(
+        "downloading {} crates from the sparse index {}",
+        names.len(),
+        url
+    );
+    let chunk_size = names.len() / SPARSE_THREADS + 1;
+    let failed = scope(|scope| -> Fallible<usize> {
+        let threads = names
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move |_| {
+                    let mut failed = 0;
+                    for name in chunk {
+                        if let Err(err) = fetch_sparse_file(url, cache, &crate_file_path(name)) {
+                            warn!("failed to download the index file of {}: {}", name, err);
+                            failed += 1;
+                        }
+                    }
+                    failed
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut failed = 0;
+        for thread in threads {
+            failed += thread
+                .join()
+                .map_err(|_| err_msg("a thread downloading the sparse index panicked"))?;
+        }
+        Ok(failed)
+    })
+    .map_err(|_| err_msg("a thread downloading the sparse index panicked"))??;
+    if failed > 0 && failed == names.len() {
+        bail!("failed to download every crate of the sparse index {}", url);
+    } else if failed > 0 {
+        warn!(
+            "{} crates of the sparse index {} kept their cached copy",
+            failed, url
+        );
+    }
+
+    let expected = names
+        .iter()
+        .map(|name| cache.join(crate_file_path(name)))
+        .collect::<HashSet<_>>();
+    for file in crate_files(cache) {
+        let file = file?;
+        if !expected.contains(&file) {
+            fs::remove_file(&file)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn convert_crate(krate: crates_index::Crate) -> IndexCrate {
+    IndexCrate {
+        name: krate.name().to_string(),
+        versions: krate
+            .versions()
+            .iter()
+            .map(|version| IndexVersion {
+                version: version.version().to_string(),
+                yanked: version.is_yanked(),
+                dependencies: version
+                    .dependencies()
+                    .iter()
+                    .map(|dep| dep.package().unwrap_or_else(|| dep.name()).to_string())
+                    .collect(),
+                // The publication times aren't exposed by the git index
+                published_on: None,
+            })
+            .collect(),
+    }
+}
+
+/// Every file in the index contains one JSON object per published version.
+fn read_crate_file(path: &Path) -> Fallible<IndexCrate> {
+    let mut name = None;
+    let mut versions = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let raw: RawVersion = serde_json::from_str(&line)
+            .with_context(|_| format!("invalid index entry in {}", path.display()))?;
+        name = Some(raw.name);
+        versions.push(IndexVersion {
+            version: raw.vers,
+            yanked: raw.yanked,
+            dependencies: raw
+                .deps
+                .into_iter()
+                .map(|dep| dep.package.unwrap_or(dep.name))
+                .collect(),
+            published_on: raw
+                .pubtime
+                .and_then(|time| time.get(..10).map(String::from)),
+        });
+    }
+
+    Ok(IndexCrate {
+        name: name.ok_or_else(|| err_msg(format!("empty index file {}", path.display())))?,
+        versions,
+    })
+}
+
+/// Location of a crate's file inside the index, as defined by Cargo's registry layout.
+fn crate_file_path(name: &str) -> PathBuf {
+    let name = name.to_lowercase();
+    Path::new(&crate_prefix(&name)).join(&name)
+}
+
+/// Directories containing a crate's file inside the index, like `se/rd` for `serde`.
+fn crate_prefix(name: &str) -> String {
+    match name.len() {
+        1 => "1".into(),
+        2 => "2".into(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn write_test_index(path: &Path) -> Fallible<()> {
+    let crates: &[(&str, &[&str])] = &[
+        (
+            "a",
+            &[r#"{"name":"a","vers":"0.1.0","deps":[{"name":"log"}],"yanked":false}"#],
+        ),
+        (
+            "app",
+            &[
+                r#"{"name":"app","vers":"1.0.0","deps":[{"name":"serde1","package":"serde"}],"yanked":false}"#,
+            ],
+        ),
+        (
+            "log",
+            &[
+                r#"{"name":"log","vers":"0.4.0","deps":[],"yanked":false}"#,
+                r#"{"name":"log","vers":"0.4.1","deps":[],"yanked":true}"#,
+            ],
+        ),
+        (
+            "serde",
+            &[
+                r#"{"name":"serde","vers":"0.8.0","deps":[],"yanked":false}"#,
+                r#"{"name":"serde","vers":"0.9.5","deps":[],"yanked":false}"#,
+                r#"{"name":"serde","vers":"0.9.0","deps":[],"yanked":false}"#,
+                r#"{"name":"serde","vers":"0.9.6","deps":[],"yanked":true}"#,
+                r#"{"name":"serde","vers":"1.0.0-rc.1","deps":[],"yanked":false}"#,
+                r#"{"name":"serde","vers":"1.0.0","deps":[{"name":"log"}],"yanked":false,"pubtime":"2020-06-01T10:00:00Z"}"#,
+            ],
+        ),
+        (
+            "yanked",
+            &[r#"{"name":"yanked","vers":"1.0.0","deps":[],"yanked":true}"#],
+        ),
+    ];
+
+    fs::write(
+        path.join("config.json"),
+        r#"{"dl":"https://crates.io/api/v1/crates"}"#,
+    )?;
+    for (name, versions) in crates {
+        let file = path.join(crate_file_path(name));
+        fs::create_dir_all(file.parent().unwrap())?;
+        fs::write(file, versions.join("\n"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crate_file_path, write_test_index, RegistryIndex};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    #[test]
+    fn test_crate_file_path() {
+        assert_eq!(crate_file_path("a"), PathBuf::from("1/a"));
+        assert_eq!(crate_file_path("ab"), PathBuf::from("2/ab"));
+        assert_eq!(crate_file_path("abc"), PathBuf::from("3/a/abc"));
+        assert_eq!(crate_file_path("Serde"), PathBuf::from("se/rd/serde"));
+    }
+
+    #[test]
+    fn test_directory_index() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_index(dir.path()).unwrap();
+        let index = RegistryIndex::Directory(dir.path().into());
+
+        let mut names: Vec<_> = index
+            .crates()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "app", "log", "serde", "yanked"]);
+
+        let log = index.crate_("log").unwrap().unwrap();
+        assert_eq!(log.latest_version().unwrap().version, "0.4.1");
+        assert!(log.latest_version().unwrap().yanked);
+        assert!(index.crate_("missing").unwrap().is_none());
+
+        // Renamed dependencies are recorded with the name of the crate
+        let app = index.crate_("app").unwrap().unwrap();
+        assert_eq!(app.latest_version().unwrap().dependencies, vec!["serde"]);
+    }
+
+    #[test]
+    fn test_download_url() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_index(dir.path()).unwrap();
+        let index = RegistryIndex::Directory(dir.path().into());
+        assert_eq!(
+            index.download_url("serde", "1.0.0").unwrap(),
+            "https://crates.io/api/v1/crates/serde/1.0.0/download"
+        );
+
+        fs::write(
+            dir.path().join("config.json"),
+            r#"{"dl":"https://example.com/{lowerprefix}/{crate}/{crate}-{version}.crate"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            index.download_url("Serde", "1.0.0").unwrap(),
+            "https://example.com/se/rd/Serde/Serde-1.0.0.crate"
+        );
+    }
+
+    #[test]
+    fn test_git_download_url() {
+        let repo = tempfile::tempdir().unwrap();
+        write_test_index(repo.path()).unwrap();
+        for args in &[
+            &["init", "-q"][..],
+            &["add", "."],
+            &[
+                "-c",
+                "user.name=Crater",
+                "-c",
+                "user.email=crater@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "index",
+            ],
+        ] {
+            let status = Command::new("git")
+                .args(*args)
+                .current_dir(repo.path())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+
+        // The clone is bare, so the config can't be read from disk
+        let clone = tempfile::tempdir().unwrap();
+        let url = format!("file://{}", repo.path().display());
+        let index = RegistryIndex::open_git(clone.path().join("index"), &url).unwrap();
+        assert_eq!(
+            index.download_url("serde", "1.0.0").unwrap(),
+            "https://crates.io/api/v1/crates/serde/1.0.0/download"
+        );
+    }
+}
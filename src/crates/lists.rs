@@ -1,15 +1,23 @@
 use crate::config::Config;
 use crate::crates::sources::github::GitHubRepo;
-use crate::crates::{Crate, RegistryCrate};
+use crate::crates::{split_subdir, Crate, GitRepo, RegistryCrate};
 use crate::db::{Database, QueryUtils};
-use crate::experiments::CrateSelect;
+use crate::experiments::{CrateSelect, Experiment};
 use crate::prelude::*;
+use crate::report::{compare, Comparison};
+use crate::results::{DatabaseDB, TestResult};
 use chrono::Utc;
-use rand::{seq::SliceRandom, thread_rng};
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use std::collections::HashSet;
+use std::mem::discriminant;
 
+use crate::crates::sources::registry;
 pub(crate) use crate::crates::sources::{
-    github::GitHubList, local::LocalList, registry::RegistryList,
+    alt_registry::AltRegistryList,
+    github::{GitHubList, GitHubUnlockedList},
+    local::LocalList,
+    registry::RegistryList,
 };
 
 pub(crate) trait List {
@@ -17,17 +25,26 @@ pub(crate) trait List {
 
     fn fetch(&self) -> Fallible<Vec<Crate>>;
 
+    /// Fetch the list along with the popularity score of every crate, for lists having one.
+    fn fetch_scored(&self) -> Fallible<Vec<(Crate, Option<i64>)>> {
+        Ok(self
+            .fetch()?
+            .into_iter()
+            .map(|krate| (krate, None))
+            .collect())
+    }
+
     fn update(&self, db: &Database) -> Fallible<()> {
-        let crates = self.fetch()?;
+        let crates = self.fetch_scored()?;
 
         let now = Utc::now();
         db.transaction(|t| {
             // Replace the existing list in the database
             t.execute("DELETE FROM crates WHERE list = ?1;", &[&Self::NAME])?;
-            for krate in &crates {
+            for (krate, score) in &crates {
                 t.execute(
-                    "INSERT INTO crates (crate, list, loaded_at) VALUES (?1, ?2, ?3);",
-                    &[&krate.id(), &Self::NAME, &now],
+                    "INSERT INTO crates (crate, list, loaded_at, score) VALUES (?1, ?2, ?3, ?4);",
+                    &[&krate.id(), &Self::NAME, &now, score],
                 )
                 .with_context(|_| {
                     format!(
@@ -47,7 +64,7 @@ pub(crate) trait List {
 
     fn get(db: &Database) -> Fallible<Vec<Crate>> {
         let crates_results = db.query(
-            "SELECT crate FROM crates WHERE list = ?1 ORDER BY rowid;",
+            "SELECT crate FROM crates WHERE list = ?1 ORDER BY score DESC, rowid;",
             &[&Self::NAME],
             |r| r.get::<_, String>(0),
         )?;
@@ -68,6 +85,7 @@ pub(crate) fn get_crates(
         CrateSelect::Full => {
             crates.append(&mut RegistryList::get(db)?);
             crates.append(&mut GitHubList::get(db)?);
+            crates.append(&mut AltRegistryList::get(db)?);
         }
 
         CrateSelect::Demo => {
@@ -93,7 +111,8 @@ pub(crate) fn get_crates(
                     Crate::Registry(RegistryCrate { ref name, .. }) => demo_registry.remove(name),
                     Crate::GitHub(ref repo) => demo_github.remove(&repo.slug()),
                     Crate::Local(ref name) => demo_local.remove(name),
-                    Crate::Git(_) | Crate::Path(_) => unimplemented!("unsupported crate"),
+                    // Git repositories and paths are not part of any list
+                    Crate::Git(_) | Crate::Path(_) | Crate::AltRegistry(_) => false,
                 };
 
                 if add {
@@ -101,6 +120,17 @@ pub(crate) fn get_crates(
                 }
             }
 
+            for url in &config.demo_crates().git_repos {
+                crates.push(Crate::Git(GitRepo {
+                    url: url.clone(),
+                    sha: None,
+                    subdir: None,
+                }));
+            }
+            for path in &config.demo_crates().path_crates {
+                crates.push(Crate::Path(path.clone()));
+            }
+
             // Do some sanity checks
             if !demo_registry.is_empty() {
                 bail!("missing demo crates: {:?}", demo_registry);
@@ -113,17 +143,40 @@ pub(crate) fn get_crates(
             }
         }
         CrateSelect::List(list) => {
-            let mut desired = list.clone();
+            let mut desired = HashSet::new();
+            let mut versions = Vec::new();
+            for entry in list {
+                // Git repositories and paths are not part of any list, so they're added directly
+                match parse_unlisted_crate(entry)? {
+                    Some(krate) => crates.push(krate),
+                    None => match entry.split_once('@') {
+                        // Explicit versions of registry crates are only checked against the index
+                        Some((name, version)) => versions.push(RegistryCrate {
+                            name: name.to_string(),
+                            version: version.to_string(),
+                        }),
+                        None => {
+                            desired.insert(entry.clone());
+                        }
+                    },
+                }
+            }
+            if !versions.is_empty() {
+                crates.append(&mut RegistryList::new(&config.registry).exact_versions(&versions)?);
+            }
 
             let mut all_crates = Vec::new();
             all_crates.append(&mut RegistryList::get(db)?);
             all_crates.append(&mut GitHubList::get(db)?);
+            all_crates.append(&mut GitHubUnlockedList::get(db)?);
 
             for krate in all_crates {
                 let is_desired = match krate {
                     Crate::Registry(RegistryCrate { ref name, .. }) => desired.remove(name),
                     Crate::GitHub(ref repo) => desired.remove(&repo.slug()),
-                    _ => unreachable!(),
+                    Crate::Local(_) | Crate::Git(_) | Crate::Path(_) | Crate::AltRegistry(_) => {
+                        false
+                    }
                 };
 
                 if is_desired {
@@ -136,13 +189,28 @@ pub(crate) fn get_crates(
             }
         }
 
-        CrateSelect::Random(n) => {
+        CrateSelect::Random { count, seed } => {
+            // Alternate registries are only tested explicitly, with the full selection
             crates.append(&mut RegistryList::get(db)?);
             crates.append(&mut GitHubList::get(db)?);
 
-            let mut rng = thread_rng();
+            let mut rng = seeded_rng(*seed);
             crates.shuffle(&mut rng);
-            crates.truncate(*n as usize);
+            crates.truncate(*count as usize);
+        }
+        CrateSelect::Stratified { per_decile, seed } => {
+            // The registry list is sorted by popularity
+            let registry = RegistryList::get(db)?;
+            let decile_len = (registry.len() + 9) / 10;
+
+            let mut rng = seeded_rng(*seed);
+            for decile in registry.chunks(decile_len.max(1)) {
+                crates.extend(
+                    decile
+                        .choose_multiple(&mut rng, *per_decile as usize)
+                        .cloned(),
+                );
+            }
         }
         CrateSelect::Top(n) => {
             crates.append(&mut RegistryList::get(db)?);
@@ -151,13 +219,161 @@ pub(crate) fn get_crates(
         CrateSelect::Local => {
             crates.append(&mut LocalList::get(db)?);
         }
+        CrateSelect::GitHubUnlocked => {
+            crates.append(&mut GitHubUnlockedList::get(db)?);
+        }
+        CrateSelect::FromExperiment { name, filters } => {
+            crates.append(&mut crates_from_experiment(db, config, name, filters)?);
+        }
+        CrateSelect::ReverseDeps { krate, depth } => {
+            crates.append(&mut RegistryList::reverse_dependencies(db, krate, *depth)?);
+        }
+        CrateSelect::SemverHeads(inner) => {
+            let mut names = HashSet::new();
+            for krate in get_crates(inner, db, config)? {
+                match krate {
+                    Crate::Registry(RegistryCrate { name, .. }) => {
+                        names.insert(name);
+                    }
+                    // Only registry crates have multiple versions
+                    other => crates.push(other),
+                }
+            }
+            crates.append(&mut RegistryList::new(&config.registry).semver_heads(&names)?);
+        }
+        CrateSelect::Category(category) => {
+            let names = registry::crates_with_tag(db, "category", category)?;
+            crates.append(&mut filter_registry(db, |name, _| names.contains(name))?);
+        }
+        CrateSelect::Keyword(keyword) => {
+            let names = registry::crates_with_tag(db, "keyword", keyword)?;
+            crates.append(&mut filter_registry(db, |name, _| names.contains(name))?);
+        }
+        CrateSelect::PublishedSince(date) => {
+            let versions = registry::versions_published_since(db, *date)?;
+            crates.append(&mut filter_registry(db, |name, version| {
+                versions.contains(&(name.to_string(), version.to_string()))
+            })?);
+        }
         CrateSelect::Dummy => crates.push(Crate::GitHub(GitHubRepo::dummy())),
+        CrateSelect::Union(left, right) => {
+            crates.append(&mut get_crates(left, db, config)?);
+            crates.append(&mut get_crates(right, db, config)?);
+        }
+        CrateSelect::Intersection(left, right) => {
+            let right = get_crates(right, db, config)?
+                .into_iter()
+                .collect::<HashSet<_>>();
+            crates.extend(
+                get_crates(left, db, config)?
+                    .into_iter()
+                    .filter(|krate| right.contains(krate)),
+            );
+        }
+        CrateSelect::Difference(left, right) => {
+            let right = get_crates(right, db, config)?
+                .into_iter()
+                .collect::<HashSet<_>>();
+            crates.extend(
+                get_crates(left, db, config)?
+                    .into_iter()
+                    .filter(|krate| !right.contains(krate)),
+            );
+        }
     }
 
     crates.sort();
+    // The same crate can be selected multiple times by a union
+    crates.dedup();
+    Ok(crates)
+}
+
+/// Crates of the registry list whose name and version match the predicate.
+fn filter_registry(db: &Database, predicate: impl Fn(&str, &str) -> bool) -> Fallible<Vec<Crate>> {
+    Ok(RegistryList::get(db)?
+        .into_iter()
+        .filter(|krate| match krate {
+            Crate::Registry(RegistryCrate { name, version }) => predicate(name, version),
+            _ => false,
+        })
+        .collect())
+}
+
+/// Random number generator for random selections, which are reproducible when given a seed.
+/// Unlike `StdRng`, the algorithm of `ChaCha8Rng` is guaranteed not to change across releases,
+/// so the same seed keeps selecting the same crates.
+fn seeded_rng(seed: Option<u64>) -> ChaCha8Rng {
+    match seed {
+        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+        None => ChaCha8Rng::from_entropy(),
+    }
+}
+
+/// Crates of the `name` experiment matching at least one of the filters, which are either the
+/// name of a comparison or the kind of the result on the experiment's last toolchain.
+fn crates_from_experiment(
+    db: &Database,
+    config: &Config,
+    name: &str,
+    filters: &[String],
+) -> Fallible<Vec<Crate>> {
+    let ex = Experiment::get(db, name)?
+        .ok_or_else(|| err_msg(format!("missing experiment: {}", name)))?;
+    let mut results = DatabaseDB::new(db).load_all_results(&ex)?;
+
+    let mut crates = Vec::new();
+    for krate in ex.get_crates(db)? {
+        let toolchain_results = results.remove(&krate.id()).unwrap_or([None, None]);
+        let last_result = toolchain_results[1].as_ref();
+        let comparison = compare(config, &krate, toolchain_results[0].as_ref(), last_result);
+
+        let matches = filters.is_empty()
+            || filters.iter().any(|filter| {
+                if let Ok(expected) = filter.parse::<TestResult>() {
+                    last_result.map_or(false, |res| discriminant(res) == discriminant(&expected))
+                } else {
+                    filter.parse::<Comparison>().ok() == Some(comparison)
+                }
+            });
+        if !matches {
+            continue;
+        }
+
+        // Let GitHub repositories be tested at their latest commit, like in the other selections
+        crates.push(match krate {
+            Crate::GitHub(repo) => Crate::GitHub(GitHubRepo { sha: None, ..repo }),
+            other => other,
+        });
+    }
+
     Ok(crates)
 }
 
+/// Parse the entries of `list:` referring to crates outside of the lists, either
+/// `git+{url}[#{sha}][//{subdir}]`, `path+{path}` or `{org}/{repo}//{subdir}`.
+fn parse_unlisted_crate(entry: &str) -> Fallible<Option<Crate>> {
+    if let Some(repo) = entry.strip_prefix("git+") {
+        let (repo, subdir) = split_subdir(repo)?;
+        let mut parts = repo.splitn(2, '#');
+        Ok(Some(Crate::Git(GitRepo {
+            url: parts.next().unwrap().to_string(),
+            sha: parts.next().map(|sha| sha.to_string()),
+            subdir,
+        })))
+    } else if let Some(path) = entry.strip_prefix("path+") {
+        Ok(Some(Crate::Path(path.to_string())))
+    } else if let (slug, Some(subdir)) = split_subdir(entry)? {
+        // The GitHub list only contains repositories with the crate at their root
+        let repo: GitHubRepo = slug.parse()?;
+        Ok(Some(Crate::GitHub(GitHubRepo {
+            subdir: Some(subdir),
+            ..repo
+        })))
+    } else {
+        Ok(None)
+    }
+}
+
 #[cfg(test)]
 pub(crate) fn setup_test_lists(db: &Database, config: &Config) -> Fallible<()> {
     use crate::actions::{Action, ActionsCtx, UpdateLists};
@@ -165,7 +381,182 @@ pub(crate) fn setup_test_lists(db: &Database, config: &Config) -> Fallible<()> {
     UpdateLists {
         github: false,
         registry: false,
+        alt_registries: false,
         local: true,
     }
     .apply(&ActionsCtx::new(db, config))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{get_crates, setup_test_lists, List, RegistryList};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, GitHubRepo, GitRepo, RegistryCrate};
+    use crate::db::{Database, QueryUtils};
+    use crate::experiments::{CrateSelect, Experiment};
+    use crate::results::{DatabaseDB, EncodingType, FailureReason, TestResult, WriteResults};
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use chrono::Utc;
+
+    #[test]
+    fn test_list_with_unlisted_crates() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+
+        let select = CrateSelect::List(
+            vec![
+                "git+https://gitlab.com/example/repo#f00",
+                "git+https://git.example.com/internal",
+                "path+/opt/crates/example",
+                "git+https://gitlab.com/example/mono#f00//crates/foo",
+                "rust-lang/rust//src/tools/foo",
+            ]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect(),
+        );
+
+        assert_eq!(
+            get_crates(&select, &db, &config).unwrap(),
+            vec![
+                Crate::GitHub(GitHubRepo {
+                    org: "rust-lang".into(),
+                    name: "rust".into(),
+                    sha: None,
+                    subdir: Some("src/tools/foo".into()),
+                }),
+                Crate::Path("/opt/crates/example".into()),
+                Crate::Git(GitRepo {
+                    url: "https://git.example.com/internal".into(),
+                    sha: None,
+                    subdir: None,
+                }),
+                Crate::Git(GitRepo {
+                    url: "https://gitlab.com/example/mono".into(),
+                    sha: Some("f00".into()),
+                    subdir: Some("crates/foo".into()),
+                }),
+                Crate::Git(GitRepo {
+                    url: "https://gitlab.com/example/repo".into(),
+                    sha: Some("f00".into()),
+                    subdir: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        setup_test_lists(&db, &config).unwrap();
+
+        let select = |s: &str| get_crates(&s.parse().unwrap(), &db, &config).unwrap();
+        let local = select("local");
+        let path = |p: &str| Crate::Path(p.into());
+
+        let mut expected = local.clone();
+        expected.push(path("/a"));
+        expected.sort();
+        assert_eq!(select("local + list:path+/a + list:path+/a"), expected);
+
+        assert!(select("local - local").is_empty());
+        assert_eq!(
+            select("local + list:path+/a,path+/b & list:path+/b,path+/c"),
+            vec![path("/b")]
+        );
+        assert_eq!(
+            select("list:path+/a,path+/b - list:path+/b"),
+            vec![path("/a")]
+        );
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+
+        // The registry list is sorted by popularity, from crate0 to crate99
+        let registry = (0..100)
+            .map(|i| {
+                Crate::Registry(RegistryCrate {
+                    name: format!("crate{}", i),
+                    version: "1.0.0".into(),
+                })
+            })
+            .collect::<Vec<_>>();
+        for krate in &registry {
+            db.execute(
+                "INSERT INTO crates (crate, list, loaded_at) VALUES (?1, ?2, ?3);",
+                &[&krate.id(), &RegistryList::NAME, &Utc::now()],
+            )
+            .unwrap();
+        }
+
+        let select = |s: &str| get_crates(&s.parse().unwrap(), &db, &config).unwrap();
+        let random = select("random-10:seed=42");
+        assert_eq!(random.len(), 10);
+        assert_eq!(random, select("random-10:seed=42"));
+        assert_ne!(random, select("random-10:seed=43"));
+
+        let stratified = select("stratified-2:seed=42");
+        assert_eq!(stratified, select("stratified-2:seed=42"));
+        for decile in registry.chunks(10) {
+            let picked = stratified.iter().filter(|c| decile.contains(c)).count();
+            assert_eq!(picked, 2);
+        }
+    }
+
+    #[test]
+    fn test_crates_from_experiment() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let results = DatabaseDB::new(&db);
+        let local = |name: &str| Crate::Local(name.into());
+        let fail = || TestResult::BuildFail(FailureReason::Unknown);
+        for (krate, start, end) in &[
+            ("build-pass", TestResult::TestPass, TestResult::TestPass),
+            ("beta-regression", TestResult::TestPass, fail()),
+            ("build-fail", fail(), fail()),
+        ] {
+            for (tc, res) in &[(&*MAIN_TOOLCHAIN, start), (&*TEST_TOOLCHAIN, end)] {
+                results
+                    .record_result(
+                        &ex,
+                        tc,
+                        &local(krate),
+                        None,
+                        &config,
+                        EncodingType::Plain,
+                        || Ok((*res).clone()),
+                    )
+                    .unwrap();
+            }
+        }
+
+        let select = |s: &str| get_crates(&s.parse().unwrap(), &db, &config).unwrap();
+        assert_eq!(
+            select("from:dummy:regressed"),
+            vec![local("beta-regression")]
+        );
+        assert_eq!(
+            select("from:dummy:build-fail"),
+            vec![local("beta-regression"), local("build-fail")]
+        );
+        assert_eq!(
+            select("from:dummy:regressed,test-pass"),
+            vec![local("beta-regression"), local("build-pass")]
+        );
+        assert_eq!(
+            select("from:dummy").len(),
+            ex.get_crates(&db).unwrap().len()
+        );
+        assert!(get_crates(&"from:missing".parse().unwrap(), &db, &config).is_err());
+    }
+}
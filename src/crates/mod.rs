@@ -1,23 +1,30 @@
+pub(crate) mod index;
 pub(crate) mod lists;
 mod sources;
 
-use crate::dirs::LOCAL_CRATES_DIR;
+use crate::config::Config;
+use crate::dirs::{LOCAL_CRATES_DIR, WORK_DIR};
+use crate::experiments::{Experiment, Lockfile, Mode};
 use crate::prelude::*;
 use cargo_metadata::PackageId;
 use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
 use rustwide::Crate as RustwideCrate;
 use std::convert::TryFrom;
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+pub(crate) use crate::crates::sources::alt_registry::{update_alt_registries, AltRegistryCrate};
 pub(crate) use crate::crates::sources::github::GitHubRepo;
-pub(crate) use crate::crates::sources::registry::RegistryCrate;
+pub(crate) use crate::crates::sources::registry::{compatible_line, RegistryCrate};
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
 pub struct GitRepo {
     pub url: String,
     pub sha: Option<String>,
+    /// Directory of the repository containing the crate, if it's not at the root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<String>,
 }
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
@@ -27,10 +34,73 @@ pub enum Crate {
     Local(String),
     Path(String),
     Git(GitRepo),
+    AltRegistry(AltRegistryCrate),
+}
+
+fn find_manifest(checkout: &Path, subdir: Option<&str>) -> (PathBuf, PathBuf) {
+    let subdir = match subdir {
+        Some(subdir) => Path::new(subdir),
+        None => return (checkout.to_path_buf(), PathBuf::from("Cargo.toml")),
+    };
+
+    let mut ancestors: Vec<_> = subdir.ancestors().collect();
+    ancestors.reverse();
+    for ancestor in ancestors {
+        if is_workspace_root(&checkout.join(ancestor).join("Cargo.toml")) {
+            // The ancestors are prefixes of the subdirectory
+            let manifest = subdir.strip_prefix(ancestor).unwrap().join("Cargo.toml");
+            return (checkout.join(ancestor), manifest);
+        }
+    }
+    (checkout.join(subdir), PathBuf::from("Cargo.toml"))
+}
+
+/// Whether the manifest exists and declares a workspace.
+fn is_workspace_root(manifest: &Path) -> bool {
+    std::fs::read_to_string(manifest)
+        .ok()
+        .and_then(|contents| ::toml::from_str::<::toml::Value>(&contents).ok())
+        .map(|manifest| manifest.get("workspace").is_some())
+        .unwrap_or(false)
+}
+
+/// Split the `//{subdir}` suffix of a crate, ignoring the `//` of the URL scheme if any. The
+/// subdirectory must be relative and can't leave the repository.
+pub(crate) fn split_subdir(input: &str) -> Fallible<(&str, Option<String>)> {
+    let start = input.find("://").map(|idx| idx + "://".len()).unwrap_or(0);
+    let idx = match input[start..].find("//") {
+        Some(idx) => start + idx,
+        None => return Ok((input, None)),
+    };
+
+    let components = input[idx + 2..]
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect::<Vec<_>>();
+    if components.is_empty() || components.contains(&"..") {
+        bail!("invalid subdirectory in {}", input);
+    }
+
+    Ok((&input[..idx], Some(components.join("/"))))
+}
+
+fn write_subdir(f: &mut fmt::Formatter, subdir: &Option<String>) -> fmt::Result {
+    if let Some(subdir) = subdir {
+        write!(f, "//{}", subdir)?;
+    }
+    Ok(())
 }
 
 impl Crate {
     pub(crate) fn id(&self) -> String {
+        let id = self.id_without_subdir();
+        match self.subdir() {
+            Some(subdir) => format!("{}//{}", id, subdir),
+            None => id,
+        }
+    }
+
+    fn id_without_subdir(&self) -> String {
         match *self {
             Crate::Registry(ref details) => format!("reg/{}/{}", details.name, details.version),
             Crate::GitHub(ref repo) => {
@@ -55,10 +125,59 @@ impl Crate {
                     format!("git/{}", utf8_percent_encode(&repo.url, NON_ALPHANUMERIC),)
                 }
             }
+            Crate::AltRegistry(ref details) => format!(
+                "alt/{}/{}/{}",
+                details.registry, details.name, details.version
+            ),
         }
     }
 
-    pub(crate) fn to_rustwide(&self) -> RustwideCrate {
+    /// Directory of the git repository containing the crate, if it's not at the root.
+    pub(crate) fn subdir(&self) -> Option<&str> {
+        match self {
+            Crate::GitHub(GitHubRepo { subdir, .. }) | Crate::Git(GitRepo { subdir, .. }) => {
+                subdir.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the crate has to be checked out before being built, either because rustwide
+    /// expects `Cargo.toml` at the root of the crates it builds or because the source fetched by
+    /// rustwide needs to be changed. Resolve experiments also run cargo on the checkout.
+    pub(crate) fn needs_checkout(&self, ex: &Experiment) -> bool {
+        self.subdir().is_some() || ex.lockfile != Lockfile::Keep || ex.mode == Mode::Resolve
+    }
+
+    /// Directory the source of the crate is checked out to while the crate is being tested.
+    pub(crate) fn checkout_dir(&self) -> PathBuf {
+        WORK_DIR
+            .join("checkouts")
+            .join(utf8_percent_encode(&self.id(), NON_ALPHANUMERIC).to_string())
+    }
+
+    /// Directory of the checkout the crate is built from, along with the path of the crate's
+    /// `Cargo.toml` relative to it. Crates in a subdirectory are built from the outermost workspace
+    /// containing them, as workspace members can't be built without their workspace root.
+    pub(crate) fn checkout_manifest(&self) -> (PathBuf, PathBuf) {
+        find_manifest(&self.checkout_dir(), self.subdir())
+    }
+
+    pub(crate) fn to_rustwide(&self, config: &Config, ex: &Experiment) -> RustwideCrate {
+        if self.needs_checkout(ex) {
+            // The crate is checked out when it's prepared
+            return RustwideCrate::local(&self.checkout_manifest().0);
+        }
+        self.to_rustwide_repo(config)
+    }
+
+    /// The crate rustwide has to fetch, which for crates in a subdirectory is their whole
+    /// repository.
+    pub(crate) fn to_rustwide_repo(&self, config: &Config) -> RustwideCrate {
+        if let Some(path) = config.vendored_source(self) {
+            return RustwideCrate::local(&path);
+        }
+
         match self {
             Self::Registry(krate) => RustwideCrate::crates_io(&krate.name, &krate.version),
             Self::GitHub(repo) => {
@@ -67,6 +186,7 @@ impl Crate {
             Self::Local(name) => RustwideCrate::local(&LOCAL_CRATES_DIR.join(name)),
             Self::Path(path) => RustwideCrate::local(Path::new(&path)),
             Self::Git(repo) => RustwideCrate::git(&repo.url),
+            Self::AltRegistry(krate) => RustwideCrate::local(&krate.source_dir()),
         }
     }
 }
@@ -104,12 +224,14 @@ impl TryFrom<&'_ PackageId> for Crate {
                         (Some(url), None) => Ok(Crate::Git(GitRepo {
                             url: url.to_string(),
                             sha: None,
+                            subdir: None,
                         })),
                         (Some(url), Some(sha)) => Ok(Crate::Git(GitRepo {
                             // remove additional queries if the sha is present
                             // as the crate version is already uniquely determined
                             url: url.split('?').next().unwrap().to_string(),
                             sha: Some(sha.to_string()),
+                            subdir: None,
                         })),
                         _ => bail!("malformed git repo: {}", repo),
                     }
@@ -148,8 +270,14 @@ impl fmt::Display for Crate {
                     } else {
                         utf8_percent_encode(&repo.url, NON_ALPHANUMERIC).to_string()
                     },
+                Crate::AltRegistry(ref krate) => krate.to_string(),
             }
-        )
+        )?;
+        match *self {
+            Crate::GitHub(GitHubRepo { ref subdir, .. })
+            | Crate::Git(GitRepo { ref subdir, .. }) => write_subdir(f, subdir),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -158,7 +286,8 @@ impl FromStr for Crate {
 
     // matches with `Crate::id'
     fn from_str(s: &str) -> Fallible<Self> {
-        match s.split('/').collect::<Vec<_>>()[..] {
+        let (s, subdir) = split_subdir(s)?;
+        let krate = match s.split('/').collect::<Vec<_>>()[..] {
             ["reg", name, version] => Ok(Crate::Registry(RegistryCrate {
                 name: name.to_string(),
                 version: version.to_string(),
@@ -167,37 +296,90 @@ impl FromStr for Crate {
                 org: org.to_string(),
                 name: name.to_string(),
                 sha: Some(sha.to_string()),
+                subdir: None,
             })),
             ["gh", org, name] => Ok(Crate::GitHub(GitHubRepo {
                 org: org.to_string(),
                 name: name.to_string(),
                 sha: None,
+                subdir: None,
             })),
             ["git", repo, sha] => Ok(Crate::Git(GitRepo {
                 url: percent_decode_str(repo).decode_utf8()?.to_string(),
                 sha: Some(sha.to_string()),
+                subdir: None,
             })),
             ["git", repo] => Ok(Crate::Git(GitRepo {
                 url: percent_decode_str(repo).decode_utf8()?.to_string(),
                 sha: None,
+                subdir: None,
+            })),
+            ["alt", registry, name, version] => Ok(Crate::AltRegistry(AltRegistryCrate {
+                registry: registry.to_string(),
+                name: name.to_string(),
+                version: version.to_string(),
             })),
             ["local", name] => Ok(Crate::Local(name.to_string())),
             ["path", path] => Ok(Crate::Path(
                 percent_decode_str(path).decode_utf8()?.to_string(),
             )),
             _ => bail!("unexpected crate value"),
+        }?;
+
+        match (krate, subdir) {
+            (krate, None) => Ok(krate),
+            (Crate::GitHub(repo), subdir) => Ok(Crate::GitHub(GitHubRepo { subdir, ..repo })),
+            (Crate::Git(repo), subdir) => Ok(Crate::Git(GitRepo { subdir, ..repo })),
+            _ => bail!("only git repositories can have a subdirectory"),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Crate, GitHubRepo, GitRepo, RegistryCrate};
+    use super::{
+        find_manifest, split_subdir, AltRegistryCrate, Crate, GitHubRepo, GitRepo, RegistryCrate,
+    };
     use cargo_metadata::PackageId;
     use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
     use std::convert::TryFrom;
+    use std::fs;
+    use std::path::PathBuf;
     use std::str::FromStr;
 
+    #[test]
+    fn test_find_manifest() {
+        let checkout = tempfile::tempdir().unwrap();
+        let root = checkout.path();
+        let write = |path: &str, contents: &str| {
+            fs::create_dir_all(root.join(path)).unwrap();
+            fs::write(root.join(path).join("Cargo.toml"), contents).unwrap();
+        };
+        write("", "[package]\nname = \"root\"\n");
+        write("nested", "[workspace]\nmembers = [\"crates/*\"]\n");
+        write("nested/crates/foo", "[package]\nname = \"foo\"\n");
+        write("standalone", "[package]\nname = \"standalone\"\n");
+
+        assert_eq!(
+            find_manifest(root, None),
+            (root.to_path_buf(), PathBuf::from("Cargo.toml"))
+        );
+        // Workspace members are built from their workspace root
+        assert_eq!(
+            find_manifest(root, Some("nested/crates/foo")),
+            (root.join("nested"), PathBuf::from("crates/foo/Cargo.toml"))
+        );
+        assert_eq!(
+            find_manifest(root, Some("nested")),
+            (root.join("nested"), PathBuf::from("Cargo.toml"))
+        );
+        // Crates outside of any workspace are built on their own
+        assert_eq!(
+            find_manifest(root, Some("standalone")),
+            (root.join("standalone"), PathBuf::from("Cargo.toml"))
+        );
+    }
+
     macro_rules! test_from_pkgid {
         ($($str:expr => $rust:expr,)*) => {
             $(
@@ -221,37 +403,44 @@ mod tests {
             "dummy 0.1.0 (git+https://github.com/dummy_org/dummy#9823f01cf4948a41279f6a3febcf793130cab4f6)" => Crate::GitHub(GitHubRepo {
                 org: "dummy_org".to_string(),
                 name: "dummy".to_string(),
-                sha: Some("9823f01cf4948a41279f6a3febcf793130cab4f6".to_string())
+                sha: Some("9823f01cf4948a41279f6a3febcf793130cab4f6".to_string()),
+                subdir: None,
             }),
             "dummy 0.1.0 (git+https://github.com/dummy_org/dummy?rev=dummyrev#9823f01cf4948a41279f6a3febcf793130cab4f6)" => Crate::GitHub(GitHubRepo {
                 org: "dummy_org".to_string(),
                 name: "dummy".to_string(),
-                sha: Some("9823f01cf4948a41279f6a3febcf793130cab4f6".to_string())
+                sha: Some("9823f01cf4948a41279f6a3febcf793130cab4f6".to_string()),
+                subdir: None,
             }),
             "dummy 0.1.0 (git+https://github.com/dummy_org/dummy)" => Crate::GitHub(GitHubRepo {
                 org: "dummy_org".to_string(),
                 name: "dummy".to_string(),
-                sha: None
+                sha: None,
+                subdir: None,
             }),
             "dummy 0.1.0 (git+https://gitlab.com/dummy_org/dummy#9823f01cf4948a41279f6a3febcf793130cab4f6)" => Crate::Git(GitRepo {
                 url: "https://gitlab.com/dummy_org/dummy"
                     .to_string(),
-                sha: Some("9823f01cf4948a41279f6a3febcf793130cab4f6".to_string())
+                sha: Some("9823f01cf4948a41279f6a3febcf793130cab4f6".to_string()),
+                subdir: None,
             }),
             "dummy 0.1.0 (git+https://gitlab.com/dummy_org/dummy?branch=dummybranch#9823f01cf4948a41279f6a3febcf793130cab4f6)" => Crate::Git(GitRepo {
                 url: "https://gitlab.com/dummy_org/dummy"
                     .to_string(),
-                sha: Some("9823f01cf4948a41279f6a3febcf793130cab4f6".to_string())
+                sha: Some("9823f01cf4948a41279f6a3febcf793130cab4f6".to_string()),
+                subdir: None,
             }),
             "dummy 0.1.0 (git+https://gitlab.com/dummy_org/dummy)" => Crate::Git(GitRepo {
                 url: "https://gitlab.com/dummy_org/dummy"
                     .to_string(),
-                sha: None
+                sha: None,
+                subdir: None,
             }),
             "dummy 0.1.0 (git+https://gitlab.com/dummy_org/dummy?branch=dummybranch)" => Crate::Git(GitRepo {
                 url: "https://gitlab.com/dummy_org/dummy?branch=dummybranch"
                     .to_string(),
-                sha: None
+                sha: None,
+                subdir: None,
             }),
         }
 
@@ -282,12 +471,40 @@ mod tests {
             "local/build-fail" => Crate::Local("build-fail".to_string()),
             "path/pathtofile" => Crate::Path("pathtofile".to_string()),
             &format!("path/{}", utf8_percent_encode("path/with:stange?characters", NON_ALPHANUMERIC)) => Crate::Path("path/with:stange?characters".to_string()),
-            "gh/org/user" => Crate::GitHub(GitHubRepo{org: "org".to_string(), name: "user".to_string(), sha: None}),
-            "gh/org/user/sha" => Crate::GitHub(GitHubRepo{org: "org".to_string(), name: "user".to_string(), sha: Some("sha".to_string())}),
-            "git/url" => Crate::Git(GitRepo{url: "url".to_string(), sha: None}),
-            &format!("git/{}", utf8_percent_encode("url/with:stange?characters", NON_ALPHANUMERIC)) => Crate::Git(GitRepo{url: "url/with:stange?characters".to_string(), sha: None}),
-            "git/url/sha" => Crate::Git(GitRepo{url: "url".to_string(), sha: Some("sha".to_string())}),
+            "gh/org/user" => Crate::GitHub(GitHubRepo{org: "org".to_string(), name: "user".to_string(), sha: None, subdir: None}),
+            "gh/org/user/sha" => Crate::GitHub(GitHubRepo{org: "org".to_string(), name: "user".to_string(), sha: Some("sha".to_string()), subdir: None}),
+            "git/url" => Crate::Git(GitRepo{url: "url".to_string(), sha: None, subdir: None}),
+            &format!("git/{}", utf8_percent_encode("url/with:stange?characters", NON_ALPHANUMERIC)) => Crate::Git(GitRepo{url: "url/with:stange?characters".to_string(), sha: None, subdir: None}),
+            "git/url/sha" => Crate::Git(GitRepo{url: "url".to_string(), sha: Some("sha".to_string()), subdir: None}),
             "reg/name/version" => Crate::Registry(RegistryCrate{name: "name".to_string(), version: "version".to_string()}),
+            "alt/registry/name/version" => Crate::AltRegistry(AltRegistryCrate{registry: "registry".to_string(), name: "name".to_string(), version: "version".to_string()}),
+            "gh/org/user/sha//rust/core" => Crate::GitHub(GitHubRepo{org: "org".to_string(), name: "user".to_string(), sha: Some("sha".to_string()), subdir: Some("rust/core".to_string())}),
+            "git/url//crates/foo" => Crate::Git(GitRepo{url: "url".to_string(), sha: None, subdir: Some("crates/foo".to_string())}),
         }
+
+        // Subdirectories are normalized, and can't leave the repository
+        assert_eq!(
+            Crate::from_str("gh/org/user//./rust/").unwrap().subdir(),
+            Some("rust")
+        );
+        assert!(Crate::from_str("gh/org/user//../etc").is_err());
+        assert!(Crate::from_str("gh/org/user//").is_err());
+        assert!(Crate::from_str("local/foo//bar").is_err());
+    }
+
+    #[test]
+    fn test_split_subdir() {
+        assert_eq!(
+            split_subdir("https://gitlab.com/org/repo").unwrap(),
+            ("https://gitlab.com/org/repo", None)
+        );
+        assert_eq!(
+            split_subdir("https://gitlab.com/org/repo#f00//crates/foo").unwrap(),
+            ("https://gitlab.com/org/repo#f00", Some("crates/foo".into()))
+        );
+        assert_eq!(
+            split_subdir("org/repo//rust").unwrap(),
+            ("org/repo", Some("rust".into()))
+        );
     }
 }
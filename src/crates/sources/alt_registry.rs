@@ -0,0 +1,197 @@
+use crate::config::{AltRegistryConfig, Config};
+use crate::crates::index::{format_download_url, RegistryIndex};
+use crate::crates::{lists::List, Crate};
+use crate::dirs::WORK_DIR;
+use crate::prelude::*;
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tar::Archive;
+
+pub(crate) struct AltRegistryList<'a> {
+    registries: &'a HashMap<String, AltRegistryConfig>,
+}
+
+impl<'a> AltRegistryList<'a> {
+    pub(crate) fn new(registries: &'a HashMap<String, AltRegistryConfig>) -> Self {
+        AltRegistryList { registries }
+    }
+}
+
+impl List for AltRegistryList<'_> {
+    const NAME: &'static str = "alt-registry";
+
+    fn fetch(&self) -> Fallible<Vec<Crate>> {
+        let mut list = Vec::new();
+
+        for (registry, config) in self.registries {
+            info!("loading the index of the {} registry", registry);
+            let mut index = open_index(registry, config)?;
+            index.update()?;
+
+            for krate in index.crates()? {
+                // Only test the latest version that wasn't yanked
+                if let Some(version) = krate.versions.iter().rev().find(|v| !v.yanked) {
+                    list.push(Crate::AltRegistry(AltRegistryCrate {
+                        registry: registry.clone(),
+                        name: krate.name.clone(),
+                        version: version.version.clone(),
+                    }));
+                }
+            }
+        }
+
+        Ok(list)
+    }
+}
+
+lazy_static! {
+    /// `dl` templates of the registries, as reports need the URL of every crate and opening the
+    /// index each time would be too slow.
+    static ref DL_TEMPLATES: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+/// Fetch the indexes of all the configured registries. This has to be done once before running
+/// an experiment, as the copy on agents is only updated by the server when updating the lists.
+pub(crate) fn update_alt_registries(config: &Config) -> Fallible<()> {
+    for (registry, registry_config) in &config.alt_registries {
+        info!("updating the index of the {} registry", registry);
+        open_index(registry, registry_config)?.update()?;
+    }
+    Ok(())
+}
+
+fn registry_dir(registry: &str) -> PathBuf {
+    WORK_DIR.join("alt-registries").join(registry)
+}
+
+fn open_index(registry: &str, config: &AltRegistryConfig) -> Fallible<RegistryIndex> {
+    if let Some(ref path) = config.index_path {
+        RegistryIndex::open_directory(path)
+    } else {
+        RegistryIndex::open_git(registry_dir(registry).join("index"), &config.index)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
+pub struct AltRegistryCrate {
+    pub registry: String,
+    pub name: String,
+    pub version: String,
+}
+
+impl AltRegistryCrate {
+    /// Directory the source of the crate is extracted to.
+    pub(crate) fn source_dir(&self) -> PathBuf {
+        registry_dir(&self.registry)
+            .join("crates")
+            .join(format!("{}-{}", self.name, self.version))
+    }
+
+    fn index(&self, config: &Config) -> Fallible<RegistryIndex> {
+        let registry = config
+            .alt_registries
+            .get(&self.registry)
+            .ok_or_else(|| err_msg(format!("the {} registry is not configured", self.registry)))?;
+        open_index(&self.registry, registry)
+    }
+
+    /// URL the `.crate` file of the crate can be downloaded from, as registries don't have a
+    /// standard page to browse their crates.
+    pub(crate) fn url(&self, config: &Config) -> Fallible<String> {
+        let mut templates = DL_TEMPLATES.lock().unwrap();
+        let dl = match templates.get(&self.registry) {
+            Some(dl) => dl.clone(),
+            None => {
+                let dl = self.index(config)?.dl_template()?;
+                templates.insert(self.registry.clone(), dl.clone());
+                dl
+            }
+        };
+        Ok(format_download_url(&dl, &self.name, &self.version))
+    }
+
+    /// Download and extract the source of the crate, as rustwide can only fetch crates from
+    /// crates.io. Published versions never change, so they're downloaded only once.
+    pub(crate) fn download(&self, config: &Config) -> Fallible<()> {
+        let dest = self.source_dir();
+        if dest.is_dir() {
+            return Ok(());
+        }
+
+        // The index is fetched once by `update_alt_registries` before the experiment starts
+        let url = self
+            .index(config)?
+            .download_url(&self.name, &self.version)?;
+
+        info!("downloading {} from {}", self, url);
+        let resp = crate::utils::http::get_sync(&url)
+            .with_context(|_| format!("failed to download {}", self))?;
+
+        // The archive contains a `{name}-{version}` directory, which is extracted in a temporary
+        // directory first to avoid leaving a partial source around if the download fails
+        let parent = dest.parent().unwrap();
+        fs::create_dir_all(parent)?;
+        let tmp = tempfile::tempdir_in(parent)?;
+        Archive::new(GzDecoder::new(resp))
+            .unpack(tmp.path())
+            .with_context(|_| format!("failed to extract {}", self))?;
+        fs::rename(tmp.path().join(dest.file_name().unwrap()), &dest)?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for AltRegistryCrate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{} ({})", self.name, self.version, self.registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AltRegistryCrate, AltRegistryList};
+    use crate::config::AltRegistryConfig;
+    use crate::crates::index::write_test_index;
+    use crate::crates::lists::List;
+    use crate::crates::Crate;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_fetch() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_index(dir.path()).unwrap();
+
+        let mut registries = HashMap::new();
+        registries.insert(
+            "crater-test".to_string(),
+            AltRegistryConfig {
+                index: "https://git.example.com/index".into(),
+                index_path: Some(dir.path().into()),
+                crates: HashMap::new(),
+            },
+        );
+
+        let mut list = AltRegistryList::new(&registries).fetch().unwrap();
+        list.sort();
+        let alt = |name: &str, version: &str| {
+            Crate::AltRegistry(AltRegistryCrate {
+                registry: "crater-test".into(),
+                name: name.into(),
+                version: version.into(),
+            })
+        };
+        assert_eq!(
+            list,
+            vec![
+                alt("a", "0.1.0"),
+                alt("app", "1.0.0"),
+                alt("log", "0.4.0"),
+                alt("serde", "1.0.0")
+            ]
+        );
+    }
+}
@@ -27,10 +27,10 @@ impl Default for GitHubList {
     }
 }
 
-impl List for GitHubList {
-    const NAME: &'static str = "github-oss";
-
-    fn fetch(&self) -> Fallible<Vec<Crate>> {
+impl GitHubList {
+    /// Repositories with a `Cargo.toml`, keeping only the ones that have a `Cargo.lock` or only
+    /// the ones that don't.
+    fn fetch_repos(&self, with_lockfile: bool) -> Fallible<Vec<Crate>> {
         info!("loading cached GitHub list from {}", self.source);
 
         let mut resp = crate::utils::http::get_sync(&self.source)
@@ -41,8 +41,7 @@ impl List for GitHubList {
         for line in reader.deserialize() {
             let line: ListRepo = line?;
 
-            // Only import repos with a Cargo.toml or Cargo.lock
-            if !line.has_cargo_toml || !line.has_cargo_lock {
+            if !line.has_cargo_toml || line.has_cargo_lock != with_lockfile {
                 continue;
             }
 
@@ -56,6 +55,7 @@ impl List for GitHubList {
                     org: org.to_string(),
                     name: name.to_string(),
                     sha: None,
+                    subdir: None,
                 }));
             } else {
                 warn!("skipping malformed repo name: {}", line.name);
@@ -66,11 +66,37 @@ impl List for GitHubList {
     }
 }
 
+impl List for GitHubList {
+    const NAME: &'static str = "github-oss";
+
+    fn fetch(&self) -> Fallible<Vec<Crate>> {
+        self.fetch_repos(true)
+    }
+}
+
+/// Repositories without a `Cargo.lock`, which are left out of the full selection as their
+/// dependencies have to be resolved when they're tested.
+#[derive(Default)]
+pub(crate) struct GitHubUnlockedList {
+    list: GitHubList,
+}
+
+impl List for GitHubUnlockedList {
+    const NAME: &'static str = "github-oss-unlocked";
+
+    fn fetch(&self) -> Fallible<Vec<Crate>> {
+        self.list.fetch_repos(false)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
 pub struct GitHubRepo {
     pub org: String,
     pub name: String,
     pub sha: Option<String>,
+    /// Directory of the repository containing the crate, if it's not at the root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<String>,
 }
 
 impl GitHubRepo {
@@ -83,6 +109,7 @@ impl GitHubRepo {
             org: DUMMY_ORG.to_string(),
             name: DUMMY_NAME.to_string(),
             sha: None,
+            subdir: None,
         }
     }
 }
@@ -105,6 +132,7 @@ impl FromStr for GitHubRepo {
                 org: org.to_string(),
                 name: name.to_string(),
                 sha: None,
+                subdir: None,
             }),
             (Some(org), Some(name), Some(sha)) => Ok(GitHubRepo {
                 org: org.to_string(),
@@ -112,6 +140,7 @@ impl FromStr for GitHubRepo {
                 // as the crate version is already uniquely determined
                 name: name.split('?').next().unwrap().to_string(),
                 sha: Some(sha.to_string()),
+                subdir: None,
             }),
             _ => bail!("malformed repo url: {}", input),
         }
@@ -130,7 +159,8 @@ mod tests {
             GitHubRepo {
                 org: "dummy_org".to_string(),
                 name: "dummy".to_string(),
-                sha: Some("dummy_sha".to_string())
+                sha: Some("dummy_sha".to_string()),
+                subdir: None,
             }
         );
         assert_eq!(
@@ -138,7 +168,8 @@ mod tests {
             GitHubRepo {
                 org: "dummy_org".to_string(),
                 name: "dummy".to_string(),
-                sha: None
+                sha: None,
+                subdir: None,
             }
         );
     }
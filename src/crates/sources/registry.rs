@@ -1,66 +1,915 @@
+use crate::config::RegistryConfig;
+use crate::crates::index::{IndexCrate, IndexVersion, RegistryIndex};
 use crate::crates::{lists::List, Crate};
-use crate::dirs::WORK_DIR;
+use crate::db::{Database, QueryUtils};
 use crate::prelude::*;
-use crates_index::Index;
-use std::collections::HashMap;
-use std::fs::{self};
+use chrono::NaiveDate;
+use semver::{Comparator, Op, Prerelease, Version, VersionReq};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
-pub(crate) struct RegistryList;
+pub(crate) struct RegistryList<'a> {
+    config: &'a RegistryConfig,
+}
 
-impl List for RegistryList {
-    const NAME: &'static str = "registry";
+impl<'a> RegistryList<'a> {
+    pub(crate) fn new(config: &'a RegistryConfig) -> Self {
+        RegistryList { config }
+    }
+}
 
-    fn fetch(&self) -> Fallible<Vec<Crate>> {
-        let mut list = Vec::new();
-        let mut counts = HashMap::new();
+impl RegistryList<'_> {
+    /// Store the dependencies of the version of every crate included in the list, so that
+    /// selecting the reverse dependencies of a crate doesn't need to read the whole index. Only
+    /// the crates whose listed version changed since the last update are written, and their
+    /// number is returned.
+    pub(crate) fn update_dependencies(&self, db: &Database) -> Fallible<usize> {
+        let index = RegistryIndex::open(self.config)?;
+        let crates = index.crates()?;
 
-        fs::create_dir_all(&*WORK_DIR)?;
-        let mut index = Index::with_path(
-            WORK_DIR.join("crates.io-index"),
-            "https://github.com/rust-lang/crates.io-index",
-        )
-        .to_failure()?;
-        index.update().to_failure()?;
-
-        for krate in index.crates() {
-            // The versions() method returns the list of published versions starting from the
-            // first one, so its output is reversed to check the latest first
-            for version in krate.versions().iter().rev() {
-                // Try every version until we find a non-yanked one. If all the versions are
-                // yanked the crate is automatically skipped
-                if !version.is_yanked() {
-                    // Increment the counters of this crate's dependencies
-                    for dependency in version.dependencies() {
-                        let count = counts.entry(dependency.name().to_string()).or_insert(0);
-                        *count += 1;
+        // The listed versions are stored on their own, as crates without dependencies don't have
+        // any row in `registry_dependencies`
+        let stored = db
+            .query("SELECT name, version FROM registry_versions;", &[], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let listed = crates
+            .iter()
+            .filter_map(|krate| listed_version(krate).map(|version| (&krate.name, version)))
+            .collect::<HashMap<_, _>>();
+
+        let changed = db.transaction(|t| {
+            let mut changed = 0;
+            for (name, version) in &listed {
+                if stored.get(*name) == Some(&version.version) {
+                    continue;
+                }
+                changed += 1;
+                t.execute_cached(
+                    "INSERT INTO registry_versions (name, version) VALUES (?1, ?2);",
+                    &[name, &version.version],
+                )?;
+                t.execute_cached(
+                    "DELETE FROM registry_dependencies WHERE name = ?1;",
+                    &[name],
+                )?;
+                for dependency in &version.dependencies {
+                    t.execute_cached(
+                        "INSERT INTO registry_dependencies (name, version, dependency) \
+                         VALUES (?1, ?2, ?3);",
+                        &[name, &version.version, dependency],
+                    )?;
+                }
+            }
+
+            // Crates removed from the list, or having all their versions yanked
+            for name in stored.keys().filter(|name| !listed.contains_key(name)) {
+                t.execute_cached("DELETE FROM registry_versions WHERE name = ?1;", &[name])?;
+                t.execute_cached(
+                    "DELETE FROM registry_dependencies WHERE name = ?1;",
+                    &[name],
+                )?;
+            }
+
+            Ok(changed)
+        })?;
+
+        info!("updated the dependencies of {} registry crates", changed);
+        Ok(changed)
+    }
+
+    /// Crates depending on `krate`, either directly or through at most `depth` levels of
+    /// dependencies (or any number of levels if no depth is given). The dependencies of every
+    /// crate are the ones of the version included in the list, as stored by
+    /// `update_dependencies`.
+    pub(crate) fn reverse_dependencies(
+        db: &Database,
+        krate: &str,
+        depth: Option<u32>,
+    ) -> Fallible<Vec<Crate>> {
+        if !db.exists("SELECT rowid FROM registry_versions LIMIT 1;", &[])? {
+            bail!("the dependencies of the registry crates weren't loaded yet");
+        }
+        if !db.exists(
+            "SELECT rowid FROM registry_versions WHERE name = ?1;",
+            &[&krate],
+        )? {
+            bail!("crate {} is not in the registry list", krate);
+        }
+
+        let mut found = HashSet::new();
+        let mut current = vec![krate.to_string()];
+        let mut level = 0;
+        while !current.is_empty() && depth.map(|depth| level < depth).unwrap_or(true) {
+            let mut next = Vec::new();
+            for name in &current {
+                let dependents = db.query(
+                    "SELECT name, version FROM registry_dependencies WHERE dependency = ?1;",
+                    &[name],
+                    |row| {
+                        Ok(RegistryCrate {
+                            name: row.get(0)?,
+                            version: row.get(1)?,
+                        })
+                    },
+                )?;
+                for dependent in dependents {
+                    if dependent.name != krate && found.insert(dependent.clone()) {
+                        next.push(dependent.name);
                     }
+                }
+            }
+            current = next;
+            level += 1;
+        }
 
-                    list.push(Crate::Registry(RegistryCrate {
-                        name: krate.name().to_string(),
-                        version: version.version().to_string(),
+        Ok(found.into_iter().map(Crate::Registry).collect())
+    }
+
+    /// Score of every crate in the list, as stored by the last update.
+    pub(crate) fn scores(db: &Database) -> Fallible<HashMap<String, i64>> {
+        let mut scores = HashMap::new();
+        for (krate, score) in db.query(
+            "SELECT crate, score FROM crates WHERE list = ?1 AND score IS NOT NULL;",
+            &[&Self::NAME],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+        )? {
+            if let Crate::Registry(krate) = krate.parse()? {
+                scores.insert(krate.name, score);
+            }
+        }
+        Ok(scores)
+    }
+
+    /// Number of crates in the list depending on every crate, which estimates how much of the
+    /// ecosystem breaks along with a crate. The dependencies are the ones stored by
+    /// `update_dependencies`, so the index isn't read.
+    pub(crate) fn dependents_counts(db: &Database) -> Fallible<HashMap<String, i64>> {
+        Ok(db
+            .query(
+                "SELECT dependency, COUNT(*) FROM registry_dependencies GROUP BY dependency;",
+                &[],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+            .into_iter()
+            .collect())
+    }
+
+    /// Check that every requested version was published, as they're selected explicitly rather
+    /// than through the list.
+    pub(crate) fn exact_versions(&self, requested: &[RegistryCrate]) -> Fallible<Vec<Crate>> {
+        let index = RegistryIndex::open(self.config)?;
+
+        let mut missing = Vec::new();
+        for krate in requested {
+            let published = index.crate_(&krate.name)?.map_or(false, |indexed| {
+                indexed.versions.iter().any(|v| v.version == krate.version)
+            });
+            if !published {
+                missing.push(format!("{}@{}", krate.name, krate.version));
+            }
+        }
+        if !missing.is_empty() {
+            bail!("missing desired crate versions: {:?}", missing);
+        }
+
+        Ok(requested.iter().cloned().map(Crate::Registry).collect())
+    }
+
+    /// The latest version of every semver-compatible line of the crates, like the latest 5.x,
+    /// 6.x and 7.x versions of `nom`. Yanked versions and pre-releases are skipped.
+    pub(crate) fn semver_heads(&self, names: &HashSet<String>) -> Fallible<Vec<Crate>> {
+        let index = RegistryIndex::open(self.config)?;
+
+        let mut heads = Vec::new();
+        for name in names {
+            let indexed = match index.crate_(name)? {
+                Some(indexed) => indexed,
+                None => bail!("crate {} is not in the registry index", name),
+            };
+
+            let mut releases = indexed
+                .versions
+                .iter()
+                .filter(|v| !v.yanked)
+                .filter_map(|v| match Version::parse(&v.version) {
+                    Ok(parsed) if parsed.pre.is_empty() => Some((parsed, v)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            releases.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+            // Starting from the latest release, every version not compatible with one of the
+            // heads found so far is the head of a new line
+            let mut lines: Vec<Version> = Vec::new();
+            for (parsed, version) in releases {
+                let compatible = compatible_with(&parsed);
+                if !lines.iter().any(|head| compatible.matches(head)) {
+                    lines.push(parsed);
+                    heads.push(Crate::Registry(RegistryCrate {
+                        name: indexed.name.clone(),
+                        version: version.version.clone(),
                     }));
-                    break;
                 }
             }
         }
 
-        // Ensure the list is sorted by popularity
-        list.sort_by(|a, b| {
-            if let (Crate::Registry(ref a), Crate::Registry(ref b)) = (a, b) {
-                let count_a = counts.get(&a.name).cloned().unwrap_or(0);
-                let count_b = counts.get(&b.name).cloned().unwrap_or(0);
-                count_b.cmp(&count_a)
-            } else {
-                panic!("non-registry crate produced in the registry list");
-            }
+        Ok(heads)
+    }
+}
+
+/// The versions Cargo considers compatible with `version` and newer than it, which is what a
+/// dependency on `version` (a caret requirement) accepts. For example `1.5.3` is compatible with
+/// `1.2.0`, while `0.3.0` isn't compatible with `0.2.0`.
+fn compatible_with(version: &Version) -> VersionReq {
+    VersionReq {
+        comparators: vec![Comparator {
+            op: Op::Caret,
+            major: version.major,
+            minor: Some(version.minor),
+            patch: Some(version.patch),
+            pre: Prerelease::EMPTY,
+        }],
+    }
+}
+
+/// The semver-compatible line of `version`, for example `1` for `1.5.3` and `0.3` for `0.3.1`.
+/// Versions that can't be parsed are their own line.
+pub(crate) fn compatible_line(version: &str) -> String {
+    match Version::parse(version) {
+        Ok(parsed) if parsed.major > 0 => parsed.major.to_string(),
+        Ok(parsed) if parsed.minor > 0 => format!("0.{}", parsed.minor),
+        Ok(parsed) => format!("0.0.{}", parsed.patch),
+        Err(_) => version.to_string(),
+    }
+}
+
+/// The version of the crate included in the list: the latest one that wasn't yanked. If all the
+/// versions are yanked the crate is skipped.
+fn listed_version(krate: &IndexCrate) -> Option<&IndexVersion> {
+    // The list of published versions starts from the first one, so it's reversed to check the
+    // latest first
+    krate.versions.iter().rev().find(|version| !version.yanked)
+}
+
+/// Count the crates depending on every crate, through the dependencies of their listed version.
+fn count_dependents(crates: &[IndexCrate]) -> HashMap<String, i64> {
+    let mut counts = HashMap::new();
+    for version in crates.iter().filter_map(listed_version) {
+        for dependency in &version.dependencies {
+            *counts.entry(dependency.clone()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+impl List for RegistryList<'_> {
+    const NAME: &'static str = "registry";
+
+    fn fetch(&self) -> Fallible<Vec<Crate>> {
+        Ok(self
+            .fetch_scored()?
+            .into_iter()
+            .map(|(krate, _)| krate)
+            .collect())
+    }
+
+    /// The score of a crate is its number of downloads if a crates.io database dump is
+    /// configured, or the number of crates depending on it otherwise. Crates missing from the
+    /// dump have no score, and are listed after the other ones by their number of dependents.
+    fn fetch_scored(&self) -> Fallible<Vec<(Crate, Option<i64>)>> {
+        let downloads = match self.config.db_dump {
+            Some(ref path) => Some(load_downloads(path)?),
+            None => None,
+        };
+
+        let mut index = RegistryIndex::open(self.config)?;
+        index.update()?;
+
+        let crates = index.crates()?;
+        let counts = count_dependents(&crates);
+        let list = crates.iter().filter_map(|krate| {
+            listed_version(krate).map(|version| RegistryCrate {
+                name: krate.name.clone(),
+                version: version.version.clone(),
+            })
+        });
+
+        let mut list = list
+            .map(|krate| {
+                let count = counts.get(&krate.name).cloned().unwrap_or(0);
+                let score = match downloads {
+                    // Crates published after the dump was taken are missing from it
+                    Some(ref downloads) => downloads.get(&krate.name).cloned(),
+                    None => Some(count),
+                };
+                (krate, score, count)
+            })
+            .collect::<Vec<_>>();
+
+        // Ensure the list is sorted by popularity, using the number of dependents to break ties.
+        // Crates without a score sort last.
+        list.sort_by(|(_, score_a, count_a), (_, score_b, count_b)| {
+            score_b.cmp(score_a).then(count_b.cmp(count_a))
         });
 
-        Ok(list)
+        Ok(list
+            .into_iter()
+            .map(|(krate, score, _)| (Crate::Registry(krate), score))
+            .collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct DumpCrate {
+    id: i64,
+    name: String,
+    /// Only present in dumps created before the download counts were moved to a separate file.
+    downloads: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct DumpCrateDownloads {
+    crate_id: i64,
+    downloads: i64,
+}
+
+#[derive(Deserialize)]
+struct DumpCategory {
+    id: i64,
+    slug: String,
+}
+
+#[derive(Deserialize)]
+struct DumpCrateCategory {
+    crate_id: i64,
+    category_id: i64,
+}
+
+#[derive(Deserialize)]
+struct DumpKeyword {
+    id: i64,
+    keyword: String,
+}
+
+#[derive(Deserialize)]
+struct DumpCrateKeyword {
+    crate_id: i64,
+    keyword_id: i64,
+}
+
+#[derive(Deserialize)]
+struct DumpVersion {
+    crate_id: i64,
+    num: String,
+    created_at: String,
+}
+
+/// Read one of the CSV files of the database dump `crates.csv` is part of.
+fn read_dump<T: DeserializeOwned>(crates_csv: &Path, file: &str) -> Fallible<Vec<T>> {
+    let path = crates_csv.with_file_name(file);
+    let mut records = Vec::new();
+    for record in ::csv::Reader::from_path(&path)
+        .with_context(|_| format!("failed to open {}", path.display()))?
+        .deserialize()
+    {
+        records.push(record?);
+    }
+    Ok(records)
+}
+
+/// A category or keyword of a crate, as a `(name, kind, tag)` tuple.
+type Tag = (String, String, String);
+/// Publication dates of crate versions, keyed by their `(name, version)`.
+type PublicationDates = HashMap<(String, String), String>;
+
+impl RegistryList<'_> {
+    /// Store the categories, keywords and publication dates of the registry crates. They're read
+    /// from the crates.io database dump if one is configured, otherwise only the publication
+    /// dates recorded in the index are available. Only the rows that changed since the last
+    /// update are written.
+    pub(crate) fn update_metadata(&self, db: &Database) -> Fallible<()> {
+        let (tags, versions) = match self.config.db_dump {
+            Some(ref crates_csv) => load_dump_metadata(crates_csv)?,
+            None => (HashSet::new(), self.load_index_metadata()?),
+        };
+
+        let stored_tags = db
+            .query("SELECT name, kind, tag FROM registry_tags;", &[], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .into_iter()
+            .collect::<HashSet<Tag>>();
+        let stored_versions = db
+            .query(
+                "SELECT name, version, published_on FROM registry_versions;",
+                &[],
+                |row| Ok(((row.get(0)?, row.get(1)?), row.get(2)?)),
+            )?
+            .into_iter()
+            .collect::<PublicationDates>();
+
+        db.transaction(|t| {
+            for (name, kind, tag) in tags.difference(&stored_tags) {
+                t.execute_cached(
+                    "INSERT INTO registry_tags (name, kind, tag) VALUES (?1, ?2, ?3);",
+                    &[name, kind, tag],
+                )?;
+            }
+            for (name, kind, tag) in stored_tags.difference(&tags) {
+                t.execute_cached(
+                    "DELETE FROM registry_tags WHERE name = ?1 AND kind = ?2 AND tag = ?3;",
+                    &[name, kind, tag],
+                )?;
+            }
+
+            for (key, published_on) in &versions {
+                if stored_versions.get(key) != Some(published_on) {
+                    let (name, version) = key;
+                    t.execute_cached(
+                        "INSERT INTO registry_versions (name, version, published_on) \
+                         VALUES (?1, ?2, ?3);",
+                        &[name, version, published_on],
+                    )?;
+                }
+            }
+            for key in stored_versions.keys() {
+                if !versions.contains_key(key) {
+                    let (name, version) = key;
+                    t.execute_cached(
+                        "DELETE FROM registry_versions WHERE name = ?1 AND version = ?2;",
+                        &[name, version],
+                    )?;
+                }
+            }
+
+            Ok(())
+        })?;
+
+        info!(
+            "loaded {} tags and the publication dates of {} crate versions",
+            tags.len(),
+            versions.len()
+        );
+        Ok(())
+    }
+
+    /// Publication dates of the versions in the index, which are only recorded by recent
+    /// versions of the registry.
+    fn load_index_metadata(&self) -> Fallible<PublicationDates> {
+        let index = RegistryIndex::open(self.config)?;
+        let mut versions = HashMap::new();
+        for krate in index.crates()? {
+            for version in krate.versions {
+                if let Some(published_on) = version.published_on {
+                    versions.insert((krate.name.clone(), version.version), published_on);
+                }
+            }
+        }
+        Ok(versions)
     }
 }
 
+/// Load the categories, keywords and publication dates of the crates from a crates.io database
+/// dump.
+fn load_dump_metadata(crates_csv: &Path) -> Fallible<(HashSet<Tag>, PublicationDates)> {
+    let names = read_dump::<DumpCrate>(crates_csv, "crates.csv")?
+        .into_iter()
+        .map(|krate| (krate.id, krate.name))
+        .collect::<HashMap<_, _>>();
+    let categories = read_dump::<DumpCategory>(crates_csv, "categories.csv")?
+        .into_iter()
+        .map(|category| (category.id, category.slug))
+        .collect::<HashMap<_, _>>();
+    let keywords = read_dump::<DumpKeyword>(crates_csv, "keywords.csv")?
+        .into_iter()
+        .map(|keyword| (keyword.id, keyword.keyword))
+        .collect::<HashMap<_, _>>();
+
+    let mut tags = HashSet::new();
+    for record in read_dump::<DumpCrateCategory>(crates_csv, "crates_categories.csv")? {
+        if let (Some(name), Some(category)) = (
+            names.get(&record.crate_id),
+            categories.get(&record.category_id),
+        ) {
+            tags.insert((name.clone(), "category".into(), category.clone()));
+        }
+    }
+    for record in read_dump::<DumpCrateKeyword>(crates_csv, "crates_keywords.csv")? {
+        if let (Some(name), Some(keyword)) = (
+            names.get(&record.crate_id),
+            keywords.get(&record.keyword_id),
+        ) {
+            tags.insert((name.clone(), "keyword".into(), keyword.clone()));
+        }
+    }
+
+    let mut versions = HashMap::new();
+    for version in read_dump::<DumpVersion>(crates_csv, "versions.csv")? {
+        if let Some(name) = names.get(&version.crate_id) {
+            // Timestamps start with the date, like `2020-01-01 12:00:00.000000`
+            let published_on = version.created_at.get(..10).unwrap_or("").to_string();
+            versions.insert((name.clone(), version.num), published_on);
+        }
+    }
+
+    Ok((tags, versions))
+}
+
+/// Names of the registry crates with the given category or keyword. Categories also match their
+/// subcategories, so `science` matches `science::robotics` too.
+pub(crate) fn crates_with_tag(db: &Database, kind: &str, tag: &str) -> Fallible<HashSet<String>> {
+    if !db.exists("SELECT rowid FROM registry_tags LIMIT 1;", &[])? {
+        bail!(
+            "no categories and keywords are available, as no crates.io database dump is configured"
+        );
+    }
+    // The prefix is compared exactly, as `_` and `%` in the tag would be wildcards with `LIKE`
+    Ok(db
+        .query(
+            "SELECT name FROM registry_tags \
+             WHERE kind = ?1 AND (tag = ?2 OR substr(tag, 1, length(?2) + 2) = ?2 || '::');",
+            &[&kind, &tag],
+            |row| row.get(0),
+        )?
+        .into_iter()
+        .collect())
+}
+
+/// Registry crate versions published on or after the given date, as `(name, version)` pairs.
+pub(crate) fn versions_published_since(
+    db: &Database,
+    date: NaiveDate,
+) -> Fallible<HashSet<(String, String)>> {
+    if !db.exists("SELECT rowid FROM registry_versions LIMIT 1;", &[])? {
+        bail!(
+            "no publication dates are available, as no crates.io database dump is configured \
+             and the index doesn't record them"
+        );
+    }
+    Ok(db
+        .query(
+            "SELECT name, version FROM registry_versions WHERE published_on >= ?1;",
+            &[&date.format("%Y-%m-%d").to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?
+        .into_iter()
+        .collect())
+}
+
+/// Load the number of downloads of every crate from the `crates.csv` file of a crates.io
+/// database dump, or from the `crate_downloads.csv` file next to it in recent dumps.
+fn load_downloads(crates_csv: &Path) -> Fallible<HashMap<String, i64>> {
+    let mut names = HashMap::new();
+    let mut downloads = HashMap::new();
+    for record in ::csv::Reader::from_path(crates_csv)
+        .with_context(|_| format!("failed to open {}", crates_csv.display()))?
+        .deserialize()
+    {
+        let record: DumpCrate = record?;
+        if let Some(count) = record.downloads {
+            downloads.insert(record.name.clone(), count);
+        }
+        names.insert(record.id, record.name);
+    }
+
+    let downloads_csv = crates_csv.with_file_name("crate_downloads.csv");
+    if downloads.is_empty() && downloads_csv.is_file() {
+        for record in ::csv::Reader::from_path(&downloads_csv)?.deserialize() {
+            let record: DumpCrateDownloads = record?;
+            if let Some(name) = names.remove(&record.crate_id) {
+                downloads.insert(name, record.downloads);
+            }
+        }
+    }
+
+    Ok(downloads)
+}
+
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
 pub struct RegistryCrate {
     pub name: String,
     pub version: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compatible_line, crates_with_tag, versions_published_since, RegistryCrate, RegistryList,
+    };
+    use crate::config::{Config, RegistryConfig};
+    use crate::crates::index::write_test_index;
+    use crate::crates::lists::{get_crates, List};
+    use crate::crates::Crate;
+    use crate::db::Database;
+    use crate::experiments::CrateSelect;
+    use chrono::NaiveDate;
+    use std::collections::HashSet;
+    use std::fs;
+
+    #[test]
+    fn test_compatible_line() {
+        assert_eq!(compatible_line("1.5.3"), "1");
+        assert_eq!(compatible_line("2.0.0-alpha.1"), "2");
+        assert_eq!(compatible_line("0.3.1"), "0.3");
+        assert_eq!(compatible_line("0.0.4"), "0.0.4");
+        assert_eq!(compatible_line("not-a-version"), "not-a-version");
+    }
+
+    #[test]
+    fn test_fetch_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_index(dir.path()).unwrap();
+        let config = RegistryConfig {
+            index_path: Some(dir.path().into()),
+            ..RegistryConfig::default()
+        };
+
+        let list = RegistryList::new(&config).fetch().unwrap();
+        let reg = |name: &str, version: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: version.into(),
+            })
+        };
+
+        // The most depended on crate comes first, and yanked versions are skipped
+        assert_eq!(list.len(), 4);
+        assert_eq!(list[0], reg("log", "0.4.0"));
+        assert!(list.contains(&reg("a", "0.1.0")));
+        assert!(list.contains(&reg("app", "1.0.0")));
+        assert!(list.contains(&reg("serde", "1.0.0")));
+    }
+
+    #[test]
+    fn test_fetch_with_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_index(dir.path()).unwrap();
+        let dump = tempfile::tempdir().unwrap();
+        let crates_csv = dump.path().join("crates.csv");
+        let mut config = RegistryConfig {
+            index_path: Some(dir.path().into()),
+            db_dump: Some(crates_csv.clone()),
+            ..RegistryConfig::default()
+        };
+        let names = |config: &RegistryConfig| {
+            RegistryList::new(config)
+                .fetch_scored()
+                .unwrap()
+                .into_iter()
+                .map(|(krate, score)| match krate {
+                    Crate::Registry(krate) => (krate.name, score),
+                    _ => panic!("non-registry crate produced in the registry list"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Old dumps include the downloads in crates.csv
+        fs::write(
+            &crates_csv,
+            "created_at,downloads,id,name,readme\n\
+             2020-01-01,10,1,a,\"a, b\"\n\
+             2020-01-01,30,2,app,\n\
+             2020-01-01,20,4,serde,\n",
+        )
+        .unwrap();
+        // Crates missing from the dump are ranked last, by their number of dependents
+        assert_eq!(
+            names(&config),
+            vec![
+                ("app".into(), Some(30)),
+                ("serde".into(), Some(20)),
+                ("a".into(), Some(10)),
+                ("log".into(), None)
+            ]
+        );
+
+        // New dumps store them in a separate file
+        fs::write(&crates_csv, "id,name\n1,a\n2,app\n3,log\n4,serde\n").unwrap();
+        fs::write(
+            dump.path().join("crate_downloads.csv"),
+            "crate_id,downloads\n1,5\n3,50\n",
+        )
+        .unwrap();
+        assert_eq!(
+            names(&config),
+            vec![
+                ("log".into(), Some(50)),
+                ("a".into(), Some(5)),
+                ("serde".into(), None),
+                ("app".into(), None)
+            ]
+        );
+
+        // Without a dump the crates are ranked by their number of dependents
+        config.db_dump = None;
+        assert_eq!(names(&config)[0], ("log".into(), Some(2)));
+    }
+
+    #[test]
+    fn test_reverse_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_index(dir.path()).unwrap();
+        let config = RegistryConfig {
+            index_path: Some(dir.path().into()),
+            ..RegistryConfig::default()
+        };
+        let db = Database::temp().unwrap();
+        assert!(RegistryList::reverse_dependencies(&db, "log", None).is_err());
+        assert_eq!(
+            RegistryList::new(&config).update_dependencies(&db).unwrap(),
+            4
+        );
+
+        let rdeps = |krate: &str, depth: Option<u32>| {
+            let mut crates = RegistryList::reverse_dependencies(&db, krate, depth).unwrap();
+            crates.sort();
+            crates
+                .into_iter()
+                .map(|krate| match krate {
+                    Crate::Registry(krate) => krate.name,
+                    _ => panic!("non-registry reverse dependency"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(rdeps("log", Some(1)), vec!["a", "serde"]);
+        assert_eq!(rdeps("log", Some(2)), vec!["a", "app", "serde"]);
+        assert_eq!(rdeps("log", None), vec!["a", "app", "serde"]);
+        assert_eq!(rdeps("app", None), Vec::<String>::new());
+        assert!(RegistryList::reverse_dependencies(&db, "missing", None).is_err());
+
+        let counts = RegistryList::dependents_counts(&db).unwrap();
+        assert_eq!(counts["log"], 2);
+        assert_eq!(counts["serde"], 1);
+        assert!(!counts.contains_key("app"));
+
+        // Only the crates whose listed version changed are rewritten
+        fs::write(
+            dir.path().join("3/a/app"),
+            r#"{"name":"app","vers":"2.0.0","deps":[],"yanked":false}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            RegistryList::new(&config).update_dependencies(&db).unwrap(),
+            1
+        );
+        assert_eq!(rdeps("log", None), vec!["a", "serde"]);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let db = Database::temp().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        write_test_index(dir.path()).unwrap();
+        let dump = tempfile::tempdir().unwrap();
+        let crates_csv = dump.path().join("crates.csv");
+        let mut config = Config::default();
+        config.registry = RegistryConfig {
+            index_path: Some(dir.path().into()),
+            db_dump: Some(crates_csv.clone()),
+            ..RegistryConfig::default()
+        };
+
+        let files: &[(&str, &str)] = &[
+            ("crates.csv", "id,name\n1,a\n2,app\n3,log\n4,serde\n"),
+            (
+                "categories.csv",
+                "category,id,slug\nScience,1,science\nRobotics,2,science::robotics\n\
+                 Encoding,3,encoding\nNo std,4,no_std\nEmbedded,5,noxstd::embedded\n",
+            ),
+            (
+                "crates_categories.csv",
+                "category_id,crate_id\n1,1\n2,2\n3,4\n4,1\n5,3\n",
+            ),
+            ("keywords.csv", "id,keyword\n1,logging\n2,serde\n"),
+            ("crates_keywords.csv", "crate_id,keyword_id\n3,1\n4,2\n"),
+            (
+                "versions.csv",
+                "crate_id,created_at,num\n\
+                 1,2019-05-01 10:00:00.000000,0.1.0\n\
+                 3,2017-01-01 10:00:00.000000,0.4.0\n\
+                 3,2021-01-01 10:00:00.000000,0.4.1\n\
+                 4,2020-06-01 10:00:00.000000,1.0.0\n",
+            ),
+        ];
+        for (name, content) in files {
+            fs::write(dump.path().join(name), content).unwrap();
+        }
+
+        // Selecting crates without metadata fails instead of returning nothing
+        assert!(crates_with_tag(&db, "keyword", "serde").is_err());
+
+        let list = RegistryList::new(&config.registry);
+        list.update(&db).unwrap();
+        list.update_metadata(&db).unwrap();
+
+        let names = |select: CrateSelect| {
+            get_crates(&select, &db, &config)
+                .unwrap()
+                .into_iter()
+                .map(|krate| match krate {
+                    Crate::Registry(krate) => krate.name,
+                    _ => panic!("non-registry crate selected"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Categories include their subcategories
+        assert_eq!(
+            names(CrateSelect::Category("science".into())),
+            vec!["a", "app"]
+        );
+        assert_eq!(
+            names(CrateSelect::Category("science::robotics".into())),
+            vec!["app"]
+        );
+        assert_eq!(names(CrateSelect::Keyword("logging".into())), vec!["log"]);
+        // Underscores aren't wildcards
+        assert_eq!(names(CrateSelect::Category("no_std".into())), vec!["a"]);
+
+        // Only the version in the list counts, so log 0.4.1 being yanked excludes log
+        let date = NaiveDate::from_ymd(2019, 1, 1);
+        assert_eq!(names(CrateSelect::PublishedSince(date)), vec!["a", "serde"]);
+        assert_eq!(
+            versions_published_since(&db, date).unwrap(),
+            vec![
+                ("a".to_string(), "0.1.0".to_string()),
+                ("log".to_string(), "0.4.1".to_string()),
+                ("serde".to_string(), "1.0.0".to_string()),
+            ]
+            .into_iter()
+            .collect::<HashSet<_>>()
+        );
+
+        // Updates remove the metadata missing from the new dump
+        fs::write(
+            dump.path().join("versions.csv"),
+            "crate_id,created_at,num\n4,2020-06-01 10:00:00.000000,1.0.0\n",
+        )
+        .unwrap();
+        fs::write(
+            dump.path().join("crates_keywords.csv"),
+            "crate_id,keyword_id\n",
+        )
+        .unwrap();
+        list.update_metadata(&db).unwrap();
+        assert_eq!(names(CrateSelect::PublishedSince(date)), vec!["serde"]);
+        assert!(names(CrateSelect::Keyword("logging".into())).is_empty());
+
+        // Without a dump the publication dates are read from the index
+        config.registry.db_dump = None;
+        let list = RegistryList::new(&config.registry);
+        list.update_metadata(&db).unwrap();
+        assert!(crates_with_tag(&db, "keyword", "serde").is_err());
+        assert_eq!(
+            versions_published_since(&db, NaiveDate::from_ymd(2020, 1, 1)).unwrap(),
+            vec![("serde".to_string(), "1.0.0".to_string())]
+                .into_iter()
+                .collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn test_versions() {
+        let db = Database::temp().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        write_test_index(dir.path()).unwrap();
+        let mut config = Config::default();
+        config.registry = RegistryConfig {
+            index_path: Some(dir.path().into()),
+            ..RegistryConfig::default()
+        };
+        RegistryList::new(&config.registry).update(&db).unwrap();
+
+        let versions = |select: &str| {
+            get_crates(&select.parse().unwrap(), &db, &config)
+                .unwrap()
+                .into_iter()
+                .map(|krate| match krate {
+                    Crate::Registry(krate) => format!("{}@{}", krate.name, krate.version),
+                    _ => panic!("non-registry crate selected"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            versions("list:serde@0.8.0,serde@1.0.0,log"),
+            vec!["log@0.4.0", "serde@0.8.0", "serde@1.0.0"]
+        );
+        assert!(get_crates(&"list:serde@2.0.0".parse().unwrap(), &db, &config).is_err());
+
+        // The stored scores rank the crates
+        assert_eq!(RegistryList::scores(&db).unwrap()["log"], 2);
+        assert_eq!(versions("top-1"), vec!["log@0.4.0"]);
+
+        // Yanked versions and pre-releases are never the head of a line
+        assert_eq!(
+            versions("semver-heads:list:serde,log"),
+            vec!["log@0.4.0", "serde@0.8.0", "serde@0.9.5", "serde@1.0.0"]
+        );
+    }
+}
@@ -378,6 +378,141 @@ fn migrations() -> Vec<(&'static str, MigrationKind)> {
         MigrationKind::SQL("alter table agents add column latest_work_for text;"),
     ));
 
+    migrations.push((
+        "add_experiment_fields_requested_toolchains",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN requested_toolchain_start TEXT;
+            ALTER TABLE experiments ADD COLUMN requested_toolchain_end TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_target",
+        MigrationKind::SQL("ALTER TABLE experiments ADD COLUMN target TEXT;"),
+    ));
+
+    migrations.push((
+        "add_experiment_field_patches",
+        MigrationKind::SQL("ALTER TABLE experiments ADD COLUMN patches TEXT;"),
+    ));
+
+    migrations.push((
+        "add_experiment_field_crate_select",
+        MigrationKind::SQL("ALTER TABLE experiments ADD COLUMN crate_select TEXT;"),
+    ));
+
+    migrations.push((
+        "add_crates_field_score",
+        MigrationKind::SQL("ALTER TABLE crates ADD COLUMN score INTEGER;"),
+    ));
+
+    migrations.push((
+        "create_registry_metadata_tables",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE registry_tags (
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                tag TEXT NOT NULL
+            );
+
+            CREATE INDEX registry_tags__kind_tag ON registry_tags (kind, tag);
+
+            CREATE TABLE registry_versions (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                published_on TEXT NOT NULL,
+
+                PRIMARY KEY (name, version) ON CONFLICT REPLACE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_member_results_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE member_results (
+                experiment TEXT NOT NULL,
+                crate TEXT NOT NULL,
+                toolchain TEXT NOT NULL,
+                member TEXT NOT NULL,
+                result TEXT NOT NULL,
+
+                PRIMARY KEY (experiment, crate, toolchain, member) ON CONFLICT REPLACE,
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_lockfile",
+        MigrationKind::SQL(
+            "ALTER TABLE experiments ADD COLUMN lockfile TEXT NOT NULL DEFAULT 'keep';",
+        ),
+    ));
+
+    migrations.push((
+        "create_resolved_dependencies_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE resolved_dependencies (
+                experiment TEXT NOT NULL,
+                crate TEXT NOT NULL,
+                toolchain TEXT NOT NULL,
+                dependencies BLOB NOT NULL,
+
+                PRIMARY KEY (experiment, crate, toolchain) ON CONFLICT REPLACE,
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_host_results_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE host_results (
+                experiment TEXT NOT NULL,
+                crate TEXT NOT NULL,
+                toolchain TEXT NOT NULL,
+                result TEXT NOT NULL,
+
+                PRIMARY KEY (experiment, crate, toolchain) ON CONFLICT REPLACE,
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_registry_dependencies_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE registry_dependencies (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                dependency TEXT NOT NULL,
+
+                PRIMARY KEY (name, dependency) ON CONFLICT REPLACE
+            );
+
+            CREATE INDEX registry_dependencies__dependency
+                ON registry_dependencies (dependency);
+
+            CREATE TABLE registry_versions (
+                name TEXT PRIMARY KEY ON CONFLICT REPLACE,
+                version TEXT NOT NULL
+            );
+            ",
+        ),
+    ));
+
     migrations
 }
 
@@ -1,10 +1,11 @@
 use crate::crates::Crate;
 use crate::db::{Database, QueryUtils};
 use crate::prelude::*;
+use crate::report::Comparison;
 use crate::results::TestResult;
-use crate::toolchain::Toolchain;
+use crate::toolchain::{CratePatch, Toolchain};
 use crate::utils;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use rusqlite::Row;
 use std::collections::HashSet;
 use std::fmt;
@@ -30,6 +31,7 @@ string_enum!(pub enum Mode {
     Clippy => "clippy",
     Rustdoc => "rustdoc",
     UnstableFeatures => "unstable-features",
+    Resolve => "resolve",
 });
 
 string_enum!(pub enum CapLints {
@@ -39,6 +41,32 @@ string_enum!(pub enum CapLints {
     Forbid => "forbid",
 });
 
+// How the `Cargo.lock` shipped by a crate is handled before building it
+string_enum!(pub enum Lockfile {
+    Keep => "keep",
+    Regenerate => "regenerate",
+    MinimalVersions => "minimal-versions",
+    DirectMinimalVersions => "direct-minimal-versions",
+});
+
+impl Lockfile {
+    /// The unstable Cargo flag resolving the dependencies of the crates to the lowest versions
+    /// their requirements allow, used when building with the end toolchain.
+    pub(crate) fn minimal_versions_flag(self) -> Option<&'static str> {
+        match self {
+            Lockfile::Keep | Lockfile::Regenerate => None,
+            Lockfile::MinimalVersions => Some("minimal-versions"),
+            Lockfile::DirectMinimalVersions => Some("direct-minimal-versions"),
+        }
+    }
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Lockfile::Keep
+    }
+}
+
 const SMALL_RANDOM_COUNT: u32 = 20;
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -49,8 +77,45 @@ pub enum CrateSelect {
     Top(u32),
     Local,
     Dummy,
-    Random(u32),
+    /// GitHub repositories without a lockfile.
+    GitHubUnlocked,
+    /// Random crates, picked with the given seed if any.
+    Random {
+        count: u32,
+        seed: Option<u64>,
+    },
+    /// Random crates from each tenth of the registry list, sorted by popularity.
+    Stratified {
+        per_decile: u32,
+        seed: Option<u64>,
+    },
     List(HashSet<String>),
+    /// Registry crates depending on `krate`, through at most `depth` levels of dependencies.
+    ReverseDeps {
+        krate: String,
+        depth: Option<u32>,
+    },
+    /// Crates of a previous experiment, keeping only the ones whose comparison or result on the
+    /// last toolchain matches one of the filters (or all of them if there are no filters).
+    FromExperiment {
+        name: String,
+        filters: Vec<String>,
+    },
+    /// The latest version of every semver-compatible line of the registry crates in the
+    /// selection, like the latest `nom` 5, 6 and 7.
+    SemverHeads(Box<CrateSelect>),
+    /// Registry crates in a category or any of its subcategories.
+    Category(String),
+    /// Registry crates with a keyword.
+    Keyword(String),
+    /// Registry crates whose selected version was published on or after the date.
+    PublishedSince(NaiveDate),
+    /// Crates selected by either side (`a + b`).
+    Union(Box<CrateSelect>, Box<CrateSelect>),
+    /// Crates selected by both sides (`a & b`).
+    Intersection(Box<CrateSelect>, Box<CrateSelect>),
+    /// Crates selected by the left side but not by the right one (`a - b`).
+    Difference(Box<CrateSelect>, Box<CrateSelect>),
 }
 
 from_into_string!(CrateSelect);
@@ -58,17 +123,119 @@ from_into_string!(CrateSelect);
 impl FromStr for CrateSelect {
     type Err = failure::Error;
 
+    /// Operators must be surrounded by spaces, and are applied from left to right.
     fn from_str(s: &str) -> failure::Fallible<Self> {
+        let mut tokens = s.split_whitespace();
+        let mut select = match tokens.next() {
+            Some(first) => CrateSelect::parse_operand(first)?,
+            None => bail!("empty CrateSelect"),
+        };
+
+        while let Some(operator) = tokens.next() {
+            let operand = match tokens.next() {
+                Some(operand) => Box::new(CrateSelect::parse_operand(operand)?),
+                None => bail!("missing crate selection after `{}`", operator),
+            };
+            let left = Box::new(select);
+            select = match operator {
+                "+" => CrateSelect::Union(left, operand),
+                "&" => CrateSelect::Intersection(left, operand),
+                "-" => CrateSelect::Difference(left, operand),
+                other => bail!("invalid crate selection operator: {}", other),
+            };
+        }
+
+        Ok(select)
+    }
+}
+
+/// Parse the `{n}[:seed={seed}]` suffix of random selections.
+fn parse_seeded(s: &str) -> Fallible<(u32, Option<u64>)> {
+    let mut parts = s.splitn(2, ':');
+    let count = parts.next().unwrap().parse()?;
+    let seed = match parts.next() {
+        Some(seed) => match seed.strip_prefix("seed=") {
+            Some(seed) => Some(seed.parse()?),
+            None => bail!("invalid random selection option: {}", seed),
+        },
+        None => None,
+    };
+
+    Ok((count, seed))
+}
+
+/// Validate the category or keyword of a selection, which are always lowercase on crates.io.
+fn parse_tag(select: &str, tag: &str) -> Fallible<String> {
+    if tag.is_empty() {
+        bail!("missing value in CrateSelect: {}", select);
+    }
+    Ok(tag.to_lowercase())
+}
+
+fn write_seed(f: &mut fmt::Formatter, seed: Option<u64>) -> fmt::Result {
+    if let Some(seed) = seed {
+        write!(f, ":seed={}", seed)?;
+    }
+    Ok(())
+}
+
+impl CrateSelect {
+    /// Pick a seed for every random selection without one, so that the selection can be
+    /// reproduced later.
+    pub fn with_seeds(self) -> Self {
+        let pick = |seed: Option<u64>| Some(seed.unwrap_or_else(rand::random));
+        match self {
+            CrateSelect::Random { count, seed } => CrateSelect::Random {
+                count,
+                seed: pick(seed),
+            },
+            CrateSelect::Stratified { per_decile, seed } => CrateSelect::Stratified {
+                per_decile,
+                seed: pick(seed),
+            },
+            CrateSelect::SemverHeads(inner) => {
+                CrateSelect::SemverHeads(Box::new(inner.with_seeds()))
+            }
+            CrateSelect::Union(left, right) => {
+                CrateSelect::Union(Box::new(left.with_seeds()), Box::new(right.with_seeds()))
+            }
+            CrateSelect::Intersection(left, right) => {
+                CrateSelect::Intersection(Box::new(left.with_seeds()), Box::new(right.with_seeds()))
+            }
+            CrateSelect::Difference(left, right) => {
+                CrateSelect::Difference(Box::new(left.with_seeds()), Box::new(right.with_seeds()))
+            }
+            other => other,
+        }
+    }
+
+    fn from_newline_separated_list(s: &str) -> Fallible<CrateSelect> {
+        if s.contains(',') {
+            bail!("Crate identifiers must not contain a comma");
+        }
+
+        let crates = s.split_whitespace().map(|s| s.to_owned()).collect();
+        Ok(CrateSelect::List(crates))
+    }
+
+    fn parse_operand(s: &str) -> Fallible<Self> {
         let ret = match s {
             s if s.starts_with("top-") => {
                 let n: u32 = s["top-".len()..].parse()?;
                 CrateSelect::Top(n)
             }
 
-            "small-random" => CrateSelect::Random(SMALL_RANDOM_COUNT),
+            "small-random" => CrateSelect::Random {
+                count: SMALL_RANDOM_COUNT,
+                seed: None,
+            },
             s if s.starts_with("random-") => {
-                let n: u32 = s["random-".len()..].parse()?;
-                CrateSelect::Random(n)
+                let (count, seed) = parse_seeded(&s["random-".len()..])?;
+                CrateSelect::Random { count, seed }
+            }
+            s if s.starts_with("stratified-") => {
+                let (per_decile, seed) = parse_seeded(&s["stratified-".len()..])?;
+                CrateSelect::Stratified { per_decile, seed }
             }
 
             s if s.starts_with("list:") => {
@@ -80,10 +247,68 @@ impl FromStr for CrateSelect {
                 CrateSelect::List(list)
             }
 
+            s if s.starts_with("from:") => {
+                let value = &s["from:".len()..];
+                let (name, filters) = match value.rfind(':') {
+                    Some(idx) => (
+                        &value[..idx],
+                        value[idx + 1..].split(',').map(|s| s.to_owned()).collect(),
+                    ),
+                    None => (value, Vec::new()),
+                };
+                if name.is_empty() {
+                    bail!("missing experiment name in CrateSelect: {}", s);
+                }
+                for filter in &filters {
+                    if filter.parse::<TestResult>().is_err()
+                        && filter.parse::<Comparison>().is_err()
+                    {
+                        bail!("invalid result filter: {}", filter);
+                    }
+                }
+
+                CrateSelect::FromExperiment {
+                    name: name.to_string(),
+                    filters,
+                }
+            }
+
+            s if s.starts_with("rdeps:") => {
+                let mut parts = s["rdeps:".len()..].splitn(2, ':');
+                let krate = parts.next().unwrap();
+                if krate.is_empty() {
+                    bail!("missing crate name in CrateSelect: {}", s);
+                }
+                let depth = parts.next().map(|depth| depth.parse()).transpose()?;
+
+                CrateSelect::ReverseDeps {
+                    krate: krate.to_string(),
+                    depth,
+                }
+            }
+
+            s if s.starts_with("semver-heads:") => CrateSelect::SemverHeads(Box::new(
+                CrateSelect::parse_operand(&s["semver-heads:".len()..])?,
+            )),
+            s if s.starts_with("category:") => {
+                CrateSelect::Category(parse_tag(s, &s["category:".len()..])?)
+            }
+            s if s.starts_with("keyword:") => {
+                CrateSelect::Keyword(parse_tag(s, &s["keyword:".len()..])?)
+            }
+            s if s.starts_with("published-since:") => {
+                let date = &s["published-since:".len()..];
+                CrateSelect::PublishedSince(
+                    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                        .with_context(|_| format!("invalid date in CrateSelect: {}", s))?,
+                )
+            }
+
             "full" => CrateSelect::Full,
             "demo" => CrateSelect::Demo,
             "local" => CrateSelect::Local,
             "dummy" => CrateSelect::Dummy,
+            "github-unlocked" => CrateSelect::GitHubUnlocked,
             s => bail!("invalid CrateSelect: {}", s),
         };
 
@@ -99,7 +324,15 @@ impl fmt::Display for CrateSelect {
             CrateSelect::Dummy => write!(f, "dummy"),
             CrateSelect::Top(n) => write!(f, "top-{}", n),
             CrateSelect::Local => write!(f, "local"),
-            CrateSelect::Random(n) => write!(f, "random-{}", n),
+            CrateSelect::GitHubUnlocked => write!(f, "github-unlocked"),
+            CrateSelect::Random { count, seed } => {
+                write!(f, "random-{}", count)?;
+                write_seed(f, *seed)
+            }
+            CrateSelect::Stratified { per_decile, seed } => {
+                write!(f, "stratified-{}", per_decile)?;
+                write_seed(f, *seed)
+            }
             CrateSelect::List(list) => {
                 let mut first = true;
                 write!(f, "list:")?;
@@ -115,21 +348,33 @@ impl fmt::Display for CrateSelect {
 
                 Ok(())
             }
+            CrateSelect::ReverseDeps { krate, depth } => {
+                write!(f, "rdeps:{}", krate)?;
+                if let Some(depth) = depth {
+                    write!(f, ":{}", depth)?;
+                }
+                Ok(())
+            }
+            CrateSelect::FromExperiment { name, filters } => {
+                write!(f, "from:{}", name)?;
+                if !filters.is_empty() {
+                    write!(f, ":{}", filters.join(","))?;
+                }
+                Ok(())
+            }
+            CrateSelect::SemverHeads(inner) => write!(f, "semver-heads:{}", inner),
+            CrateSelect::Category(category) => write!(f, "category:{}", category),
+            CrateSelect::Keyword(keyword) => write!(f, "keyword:{}", keyword),
+            CrateSelect::PublishedSince(date) => {
+                write!(f, "published-since:{}", date.format("%Y-%m-%d"))
+            }
+            CrateSelect::Union(left, right) => write!(f, "{} + {}", left, right),
+            CrateSelect::Intersection(left, right) => write!(f, "{} & {}", left, right),
+            CrateSelect::Difference(left, right) => write!(f, "{} - {}", left, right),
         }
     }
 }
 
-impl CrateSelect {
-    fn from_newline_separated_list(s: &str) -> Fallible<CrateSelect> {
-        if s.contains(',') {
-            bail!("Crate identifiers must not contain a comma");
-        }
-
-        let crates = s.split_whitespace().map(|s| s.to_owned()).collect();
-        Ok(CrateSelect::List(crates))
-    }
-}
-
 /// Either a `CrateSelect` or `Url` pointing to a list of crates.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum DeferredCrateSelect {
@@ -246,6 +491,10 @@ pub struct GitHubIssue {
 pub struct Experiment {
     pub name: String,
     pub toolchains: [Toolchain; 2],
+    /// The toolchains as originally requested, if they were floating channels pinned to a
+    /// specific release when the experiment was created.
+    #[serde(default)]
+    pub requested_toolchains: [Option<Toolchain>; 2],
     pub mode: Mode,
     pub cap_lints: CapLints,
     pub priority: i32,
@@ -258,6 +507,20 @@ pub struct Experiment {
     pub report_url: Option<String>,
     pub ignore_blacklist: bool,
     pub requirement: Option<String>,
+    /// The target triple to build for instead of the host, if any.
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Patches applied to every crate, regardless of the toolchain.
+    #[serde(default)]
+    pub patches: Vec<CratePatch>,
+    /// The crate selection used to create the experiment, including the seeds of its random
+    /// selections. Missing for experiments created before it was recorded.
+    #[serde(default)]
+    pub crate_select: Option<CrateSelect>,
+    /// Whether crates are built with the dependencies pinned by their lockfile, or with freshly
+    /// resolved ones.
+    #[serde(default)]
+    pub lockfile: Lockfile,
 }
 
 impl Experiment {
@@ -668,6 +931,8 @@ struct ExperimentDBRecord {
     cap_lints: String,
     toolchain_start: String,
     toolchain_end: String,
+    requested_toolchain_start: Option<String>,
+    requested_toolchain_end: Option<String>,
     priority: i32,
     created_at: DateTime<Utc>,
     started_at: Option<DateTime<Utc>>,
@@ -680,6 +945,10 @@ struct ExperimentDBRecord {
     report_url: Option<String>,
     ignore_blacklist: bool,
     requirement: Option<String>,
+    target: Option<String>,
+    patches: Option<String>,
+    crate_select: Option<String>,
+    lockfile: String,
 }
 
 impl ExperimentDBRecord {
@@ -690,6 +959,8 @@ impl ExperimentDBRecord {
             cap_lints: row.get("cap_lints")?,
             toolchain_start: row.get("toolchain_start")?,
             toolchain_end: row.get("toolchain_end")?,
+            requested_toolchain_start: row.get("requested_toolchain_start")?,
+            requested_toolchain_end: row.get("requested_toolchain_end")?,
             priority: row.get("priority")?,
             created_at: row.get("created_at")?,
             started_at: row.get("started_at")?,
@@ -702,6 +973,10 @@ impl ExperimentDBRecord {
             report_url: row.get("report_url")?,
             ignore_blacklist: row.get("ignore_blacklist")?,
             requirement: row.get("requirement")?,
+            target: row.get("target")?,
+            patches: row.get("patches")?,
+            crate_select: row.get("crate_select")?,
+            lockfile: row.get("lockfile")?,
         })
     }
 
@@ -709,6 +984,14 @@ impl ExperimentDBRecord {
         Ok(Experiment {
             name: self.name,
             toolchains: [self.toolchain_start.parse()?, self.toolchain_end.parse()?],
+            requested_toolchains: [
+                self.requested_toolchain_start
+                    .map(|tc| tc.parse())
+                    .transpose()?,
+                self.requested_toolchain_end
+                    .map(|tc| tc.parse())
+                    .transpose()?,
+            ],
             cap_lints: self.cap_lints.parse()?,
             mode: self.mode.parse()?,
             priority: self.priority,
@@ -737,6 +1020,16 @@ impl ExperimentDBRecord {
             report_url: self.report_url,
             ignore_blacklist: self.ignore_blacklist,
             requirement: self.requirement,
+            target: self.target,
+            patches: match self.patches {
+                Some(patches) => serde_json::from_str::<Vec<String>>(&patches)?
+                    .iter()
+                    .map(|patch| patch.parse())
+                    .collect::<Result<_, _>>()?,
+                None => Vec::new(),
+            },
+            crate_select: self.crate_select.map(|s| s.parse()).transpose()?,
+            lockfile: self.lockfile.parse()?,
         })
     }
 }
@@ -752,6 +1045,7 @@ mod tests {
     use crate::db::Database;
     use crate::server::agents::Agents;
     use crate::server::tokens::Tokens;
+    use chrono::NaiveDate;
     use std::collections::HashSet;
     use std::str::FromStr;
 
@@ -765,16 +1059,102 @@ mod tests {
         let suite = vec![
             ("demo", CrateSelect::Demo),
             ("top-25", CrateSelect::Top(25)),
-            ("random-87", CrateSelect::Random(87)),
-            ("small-random", CrateSelect::Random(20)),
+            ("github-unlocked", CrateSelect::GitHubUnlocked),
+            (
+                "random-87",
+                CrateSelect::Random {
+                    count: 87,
+                    seed: None,
+                },
+            ),
+            (
+                "random-87:seed=42",
+                CrateSelect::Random {
+                    count: 87,
+                    seed: Some(42),
+                },
+            ),
+            (
+                "small-random",
+                CrateSelect::Random {
+                    count: 20,
+                    seed: None,
+                },
+            ),
+            (
+                "stratified-10:seed=7",
+                CrateSelect::Stratified {
+                    per_decile: 10,
+                    seed: Some(7),
+                },
+            ),
             (
                 "list:brson/hello-rs,lazy_static",
                 CrateSelect::List(demo_crates.clone()),
             ),
+            (
+                "rdeps:serde",
+                CrateSelect::ReverseDeps {
+                    krate: "serde".into(),
+                    depth: None,
+                },
+            ),
+            (
+                "rdeps:serde:2",
+                CrateSelect::ReverseDeps {
+                    krate: "serde".into(),
+                    depth: Some(2),
+                },
+            ),
+            (
+                "from:pr-12345",
+                CrateSelect::FromExperiment {
+                    name: "pr-12345".into(),
+                    filters: vec![],
+                },
+            ),
+            (
+                "top-1000 - list:foo & demo + local",
+                CrateSelect::Union(
+                    Box::new(CrateSelect::Intersection(
+                        Box::new(CrateSelect::Difference(
+                            Box::new(CrateSelect::Top(1000)),
+                            Box::new(CrateSelect::List(
+                                vec!["foo".to_string()].into_iter().collect(),
+                            )),
+                        )),
+                        Box::new(CrateSelect::Demo),
+                    )),
+                    Box::new(CrateSelect::Local),
+                ),
+            ),
+            (
+                "category:science::robotics",
+                CrateSelect::Category("science::robotics".into()),
+            ),
+            ("keyword:async", CrateSelect::Keyword("async".into())),
+            (
+                "semver-heads:top-100",
+                CrateSelect::SemverHeads(Box::new(CrateSelect::Top(100))),
+            ),
+            (
+                "published-since:2020-01-31",
+                CrateSelect::PublishedSince(NaiveDate::from_ymd(2020, 1, 31)),
+            ),
+            (
+                "from:beta-1.90:regressed,build-fail",
+                CrateSelect::FromExperiment {
+                    name: "beta-1.90".into(),
+                    filters: vec!["regressed".into(), "build-fail".into()],
+                },
+            ),
         ];
 
         for (s, output) in suite.into_iter() {
             assert_eq!(CrateSelect::from_str(s).unwrap(), output);
+            if !s.starts_with("list:") && s != "small-random" {
+                assert_eq!(output.to_string(), s);
+            }
             assert_eq!(
                 DeferredCrateSelect::from_str(s).unwrap(),
                 DeferredCrateSelect::Direct(output),
@@ -800,6 +1180,46 @@ mod tests {
         .unwrap();
 
         assert_eq!(list, CrateSelect::List(demo_crates));
+
+        assert!(CrateSelect::from_str("rdeps:").is_err());
+        assert!(CrateSelect::from_str("keyword:").is_err());
+        assert!(CrateSelect::from_str("semver-heads:").is_err());
+        assert!(CrateSelect::from_str("published-since:2020-13-01").is_err());
+        assert_eq!(
+            CrateSelect::from_str("category:Science").unwrap(),
+            CrateSelect::Category("science".into())
+        );
+        assert!(CrateSelect::from_str("rdeps:serde:deep").is_err());
+        assert!(CrateSelect::from_str("from::regressed").is_err());
+        assert!(CrateSelect::from_str("from:pr-12345:flaky").is_err());
+        assert!(CrateSelect::from_str("").is_err());
+        assert!(CrateSelect::from_str("top-10 +").is_err());
+        assert!(CrateSelect::from_str("top-10 | demo").is_err());
+        assert!(CrateSelect::from_str("random-10:42").is_err());
+
+        // Seeds are only picked for the random selections without one
+        match CrateSelect::from_str("random-10:seed=1 + stratified-5 - demo")
+            .unwrap()
+            .with_seeds()
+        {
+            CrateSelect::Difference(left, _) => match *left {
+                CrateSelect::Union(random, stratified) => {
+                    assert_eq!(
+                        *random,
+                        CrateSelect::Random {
+                            count: 10,
+                            seed: Some(1)
+                        }
+                    );
+                    assert!(matches!(
+                        *stratified,
+                        CrateSelect::Stratified { seed: Some(_), .. }
+                    ));
+                }
+                other => panic!("unexpected selection: {}", other),
+            },
+            other => panic!("unexpected selection: {}", other),
+        }
     }
 
     #[test]
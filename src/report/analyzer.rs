@@ -1,11 +1,14 @@
-use super::{Comparison, CrateResult, RawTestResults};
+use super::{Comparison, CrateResult, Popularity, RawTestResults};
 use crate::crates::Crate;
 use crate::results::{
     FailureReason,
     TestResult::{self, BuildFail},
 };
 use indexmap::IndexMap;
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
+
+/// Number of root causes of the regressions highlighted at the top of the report.
+const TOP_ROOT_CAUSES: usize = 20;
 
 pub enum ToolchainSelect {
     Start,
@@ -27,13 +30,71 @@ pub enum ReportCrates {
     },
 }
 
+/// A crate that failed on its own, and the crates failing because they depend on it.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[derive(Clone)]
+pub struct RootCause {
+    pub krate: Crate,
+    /// Crates of this run it breaks, including itself when it was tested.
+    pub broken: u32,
+    /// Crates of the registry depending on it, when it's a registry crate.
+    pub dependents: Option<i64>,
+}
+
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub struct TestResults {
     pub categories: IndexMap<Comparison, ReportCrates>,
     pub info: IndexMap<Comparison, u32>,
+    /// The root causes of the regressions breaking the most crates.
+    pub root_causes: Vec<RootCause>,
+    /// The crates regressed only on the target of the experiment.
+    pub target_regressions: Vec<CrateResult>,
 }
 
-fn analyze_detailed(toolchain: usize, crates: Vec<CrateResult>) -> ReportCrates {
+/// Rank the root causes by the number of crates they break in this run, then by the number of
+/// crates depending on them in the whole ecosystem, and finally by their popularity score.
+fn rank_root_causes(
+    tree: &IndexMap<Crate, Vec<CrateResult>>,
+    root: &[CrateResult],
+    popularity: &Popularity,
+) -> Vec<RootCause> {
+    let mut broken = IndexMap::new();
+    for (dep, crates) in tree {
+        *broken.entry(dep).or_insert(0) += crates.len() as u32;
+    }
+    for krate in root {
+        *broken.entry(&krate.krate).or_insert(0) += 1;
+    }
+
+    let mut causes = broken
+        .into_iter()
+        .map(|(krate, broken)| RootCause {
+            dependents: match krate {
+                Crate::Registry(details) => popularity.dependents.get(&details.name).cloned(),
+                _ => None,
+            },
+            krate: krate.clone(),
+            broken,
+        })
+        .collect::<Vec<_>>();
+    let score = |krate: &Crate| match krate {
+        Crate::Registry(details) => popularity.scores.get(&details.name).cloned(),
+        _ => None,
+    };
+    causes.sort_by(|a, b| {
+        b.broken
+            .cmp(&a.broken)
+            .then_with(|| b.dependents.cmp(&a.dependents))
+            .then_with(|| score(&b.krate).cmp(&score(&a.krate)))
+    });
+    causes
+}
+
+fn analyze_detailed(
+    toolchain: usize,
+    crates: Vec<CrateResult>,
+    popularity: &Popularity,
+) -> (ReportCrates, Vec<RootCause>) {
     let mut tree = IndexMap::new();
     let mut results = IndexMap::new();
 
@@ -52,6 +113,15 @@ fn analyze_detailed(toolchain: usize, crates: Vec<CrateResult>) -> ReportCrates
         }
     }
 
+    // List the dependencies breaking the most crates first
+    let causes = rank_root_causes(&tree, &root, popularity);
+    let ranks = causes
+        .iter()
+        .enumerate()
+        .map(|(rank, cause)| (cause.krate.clone(), rank))
+        .collect::<HashMap<_, _>>();
+    tree.sort_by(|a, _, b, _| ranks[a].cmp(&ranks[b]));
+
     for krate in root {
         // record results only for root crates
         if let BuildFail(FailureReason::CompilerError(codes)) =
@@ -71,10 +141,17 @@ fn analyze_detailed(toolchain: usize, crates: Vec<CrateResult>) -> ReportCrates
         }
     }
 
-    ReportCrates::Complete { tree, results }
+    (ReportCrates::Complete { tree, results }, causes)
 }
 
-pub fn analyze_report(test: RawTestResults) -> TestResults {
+pub fn analyze_report(test: RawTestResults, popularity: &Popularity) -> TestResults {
+    let target_regressions = test
+        .crates
+        .iter()
+        .filter(|krate| krate.target_only)
+        .cloned()
+        .collect();
+
     let mut comparison = IndexMap::new();
     for krate in test.crates {
         comparison
@@ -89,17 +166,28 @@ pub fn analyze_report(test: RawTestResults) -> TestResults {
         .collect::<IndexMap<_, _>>();
 
     let mut categories = IndexMap::new();
+    let mut root_causes = Vec::new();
     for (cat, crates) in comparison {
         if let ReportConfig::Complete(toolchain) = cat.report_config() {
             // variants in an enum are numbered following an
             // increasing sequence starting from 0
-            categories.insert(cat, analyze_detailed(toolchain as usize, crates));
+            let (detailed, mut causes) = analyze_detailed(toolchain as usize, crates, popularity);
+            if cat == Comparison::Regressed {
+                causes.truncate(TOP_ROOT_CAUSES);
+                root_causes = causes;
+            }
+            categories.insert(cat, detailed);
         } else {
             categories.insert(cat, ReportCrates::Plain(crates));
         }
     }
 
-    TestResults { categories, info }
+    TestResults {
+        categories,
+        info,
+        root_causes,
+        target_regressions,
+    }
 }
 
 #[cfg(test)]
@@ -107,7 +195,7 @@ mod tests {
     use super::*;
     use crate::config::Config;
     use crate::crates::{Crate, RegistryCrate};
-    use crate::experiments::{CapLints, Experiment, Mode, Status};
+    use crate::experiments::{CapLints, Experiment, Lockfile, Mode, Status};
     use crate::report::{generate_report, Comparison};
     use crate::results::{DummyDB, FailureReason::*};
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
@@ -154,6 +242,7 @@ mod tests {
         let ex = Experiment {
             name: "foo".to_string(),
             toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
+            requested_toolchains: [None, None],
             mode: Mode::BuildAndTest,
             cap_lints: CapLints::Forbid,
             priority: 0,
@@ -166,6 +255,10 @@ mod tests {
             report_url: None,
             ignore_blacklist: false,
             requirement: None,
+            target: None,
+            patches: Vec::new(),
+            crate_select: None,
+            lockfile: Lockfile::Keep,
         };
 
         let crates = record_crates! {db, ex,
@@ -192,7 +285,19 @@ mod tests {
                 }
             })
             .collect::<IndexMap<_, _>>();
-        let analyzed = analyze_report(raw);
+        let mut popularity = Popularity::default();
+        popularity.dependents.insert("unknown".to_string(), 10);
+        let analyzed = analyze_report(raw, &popularity);
+
+        // The dependencies breaking the most crates are listed first
+        if let ReportCrates::Complete { tree, .. } = &analyzed.categories[&Comparison::Regressed] {
+            assert_eq!(
+                tree.keys().collect::<Vec<_>>(),
+                vec![&reg!("ce-1"), &reg!("unknown"), &reg!("ce-2")]
+            );
+        } else {
+            panic!("regressions should be analyzed in detail");
+        }
 
         let mut info = IndexMap::new();
         info.insert(Comparison::Regressed, 5);
@@ -253,9 +358,59 @@ mod tests {
         categories.insert(Comparison::Fixed, fixed);
         categories.insert(Comparison::SameTestPass, test_pass);
 
-        let expected = TestResults { categories, info };
+        let root_causes = vec![
+            RootCause {
+                krate: reg!("ce-1"),
+                broken: 3,
+                dependents: None,
+            },
+            RootCause {
+                krate: reg!("unknown"),
+                broken: 2,
+                dependents: Some(10),
+            },
+            RootCause {
+                krate: reg!("ce-2"),
+                broken: 2,
+                dependents: None,
+            },
+        ];
+
+        let expected = TestResults {
+            categories,
+            info,
+            root_causes,
+            target_regressions: Vec::new(),
+        };
         assert_eq!(expected, analyzed);
 
         Ok(())
     }
+
+    #[test]
+    fn test_rank_root_causes() {
+        let reg = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "0.0.1".into(),
+            })
+        };
+        let mut tree = IndexMap::new();
+        for name in &["a", "b", "c"] {
+            tree.insert(reg(name), Vec::new());
+        }
+
+        // The score breaks the ties between crates with as many dependents
+        let mut popularity = Popularity::default();
+        popularity.dependents.insert("a".to_string(), 5);
+        popularity.dependents.insert("b".to_string(), 5);
+        popularity.scores.insert("a".to_string(), 1);
+        popularity.scores.insert("b".to_string(), 100);
+        popularity.scores.insert("c".to_string(), 1000);
+        let ranked = rank_root_causes(&tree, &[], &popularity)
+            .into_iter()
+            .map(|cause| cause.krate)
+            .collect::<Vec<_>>();
+        assert_eq!(ranked, vec![reg("b"), reg("a"), reg("c")]);
+    }
 }
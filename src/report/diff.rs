@@ -0,0 +1,251 @@
+use crate::config::Config;
+use crate::crates::{compatible_line, Crate};
+use crate::db::Database;
+use crate::experiments::{Experiment, Status};
+use crate::prelude::*;
+use crate::report::{compare, crate_to_name, crate_to_url, Comparison, RawTestResults};
+use crate::results::{DatabaseDB, TestResult};
+use crate::utils;
+use indexmap::IndexMap;
+
+/// A crate of an experiment along with its results with each toolchain.
+pub type CrateResults = (Crate, [Option<TestResult>; 2]);
+
+/// A crate tested by both experiments, or regressed in one of them.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[derive(Serialize, Clone)]
+pub struct CrateDiff {
+    pub name: String,
+    pub url: String,
+    /// The outcome in each experiment, `None` if the experiment didn't test the crate.
+    pub res: [Option<Comparison>; 2],
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+#[derive(Serialize, Default)]
+pub struct ExperimentsDiff {
+    /// Crates regressed in the second experiment but not in the first one.
+    pub new_regressions: Vec<CrateDiff>,
+    /// Crates regressed in the first experiment but not in the second one.
+    pub gone_regressions: Vec<CrateDiff>,
+    /// Crates tested by both experiments with any other change in their outcome.
+    pub changed: Vec<CrateDiff>,
+}
+
+/// Compare the results of two experiments crate by crate. Crates are matched regardless of their
+/// commit or of the exact version within a semver-compatible line, so that two runs picking up
+/// different releases of a crate still line up.
+pub fn diff_experiments(config: &Config, experiments: [&[CrateResults]; 2]) -> ExperimentsDiff {
+    let mut crates: IndexMap<String, CrateDiff> = IndexMap::new();
+    for (idx, ex_crates) in experiments.iter().enumerate() {
+        for (krate, results) in ex_crates.iter() {
+            let res = compare(config, krate, results[0].as_ref(), results[1].as_ref());
+
+            let diff = crates.entry(diff_key(krate)).or_insert_with(|| CrateDiff {
+                name: String::new(),
+                url: String::new(),
+                res: [None, None],
+            });
+            // Show the crate as tested by the latest experiment
+            diff.name = crate_to_name(krate);
+            diff.url = crate_to_url(krate, config);
+            diff.res[idx] = Some(res);
+        }
+    }
+
+    let mut diff = ExperimentsDiff::default();
+    let regressed = Some(Comparison::Regressed);
+    for (_, krate) in crates {
+        match krate.res {
+            [start, end] if start != regressed && end == regressed => {
+                diff.new_regressions.push(krate)
+            }
+            [start, end] if start == regressed && end != regressed => {
+                diff.gone_regressions.push(krate)
+            }
+            [Some(start), Some(end)] if start != end => diff.changed.push(krate),
+            _ => {}
+        }
+    }
+
+    for list in &mut [
+        &mut diff.new_regressions,
+        &mut diff.gone_regressions,
+        &mut diff.changed,
+    ] {
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    diff
+}
+
+/// Load an experiment to compare along with the results of its crates. The results of completed
+/// experiments are purged from the database over time, so they are loaded from the published
+/// report once they're gone.
+pub fn load_experiment(
+    db: &Database,
+    name: &str,
+) -> Fallible<Option<(Experiment, Vec<CrateResults>)>> {
+    let ex = match Experiment::get(db, name)? {
+        Some(ex) => ex,
+        None => return Ok(None),
+    };
+
+    let (completed, all) = ex.raw_progress(db)?;
+    if ex.status == Status::Completed && completed < all {
+        let results = load_published_results(&ex).with_context(|_| {
+            format!(
+                "the results of {} were purged from the database and its report couldn't be loaded",
+                ex.name
+            )
+        })?;
+        return Ok(Some((ex, results)));
+    }
+
+    let mut results = DatabaseDB::new(db).load_all_results(&ex)?;
+    let crates = ex
+        .get_crates(db)?
+        .into_iter()
+        .map(|krate| {
+            let res = results.remove(&krate.id()).unwrap_or([None, None]);
+            (krate, res)
+        })
+        .collect();
+    Ok(Some((ex, crates)))
+}
+
+/// Results of the crates of an experiment, as published in the `results.json` of its report.
+fn load_published_results(ex: &Experiment) -> Fallible<Vec<CrateResults>> {
+    let report_url = ex
+        .report_url
+        .as_ref()
+        .ok_or_else(|| err_msg("no report was published"))?;
+    let url = format!("{}results.json", report_url.trim_end_matches("index.html"));
+    let raw: RawTestResults = utils::http::get_sync(&url)?.json()?;
+
+    Ok(raw
+        .crates
+        .into_iter()
+        .map(|krate| {
+            let [start, end] = krate.runs;
+            (krate.krate, [start.map(|r| r.res), end.map(|r| r.res)])
+        })
+        .collect())
+}
+
+/// Identify a crate without its commit, and only by the semver-compatible line of its version as
+/// experiments can test several lines of the same crate.
+fn diff_key(krate: &Crate) -> String {
+    let mut krate = krate.clone();
+    match krate {
+        Crate::Registry(ref mut details) => details.version = compatible_line(&details.version),
+        Crate::AltRegistry(ref mut details) => details.version = compatible_line(&details.version),
+        Crate::GitHub(ref mut repo) => repo.sha = None,
+        Crate::Git(ref mut repo) => repo.sha = None,
+        Crate::Local(_) | Crate::Path(_) => {}
+    }
+    krate.id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_experiments, CrateDiff, ExperimentsDiff};
+    use crate::config::Config;
+    use crate::crates::{Crate, GitHubRepo, RegistryCrate};
+    use crate::report::Comparison;
+    use crate::results::{FailureReason, TestResult};
+
+    fn reg(name: &str, version: &str) -> Crate {
+        Crate::Registry(RegistryCrate {
+            name: name.into(),
+            version: version.into(),
+        })
+    }
+
+    #[test]
+    fn test_diff_experiments() {
+        let gh = |sha: &str| {
+            Crate::GitHub(GitHubRepo {
+                org: "brson".into(),
+                name: "hello-rs".into(),
+                sha: Some(sha.into()),
+                subdir: None,
+            })
+        };
+
+        let pass = || Some(TestResult::TestPass);
+        let fail = || Some(TestResult::BuildFail(FailureReason::Unknown));
+
+        let old_crates = vec![
+            (reg("fixed", "1.0.0"), [pass(), fail()]),
+            (reg("still-broken", "1.0.0"), [pass(), fail()]),
+            (reg("newly-broken", "1.0.0"), [pass(), pass()]),
+            (reg("now-failing", "1.0.0"), [pass(), pass()]),
+            (reg("removed", "1.0.0"), [pass(), fail()]),
+            (reg("nom", "5.1.0"), [pass(), pass()]),
+            (reg("nom", "7.0.0"), [pass(), pass()]),
+            (gh("f00"), [pass(), pass()]),
+        ];
+
+        // New releases of the crates, and a crate only tested by the new experiment
+        let new_crates = vec![
+            (reg("fixed", "1.0.1"), [pass(), pass()]),
+            (reg("still-broken", "1.0.1"), [pass(), fail()]),
+            (reg("newly-broken", "1.0.0"), [pass(), fail()]),
+            (reg("now-failing", "1.0.0"), [fail(), fail()]),
+            (reg("added", "0.1.0"), [pass(), fail()]),
+            (reg("nom", "5.1.2"), [pass(), fail()]),
+            (reg("nom", "7.1.0"), [pass(), pass()]),
+            (gh("ba5"), [pass(), pass()]),
+        ];
+
+        let diff = diff_experiments(&Config::default(), [&old_crates[..], &new_crates[..]]);
+
+        let krate = |name: &str, url: &str, res: [Option<Comparison>; 2]| CrateDiff {
+            name: name.into(),
+            url: url.into(),
+            res,
+        };
+        assert_eq!(
+            diff,
+            ExperimentsDiff {
+                new_regressions: vec![
+                    krate(
+                        "added-0.1.0",
+                        "https://crates.io/crates/added/0.1.0",
+                        [None, Some(Comparison::Regressed)]
+                    ),
+                    krate(
+                        "newly-broken-1.0.0",
+                        "https://crates.io/crates/newly-broken/1.0.0",
+                        [Some(Comparison::SameTestPass), Some(Comparison::Regressed)]
+                    ),
+                    krate(
+                        "nom-5.1.2",
+                        "https://crates.io/crates/nom/5.1.2",
+                        [Some(Comparison::SameTestPass), Some(Comparison::Regressed)]
+                    ),
+                ],
+                gone_regressions: vec![
+                    krate(
+                        "fixed-1.0.1",
+                        "https://crates.io/crates/fixed/1.0.1",
+                        [Some(Comparison::Regressed), Some(Comparison::SameTestPass)]
+                    ),
+                    krate(
+                        "removed-1.0.0",
+                        "https://crates.io/crates/removed/1.0.0",
+                        [Some(Comparison::Regressed), None]
+                    ),
+                ],
+                changed: vec![krate(
+                    "now-failing-1.0.0",
+                    "https://crates.io/crates/now-failing/1.0.0",
+                    [
+                        Some(Comparison::SameTestPass),
+                        Some(Comparison::SameBuildFail)
+                    ]
+                )],
+            }
+        );
+    }
+}
@@ -40,6 +40,7 @@ impl ResultName for BrokenReason {
             BrokenReason::Yanked => "deps yanked".into(),
             BrokenReason::MissingGitRepository => "missing repo".into(),
             BrokenReason::MissingDependencies => "missing deps".into(),
+            BrokenReason::MinimalVersions => "unresolvable min deps".into(),
         }
     }
 
@@ -1,9 +1,10 @@
 use crate::assets;
+use crate::config::Config;
 use crate::experiments::Experiment;
 use crate::prelude::*;
 use crate::report::{
-    analyzer::ReportCrates, archives::Archive, Color, Comparison, CrateResult, ReportWriter,
-    ResultColor, ResultName, TestResults,
+    analyzer::ReportCrates, archives::Archive, crate_to_name, crate_to_url, Color, Comparison,
+    CrateResult, ReportWriter, ResultColor, ResultName, TestResults,
 };
 use crate::results::EncodingType;
 use indexmap::IndexMap;
@@ -63,6 +64,8 @@ struct ResultsContext<'a> {
     nav: Vec<NavbarItem>,
     categories: Vec<(Comparison, ReportCratesHTML)>,
     info: IndexMap<Comparison, u32>,
+    root_causes: Vec<RootCauseHTML>,
+    target_regressions: Vec<CrateResultHTML>,
     full: bool,
     crates_count: usize,
     comparison_colors: IndexMap<Comparison, Color>,
@@ -87,6 +90,21 @@ struct CrateResultHTML {
     #[serde(skip_serializing_if = "Option::is_none")]
     status: Option<String>,
     runs: [Option<BuildTestResultHTML>; 2],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    regressed_members: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    resolution_changes: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    target_only: bool,
+}
+
+#[derive(Serialize)]
+struct RootCauseHTML {
+    name: String,
+    url: String,
+    broken: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dependents: Option<i64>,
 }
 
 // Map TestResult to usize to avoid the presence of special characters in html
@@ -100,6 +118,7 @@ fn write_report<W: ReportWriter>(
     ex: &Experiment,
     crates_count: usize,
     res: &TestResults,
+    config: &Config,
     full: bool,
     to: &str,
     dest: &W,
@@ -135,6 +154,9 @@ fn write_report<W: ReportWriter>(
             status: result.status.map(|status| status.to_string()),
             res: result.res,
             runs,
+            regressed_members: result.regressed_members.clone(),
+            resolution_changes: result.resolution_changes.clone(),
+            target_only: result.target_only,
         }
     };
 
@@ -203,6 +225,11 @@ fn write_report<W: ReportWriter>(
             }
         })
         .collect();
+    let target_regressions = res
+        .target_regressions
+        .iter()
+        .map(|result| to_html_crate_result(result.clone()))
+        .collect();
 
     let context = ResultsContext {
         ex,
@@ -214,6 +241,17 @@ fn write_report<W: ReportWriter>(
         .navbar(),
         categories,
         info: res.info.clone(),
+        root_causes: res
+            .root_causes
+            .iter()
+            .map(|cause| RootCauseHTML {
+                name: crate_to_name(&cause.krate),
+                url: crate_to_url(&cause.krate, config),
+                broken: cause.broken,
+                dependents: cause.dependents,
+            })
+            .collect(),
+        target_regressions,
         full,
         crates_count,
         comparison_colors,
@@ -269,6 +307,7 @@ pub fn write_html_report<W: ReportWriter>(
     ex: &Experiment,
     crates_count: usize,
     res: &TestResults,
+    config: &Config,
     available_archives: Vec<Archive>,
     dest: &W,
     output_templates: bool,
@@ -279,6 +318,7 @@ pub fn write_html_report<W: ReportWriter>(
         ex,
         crates_count,
         res,
+        config,
         false,
         "index.html",
         dest,
@@ -288,6 +328,7 @@ pub fn write_html_report<W: ReportWriter>(
         ex,
         crates_count,
         res,
+        config,
         true,
         "full.html",
         dest,
@@ -1,5 +1,6 @@
+use crate::config::Config;
 use crate::crates::Crate;
-use crate::experiments::Experiment;
+use crate::experiments::{Experiment, Lockfile};
 use crate::prelude::*;
 use crate::report::analyzer::{ReportConfig, ReportCrates, ToolchainSelect};
 use crate::report::{
@@ -26,6 +27,7 @@ struct ResultsContext<'a> {
     ex: &'a Experiment,
     categories: Vec<(Comparison, ReportCratesMD)>,
     info: IndexMap<Comparison, u32>,
+    target_regressions: Vec<CrateResult>,
     full: bool,
     crates_count: usize,
 }
@@ -68,6 +70,23 @@ fn write_crate(
         .status
         .map(|status| format!(" ({})", status))
         .unwrap_or_default();
+    let mut notes = if krate.regressed_members.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " (regressed members: `{}`)",
+            krate.regressed_members.join("`, `")
+        )
+    };
+    if !krate.resolution_changes.is_empty() {
+        notes.push_str(&format!(
+            " (resolution changes: `{}`)",
+            krate.resolution_changes.join("`, `")
+        ));
+    }
+    if krate.target_only {
+        notes.push_str(" (builds for the host)");
+    }
 
     if let ReportConfig::Complete(toolchain) = comparison.report_config() {
         let (conj, run) = match toolchain {
@@ -77,7 +96,7 @@ fn write_crate(
 
         writeln!(
             rendered,
-            "{}[{}{}]({}) {} {} **{}** [start]({}/log.txt) | [end]({}/log.txt)",
+            "{}[{}{}]({}) {} {} **{}** [start]({}/log.txt) | [end]({}/log.txt){}",
             prefix,
             krate.name,
             status_warning,
@@ -86,24 +105,55 @@ fn write_crate(
             conj,
             runs[run],
             runs[1],
-            runs[3]
+            runs[3],
+            notes
         )?;
     } else {
         writeln!(
             rendered,
-            "{}[{}{}]({}) {} [start]({}/log.txt) | [end]({}/log.txt)",
-            prefix, krate.name, status_warning, krate.url, comparison, runs[1], runs[3]
+            "{}[{}{}]({}) {} [start]({}/log.txt) | [end]({}/log.txt){}",
+            prefix, krate.name, status_warning, krate.url, comparison, runs[1], runs[3], notes
         )?;
     };
 
     Ok(())
 }
 
-fn render_markdown(context: &ResultsContext) -> Fallible<String> {
+fn render_markdown(context: &ResultsContext, config: &Config) -> Fallible<String> {
     let mut rendered = String::new();
 
     //add title
     writeln!(rendered, "# Crater report for {}\n\n", context.ex.name)?;
+    if let Some(ref target) = context.ex.target {
+        writeln!(
+            rendered,
+            "All crates were built for the `{}` target.\n",
+            target
+        )?;
+    }
+    match context.ex.lockfile {
+        Lockfile::Keep => {}
+        Lockfile::Regenerate => writeln!(
+            rendered,
+            "The lockfiles of the crates were discarded, and their dependencies resolved again.\n"
+        )?,
+        Lockfile::MinimalVersions | Lockfile::DirectMinimalVersions => writeln!(
+            rendered,
+            "The lockfiles of the crates were discarded. The start toolchain built them with the \
+             newest versions of their dependencies, and the end toolchain with the lowest versions \
+             allowed by `-Z {}`.\n",
+            context.ex.lockfile.minimal_versions_flag().unwrap()
+        )?,
+    }
+
+    if let Some(ref target) = context.ex.target {
+        if !context.target_regressions.is_empty() {
+            writeln!(rendered, "\n### regressed only on `{}`", target)?;
+            for krate in &context.target_regressions {
+                write_crate(&mut rendered, krate, Comparison::Regressed, false)?;
+            }
+        }
+    }
 
     for (comparison, results) in context.categories.iter() {
         writeln!(rendered, "\n### {}", comparison)?;
@@ -126,7 +176,7 @@ fn render_markdown(context: &ResultsContext) -> Fallible<String> {
                         rendered,
                         "* [{}]({}) (not covered in crater testing)",
                         krate,
-                        crate_to_url(krate)
+                        crate_to_url(krate, config)
                     )?;
                     for krate in deps {
                         write_crate(&mut rendered, krate, *comparison, true)?;
@@ -143,6 +193,7 @@ fn write_report<W: ReportWriter>(
     ex: &Experiment,
     crates_count: usize,
     res: &TestResults,
+    config: &Config,
     full: bool,
     to: &str,
     dest: &W,
@@ -180,11 +231,12 @@ fn write_report<W: ReportWriter>(
         ex,
         categories,
         info: res.info.clone(),
+        target_regressions: res.target_regressions.clone(),
         full,
         crates_count,
     };
 
-    let markdown = render_markdown(&context)?;
+    let markdown = render_markdown(&context, config)?;
     info!("generating {}", to);
     dest.write_string(to, markdown.into(), &mime::TEXT_PLAIN)?;
 
@@ -203,6 +255,7 @@ pub fn write_markdown_report<W: ReportWriter>(
     ex: &Experiment,
     crates_count: usize,
     res: &TestResults,
+    config: &Config,
     dest: &W,
     output_templates: bool,
 ) -> Fallible<()> {
@@ -210,6 +263,7 @@ pub fn write_markdown_report<W: ReportWriter>(
         ex,
         crates_count,
         res,
+        config,
         false,
         "markdown.md",
         dest,
@@ -1,20 +1,20 @@
 use crate::config::Config;
-use crate::crates::Crate;
-use crate::dirs::WORK_DIR;
-use crate::experiments::Experiment;
+use crate::crates::{index::RegistryIndex, lists::RegistryList, Crate};
+use crate::db::Database;
+use crate::experiments::{Experiment, Mode};
 use crate::prelude::*;
 use crate::report::analyzer::{analyze_report, ReportConfig, ToolchainSelect};
-use crate::results::{EncodedLog, EncodingType, FailureReason, ReadResults, TestResult};
+use crate::results::{
+    EncodedLog, EncodingType, FailureReason, ReadResults, ResolvedDependency, TestResult,
+};
 use crate::toolchain::Toolchain;
 use crate::utils;
-use crates_index::Index;
 use mime::{self, Mime};
 use percent_encoding::{utf8_percent_encode, AsciiSet};
 use std::borrow::Cow;
 #[cfg(test)]
 use std::cell::RefCell;
-#[cfg(test)]
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::AsRef;
 use std::fmt::{self, Display};
 use std::fs;
@@ -22,11 +22,13 @@ use std::path::{Path, PathBuf};
 
 mod analyzer;
 mod archives;
+mod diff;
 mod display;
 mod html;
 mod markdown;
 mod s3;
 
+pub use self::diff::{diff_experiments, load_experiment, CrateDiff, CrateResults, ExperimentsDiff};
 pub use self::display::{Color, ResultColor, ResultName};
 pub use self::s3::{S3Prefix, S3Writer};
 pub use analyzer::TestResults;
@@ -58,6 +60,17 @@ pub struct CrateResult {
     status: Option<CrateVersionStatus>,
     pub res: Comparison,
     runs: [Option<BuildTestResult>; 2],
+    /// Workspace members passing with the first toolchain and failing with the second one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    regressed_members: Vec<String>,
+    /// Differences between the dependencies resolved by the two toolchains, in resolve
+    /// experiments.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    resolution_changes: Vec<String>,
+    /// Whether the crate regressed only on the target of the experiment, while still building
+    /// for the host with the second toolchain.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    target_only: bool,
 }
 
 string_enum!(enum CrateVersionStatus {
@@ -164,6 +177,9 @@ fn crate_to_path_fragment(
 
             let name = format!("{}.{}", repo.org, repo.name);
             path.push(dest.sanitize(&name).into_owned());
+            if let Some(ref subdir) = repo.subdir {
+                path.push(dest.sanitize(subdir).into_owned());
+            }
         }
         Crate::Local(ref name) => {
             path.push("local");
@@ -176,24 +192,59 @@ fn crate_to_path_fragment(
         Crate::Git(ref repo) => {
             path.push("git");
             path.push(dest.sanitize(&repo.url).into_owned());
+            if let Some(ref subdir) = repo.subdir {
+                path.push(dest.sanitize(subdir).into_owned());
+            }
+        }
+        Crate::AltRegistry(ref details) => {
+            path.push("alt");
+            path.push(dest.sanitize(&details.registry).into_owned());
+
+            let name = format!("{}-{}", details.name, details.version);
+            path.push(dest.sanitize(&name).into_owned());
         }
     }
 
     path
 }
 
-fn get_crate_version_status(index: &Index, krate: &Crate) -> Fallible<Option<CrateVersionStatus>> {
+/// Popularity of the registry crates, as recorded the last time the crate lists were updated,
+/// used to rank the root causes of the regressions.
+#[derive(Default)]
+pub struct Popularity {
+    /// Number of crates in the registry list depending on every crate, keyed by its name.
+    pub(crate) dependents: HashMap<String, i64>,
+    /// Score the registry list is sorted by, like the number of downloads, keyed by crate name.
+    pub(crate) scores: HashMap<String, i64>,
+}
+
+impl Popularity {
+    pub fn load(db: &Database) -> Fallible<Self> {
+        Ok(Popularity {
+            dependents: RegistryList::dependents_counts(db)?,
+            scores: RegistryList::scores(db)?,
+        })
+    }
+}
+
+fn get_crate_version_status(
+    index: &RegistryIndex,
+    krate: &Crate,
+) -> Fallible<Option<CrateVersionStatus>> {
     if let Crate::Registry(krate) = krate {
         let index_krate = index
-            .crate_(&krate.name)
+            .crate_(&krate.name)?
             .ok_or_else(|| err_msg(format!("no crate found in index {:?}", &krate)))?;
 
-        let outdated = index_krate.latest_version().version() != krate.version;
+        let outdated = index_krate
+            .latest_version()
+            .map(|latest| latest.version != krate.version)
+            .unwrap_or(false);
 
-        for version in index_krate.versions().iter().rev() {
+        for version in index_krate.versions.iter().rev() {
             // Check if the tested version is yanked
-            if version.version() == krate.version {
-                if version.is_yanked() {
+            if version.version == krate.version {
+                if version.yanked {
                     return Ok(Some(CrateVersionStatus::Yanked));
                 } else if outdated {
                     return Ok(Some(CrateVersionStatus::Outdated));
@@ -217,11 +268,7 @@ pub fn generate_report<DB: ReadResults>(
     crates: &[Crate],
 ) -> Fallible<RawTestResults> {
     let mut crates = crates.to_vec();
-    let index = Index::with_path(
-        WORK_DIR.join("crates.io-index"),
-        "https://github.com/rust-lang/crates.io-index",
-    )
-    .to_failure()?;
+    let index = RegistryIndex::open(&config.registry)?;
     //crate ids are unique so unstable sort is equivalent to stable sort but is generally faster
     crates.sort_unstable_by_key(|a| a.id());
     let res = crates
@@ -245,21 +292,46 @@ pub fn generate_report<DB: ReadResults>(
             let mut crate_results = crate_results.map(|r| r.ok()).collect::<Vec<_>>();
             let crate2 = crate_results.pop().unwrap();
             let crate1 = crate_results.pop().unwrap();
-            let comp = compare(
+            let mut comp = compare(
                 config,
                 krate,
                 crate1.as_ref().map(|b| &b.res),
                 crate2.as_ref().map(|b| &b.res),
             );
+            let resolution_changes = if ex.mode == Mode::Resolve {
+                resolution_changes(
+                    &db.load_resolved_dependencies(ex, &ex.toolchains[0], krate)?,
+                    &db.load_resolved_dependencies(ex, &ex.toolchains[1], krate)?,
+                )
+            } else {
+                Vec::new()
+            };
+            // Resolving to a different graph is what resolve experiments look for
+            if comp == Comparison::SameTestPass && !resolution_changes.is_empty() {
+                comp = Comparison::Regressed;
+            }
+            let regressed_members = regressed_members(
+                &db.load_member_results(ex, &ex.toolchains[0], krate)?,
+                &db.load_member_results(ex, &ex.toolchains[1], krate)?,
+            );
+            let target_only = ex.target.is_some()
+                && comp == Comparison::Regressed
+                && matches!(
+                    db.load_host_result(ex, &ex.toolchains[1], krate)?,
+                    Some(TestResult::TestPass | TestResult::TestSkipped)
+                );
 
             Ok(CrateResult {
                 name: crate_to_name(krate),
-                url: crate_to_url(krate),
+                url: crate_to_url(krate, config),
                 status: get_crate_version_status(&index, krate)
                     .unwrap_or(Some(CrateVersionStatus::MissingFromIndex)),
                 krate: krate.clone(),
                 res: comp,
                 runs: [crate1, crate2],
+                regressed_members,
+                resolution_changes,
+                target_only,
             })
         })
         .collect::<Fallible<Vec<_>>>()?;
@@ -267,6 +339,89 @@ pub fn generate_report<DB: ReadResults>(
     Ok(RawTestResults { crates: res })
 }
 
+/// Members of a workspace that passed with the first toolchain but failed with the second one,
+/// which can point out the culprit even when the workspace as a whole was already failing.
+fn regressed_members(
+    start: &BTreeMap<String, TestResult>,
+    end: &BTreeMap<String, TestResult>,
+) -> Vec<String> {
+    end.iter()
+        .filter(|(member, end_res)| {
+            matches!(end_res, TestResult::BuildFail(_) | TestResult::TestFail(_))
+                && matches!(
+                    start.get(*member),
+                    Some(TestResult::TestPass | TestResult::TestSkipped)
+                )
+        })
+        .map(|(member, _)| member.clone())
+        .collect()
+}
+
+/// Packages whose resolved versions, or the features enabled on them, differ between the
+/// dependency graphs of the first and the second toolchain.
+fn resolution_changes(start: &[ResolvedDependency], end: &[ResolvedDependency]) -> Vec<String> {
+    fn by_name(deps: &[ResolvedDependency]) -> BTreeMap<&str, Vec<&ResolvedDependency>> {
+        let mut by_name = BTreeMap::new();
+        for dep in deps {
+            by_name
+                .entry(dep.name.as_str())
+                .or_insert_with(Vec::new)
+                .push(dep);
+        }
+        by_name
+    }
+    fn versions(deps: Option<&Vec<&ResolvedDependency>>) -> String {
+        match deps {
+            Some(deps) => deps
+                .iter()
+                .map(|dep| dep.version.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => "none".into(),
+        }
+    }
+
+    let start = by_name(start);
+    let end = by_name(end);
+    let mut names = start.keys().chain(end.keys()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    let mut changes = Vec::new();
+    for name in names {
+        let (start_deps, end_deps) = (start.get(name), end.get(name));
+        let start_versions = versions(start_deps);
+        let end_versions = versions(end_deps);
+        if start_versions != end_versions {
+            changes.push(format!("{} {} -> {}", name, start_versions, end_versions));
+            continue;
+        }
+
+        // Both graphs contain the same versions of the package
+        for (start_dep, end_dep) in start_deps.unwrap().iter().zip(end_deps.unwrap()) {
+            if start_dep.features != end_dep.features {
+                let added = end_dep
+                    .features
+                    .iter()
+                    .filter(|f| !start_dep.features.contains(f))
+                    .map(|f| format!("+{}", f));
+                let removed = start_dep
+                    .features
+                    .iter()
+                    .filter(|f| !end_dep.features.contains(f))
+                    .map(|f| format!("-{}", f));
+                changes.push(format!(
+                    "{} {} features {}",
+                    name,
+                    start_dep.version,
+                    added.chain(removed).collect::<Vec<_>>().join(" ")
+                ));
+            }
+        }
+    }
+    changes
+}
+
 const PROGRESS_FRACTION: usize = 50; // write progress every ~1/N crates
 
 fn write_logs<DB: ReadResults, W: ReportWriter>(
@@ -321,6 +476,7 @@ pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
     crates: &[Crate],
     dest: &W,
     config: &Config,
+    popularity: &Popularity,
     output_templates: bool,
 ) -> Fallible<TestResults> {
     let raw = generate_report(db, config, ex, crates)?;
@@ -343,7 +499,7 @@ pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
         &mime::TEXT_PLAIN_UTF_8,
     )?;
 
-    let res = analyze_report(raw);
+    let res = analyze_report(raw, popularity);
     info!("writing archives");
     let available_archives = archives::write_logs_archives(db, ex, crates, dest, config)?;
     info!("writing html files");
@@ -351,12 +507,13 @@ pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
         ex,
         crates.len(),
         &res,
+        config,
         available_archives,
         dest,
         output_templates,
     )?;
     info!("writing markdown files");
-    markdown::write_markdown_report(ex, crates.len(), &res, dest, output_templates)?;
+    markdown::write_markdown_report(ex, crates.len(), &res, config, dest, output_templates)?;
     info!("writing logs");
     write_logs(db, ex, crates, dest, config)?;
 
@@ -379,8 +536,14 @@ fn gen_retry_list(res: &RawTestResults) -> String {
     for krate in regressed_crates {
         match krate {
             Crate::Registry(details) => writeln!(out, "{}", details.name).unwrap(),
-            Crate::GitHub(repo) => writeln!(out, "{}/{}", repo.org, repo.name).unwrap(),
-            Crate::Local(_) | Crate::Git(_) | Crate::Path(_) => {}
+            Crate::GitHub(repo) => {
+                write!(out, "{}/{}", repo.org, repo.name).unwrap();
+                if let Some(ref subdir) = repo.subdir {
+                    write!(out, "//{}", subdir).unwrap();
+                }
+                writeln!(out).unwrap();
+            }
+            Crate::Local(_) | Crate::Git(_) | Crate::Path(_) | Crate::AltRegistry(_) => {}
         }
     }
 
@@ -388,6 +551,14 @@ fn gen_retry_list(res: &RawTestResults) -> String {
 }
 
 fn crate_to_name(c: &Crate) -> String {
+    let name = crate_to_name_without_subdir(c);
+    match c.subdir() {
+        Some(subdir) => format!("{} ({})", name, subdir),
+        None => name,
+    }
+}
+
+fn crate_to_name_without_subdir(c: &Crate) -> String {
     match *c {
         Crate::Registry(ref details) => format!("{}-{}", details.name, details.version),
         Crate::GitHub(ref repo) => {
@@ -398,45 +569,94 @@ fn crate_to_name(c: &Crate) -> String {
             }
         }
         Crate::Local(ref name) => format!("{} (local)", name),
-        Crate::Path(ref path) => utf8_percent_encode(path, &REPORT_ENCODE_SET).to_string(),
+        Crate::Path(ref path) => format!("{} (path)", path),
+        Crate::AltRegistry(ref details) => details.to_string(),
         Crate::Git(ref repo) => {
+            let name = git_repo_name(&repo.url);
             if let Some(ref sha) = repo.sha {
-                format!(
-                    "{}.{}",
-                    utf8_percent_encode(&repo.url, &REPORT_ENCODE_SET),
-                    sha
-                )
+                format!("{}.{}", name, sha)
             } else {
-                utf8_percent_encode(&repo.url, &REPORT_ENCODE_SET).to_string()
+                name.to_string()
             }
         }
     }
 }
 
-fn crate_to_url(c: &Crate) -> String {
+/// Strip the scheme and the `.git` suffix from a repository URL, which are just noise in
+/// reports: `https://gitlab.com/org/repo.git` becomes `gitlab.com/org/repo`.
+fn git_repo_name(url: &str) -> &str {
+    let name = url.splitn(2, "://").last().unwrap();
+    let name = name.trim_end_matches('/');
+    name.strip_suffix(".git").unwrap_or(name)
+}
+
+fn crate_to_url(c: &Crate, config: &Config) -> String {
     match *c {
         Crate::Registry(ref details) => format!(
             "https://crates.io/crates/{}/{}",
             details.name, details.version
         ),
-        Crate::GitHub(ref repo) => {
-            if let Some(ref sha) = repo.sha {
+        Crate::GitHub(ref repo) => match (&repo.sha, &repo.subdir) {
+            (Some(sha), Some(subdir)) => format!(
+                "https://github.com/{}/{}/tree/{}/{}",
+                repo.org, repo.name, sha, subdir
+            ),
+            (None, Some(subdir)) => format!(
+                "https://github.com/{}/{}/tree/HEAD/{}",
+                repo.org, repo.name, subdir
+            ),
+            (Some(sha), None) => {
                 format!("https://github.com/{}/{}/tree/{}", repo.org, repo.name, sha)
-            } else {
-                format!("https://github.com/{}/{}", repo.org, repo.name)
             }
-        }
+            (None, None) => format!("https://github.com/{}/{}", repo.org, repo.name),
+        },
         Crate::Local(ref name) => format!(
             "{}/tree/master/local-crates/{}",
             crate::CRATER_REPO_URL,
             name
         ),
-        Crate::Path(ref path) => utf8_percent_encode(path, &REPORT_ENCODE_SET).to_string(),
-        Crate::Git(ref repo) => repo.url.clone(),
+        Crate::Path(ref path) => {
+            format!("file://{}", utf8_percent_encode(path, &REPORT_ENCODE_SET))
+        }
+        Crate::Git(ref repo) => {
+            let url = repo.url.trim_end_matches('/');
+            let url = url.strip_suffix(".git").unwrap_or(url);
+            // The default branch is unknown, so the subdirectory can't be linked without a commit
+            match (&repo.sha, git_commit_path(url)) {
+                (Some(sha), Some(path)) => match repo.subdir {
+                    Some(ref subdir) => format!("{}/{}/{}/{}", url, path, sha, subdir),
+                    None => format!("{}/{}/{}", url, path, sha),
+                },
+                _ => url.to_string(),
+            }
+        }
+        Crate::AltRegistry(ref krate) => krate.url(config).unwrap_or_else(|err| {
+            warn!("failed to get the download URL of {}: {}", krate, err);
+            config
+                .alt_registries
+                .get(&krate.registry)
+                .map(|registry| registry.index.clone())
+                .unwrap_or_default()
+        }),
+    }
+}
+
+/// Path browsing a commit of a repository, for the hosting services known to Crater.
+fn git_commit_path(url: &str) -> Option<&'static str> {
+    let url = url::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    if host == "github.com" {
+        Some("tree")
+    } else if host == "gitlab.com" || host.starts_with("gitlab.") {
+        Some("-/tree")
+    } else if host == "codeberg.org" || host.starts_with("gitea.") {
+        Some("src/commit")
+    } else {
+        None
     }
 }
 
-fn compare(
+pub(crate) fn compare(
     config: &Config,
     krate: &Crate,
     r1: Option<&TestResult>,
@@ -610,13 +830,12 @@ impl Display for DummyWriter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{Config, CrateConfig};
-    use crate::crates::{Crate, GitHubRepo, RegistryCrate};
-    use crate::dirs::WORK_DIR;
-    use crate::experiments::{CapLints, Experiment, Mode, Status};
+    use crate::config::{AltRegistryConfig, Config, CrateConfig, RegistryConfig};
+    use crate::crates::index::{write_test_index, RegistryIndex};
+    use crate::crates::{AltRegistryCrate, Crate, GitHubRepo, GitRepo, RegistryCrate};
+    use crate::experiments::{CapLints, Experiment, Lockfile, Mode, Status};
     use crate::results::{BrokenReason, DummyDB, FailureReason, TestResult};
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
-    use crates_index::Index;
 
     #[test]
     fn test_crate_to_path_fragment() {
@@ -628,6 +847,7 @@ mod tests {
             org: "brson".into(),
             name: "hello-rs".into(),
             sha: None,
+            subdir: None,
         });
         let gt_plus = Crate::Registry(RegistryCrate {
             name: "foo".into(),
@@ -664,6 +884,7 @@ mod tests {
             org: "brson".into(),
             name: "hello-rs".into(),
             sha: None,
+            subdir: None,
         };
         let gh = Crate::GitHub(repo);
 
@@ -673,10 +894,38 @@ mod tests {
             org: "brson".into(),
             name: "hello-rs".into(),
             sha: Some("f00".into()),
+            subdir: None,
         };
         let gh = Crate::GitHub(repo);
 
         assert_eq!(crate_to_name(&gh), "brson.hello-rs.f00".to_string());
+
+        let git = Crate::Git(GitRepo {
+            url: "https://gitlab.com/example/repo.git".into(),
+            sha: Some("f00".into()),
+            subdir: None,
+        });
+        assert_eq!(
+            crate_to_name(&git),
+            "gitlab.com/example/repo.f00".to_string()
+        );
+
+        let path = Crate::Path("/opt/crates/example".into());
+        assert_eq!(
+            crate_to_name(&path),
+            "/opt/crates/example (path)".to_string()
+        );
+
+        let alt = Crate::AltRegistry(AltRegistryCrate {
+            registry: "internal".into(),
+            name: "foo".into(),
+            version: "1.0".into(),
+        });
+        assert_eq!(crate_to_name(&alt), "foo-1.0 (internal)".to_string());
+        assert_eq!(
+            crate_to_path_fragment(&MAIN_TOOLCHAIN, &alt, SanitizationContext::Path),
+            PathBuf::from("stable/alt/internal/foo-1.0")
+        );
     }
 
     #[test]
@@ -695,14 +944,11 @@ mod tests {
             org: "brson".into(),
             name: "hello-rs".into(),
             sha: None,
+            subdir: None,
         };
         let gh = Crate::GitHub(repo);
 
-        let mut index = Index::with_path(
-            WORK_DIR.join("crates.io-index"),
-            "https://github.com/rust-lang/crates.io-index",
-        )
-        .unwrap();
+        let mut index = RegistryIndex::open(&RegistryConfig::default()).unwrap();
         index.update().unwrap();
 
         assert_eq!(
@@ -718,12 +964,14 @@ mod tests {
 
     #[test]
     fn test_crate_to_url() {
+        let config = Config::default();
+
         let reg = Crate::Registry(RegistryCrate {
             name: "lazy_static".into(),
             version: "1.0".into(),
         });
         assert_eq!(
-            crate_to_url(&reg),
+            crate_to_url(&reg, &config),
             "https://crates.io/crates/lazy_static/1.0"
         );
 
@@ -731,21 +979,111 @@ mod tests {
             org: "brson".into(),
             name: "hello-rs".into(),
             sha: None,
+            subdir: None,
         };
         let gh = Crate::GitHub(repo);
 
-        assert_eq!(crate_to_url(&gh), "https://github.com/brson/hello-rs");
+        assert_eq!(
+            crate_to_url(&gh, &config),
+            "https://github.com/brson/hello-rs"
+        );
 
         let repo = GitHubRepo {
             org: "brson".into(),
             name: "hello-rs".into(),
             sha: Some("f00".into()),
+            subdir: None,
         };
         let gh = Crate::GitHub(repo);
         assert_eq!(
-            crate_to_url(&gh),
+            crate_to_url(&gh, &config),
             "https://github.com/brson/hello-rs/tree/f00"
         );
+
+        let git = Crate::Git(GitRepo {
+            url: "https://gitlab.com/example/repo.git".into(),
+            sha: None,
+            subdir: None,
+        });
+        assert_eq!(
+            crate_to_url(&git, &config),
+            "https://gitlab.com/example/repo"
+        );
+
+        let git = Crate::Git(GitRepo {
+            url: "https://gitlab.com/example/repo.git".into(),
+            sha: Some("f00".into()),
+            subdir: None,
+        });
+        assert_eq!(
+            crate_to_url(&git, &config),
+            "https://gitlab.com/example/repo/-/tree/f00"
+        );
+
+        let git = Crate::Git(GitRepo {
+            url: "https://codeberg.org/example/repo".into(),
+            sha: Some("f00".into()),
+            subdir: None,
+        });
+        assert_eq!(
+            crate_to_url(&git, &config),
+            "https://codeberg.org/example/repo/src/commit/f00"
+        );
+
+        // Commits can't be linked on unknown hosts
+        let git = Crate::Git(GitRepo {
+            url: "https://git.example.com/repo.git".into(),
+            sha: Some("f00".into()),
+            subdir: None,
+        });
+        assert_eq!(crate_to_url(&git, &config), "https://git.example.com/repo");
+
+        let git = Crate::Git(GitRepo {
+            url: "https://gitlab.com/example/repo.git".into(),
+            sha: Some("f00".into()),
+            subdir: Some("crates/foo".into()),
+        });
+        assert_eq!(
+            crate_to_url(&git, &config),
+            "https://gitlab.com/example/repo/-/tree/f00/crates/foo"
+        );
+        assert_eq!(
+            crate_to_name(&git),
+            "gitlab.com/example/repo.f00 (crates/foo)"
+        );
+
+        let gh = Crate::GitHub(GitHubRepo {
+            org: "rust-lang".into(),
+            name: "rust".into(),
+            sha: None,
+            subdir: Some("src/tools/foo".into()),
+        });
+        assert_eq!(
+            crate_to_url(&gh, &config),
+            "https://github.com/rust-lang/rust/tree/HEAD/src/tools/foo"
+        );
+
+        // Crates of alternate registries link to their download
+        let index = tempfile::tempdir().unwrap();
+        write_test_index(index.path()).unwrap();
+        let mut config = Config::default();
+        config.alt_registries.insert(
+            "internal".into(),
+            AltRegistryConfig {
+                index: "https://git.example.com/index".into(),
+                index_path: Some(index.path().into()),
+                crates: HashMap::new(),
+            },
+        );
+        let alt = Crate::AltRegistry(AltRegistryCrate {
+            registry: "internal".into(),
+            name: "foo".into(),
+            version: "1.0".into(),
+        });
+        assert_eq!(
+            crate_to_url(&alt, &config),
+            "https://crates.io/api/v1/crates/foo/1.0/download"
+        );
     }
 
     #[test]
@@ -842,6 +1180,7 @@ mod tests {
                 TestSkipped, BrokenCrate(BrokenReason::Unknown) => Broken;
                 TestFail(Unknown), BrokenCrate(BrokenReason::Unknown) => Broken;
                 BuildFail(Unknown), BrokenCrate(BrokenReason::Unknown) => Broken;
+                TestPass, BrokenCrate(BrokenReason::MinimalVersions) => Broken;
             ]
         );
 
@@ -859,6 +1198,38 @@ mod tests {
         assert_eq!(compare(&config, &reg, None, None), Comparison::Skipped);
     }
 
+    #[test]
+    fn test_resolution_changes() {
+        let dep = |name: &str, version: &str, features: &[&str]| ResolvedDependency {
+            name: name.into(),
+            version: version.into(),
+            features: features.iter().map(|f| f.to_string()).collect(),
+        };
+
+        let start = vec![
+            dep("cfg-if", "1.0.0", &[]),
+            dep("libc", "0.2.100", &["default", "std"]),
+            dep("log", "0.4.14", &[]),
+            dep("serde", "1.0.130", &["default", "std"]),
+        ];
+        assert!(resolution_changes(&start, &start).is_empty());
+
+        let end = vec![
+            dep("cfg-if", "1.0.0", &[]),
+            dep("libc", "0.2.100", &["default", "extra_traits", "std"]),
+            dep("serde", "1.0.130", &["default", "std"]),
+            dep("serde", "1.0.131", &["default"]),
+        ];
+        assert_eq!(
+            resolution_changes(&start, &end),
+            vec![
+                "libc 0.2.100 features +extra_traits",
+                "log 0.4.14 -> none",
+                "serde 1.0.130 -> 1.0.130, 1.0.131",
+            ]
+        );
+    }
+
     #[test]
     fn test_report_generation() {
         let config = Config::default();
@@ -867,6 +1238,7 @@ mod tests {
             org: "brson".into(),
             name: "hello-rs".into(),
             sha: Some("f00".into()),
+            subdir: None,
         };
         let gh = Crate::GitHub(repo);
         let reg = Crate::Registry(RegistryCrate {
@@ -877,6 +1249,7 @@ mod tests {
         let ex = Experiment {
             name: "foo".to_string(),
             toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
+            requested_toolchains: [None, None],
             mode: Mode::BuildAndTest,
             cap_lints: CapLints::Forbid,
             priority: 0,
@@ -889,6 +1262,10 @@ mod tests {
             report_url: None,
             ignore_blacklist: false,
             requirement: None,
+            target: Some("wasm32-unknown-unknown".into()),
+            patches: Vec::new(),
+            crate_select: None,
+            lockfile: Lockfile::Keep,
         };
 
         let mut db = DummyDB::default();
@@ -898,6 +1275,18 @@ mod tests {
             MAIN_TOOLCHAIN.clone(),
             TestResult::TestPass,
         );
+        db.add_dummy_host_result(
+            &ex,
+            gh.clone(),
+            TEST_TOOLCHAIN.clone(),
+            TestResult::BuildFail(FailureReason::Unknown),
+        );
+        db.add_dummy_host_result(
+            &ex,
+            reg.clone(),
+            TEST_TOOLCHAIN.clone(),
+            TestResult::TestPass,
+        );
         db.add_dummy_result(
             &ex,
             gh.clone(),
@@ -916,6 +1305,22 @@ mod tests {
             TEST_TOOLCHAIN.clone(),
             EncodedLog::Plain(b"beta log".to_vec()),
         );
+        for member in &["cli", "core"] {
+            db.add_dummy_member_result(
+                &ex,
+                gh.clone(),
+                MAIN_TOOLCHAIN.clone(),
+                member,
+                TestResult::TestPass,
+            );
+        }
+        db.add_dummy_member_result(
+            &ex,
+            gh.clone(),
+            TEST_TOOLCHAIN.clone(),
+            "core",
+            TestResult::BuildFail(FailureReason::ICE),
+        );
 
         db.add_dummy_result(
             &ex,
@@ -943,7 +1348,16 @@ mod tests {
         );
 
         let writer = DummyWriter::default();
-        gen(&db, &ex, &[gh, reg], &writer, &config, false).unwrap();
+        gen(
+            &db,
+            &ex,
+            &[gh, reg],
+            &writer,
+            &config,
+            &Popularity::default(),
+            false,
+        )
+        .unwrap();
 
         assert_eq!(
             writer.get("config.json", &mime::APPLICATION_JSON),
@@ -988,6 +1402,8 @@ mod tests {
             Path::new((&gh_result.runs[1]).as_ref().unwrap().log.as_str()),
             Path::new("beta/gh/brson.hello-rs")
         );
+        assert_eq!(gh_result.regressed_members, vec!["core"]);
+        assert!(!gh_result.target_only);
 
         assert_eq!(reg_result.name.as_str(), "syn-1.0.0");
         assert_eq!(
@@ -1011,6 +1427,8 @@ mod tests {
             Path::new((&reg_result.runs[1]).as_ref().unwrap().log.as_str()),
             Path::new("beta/reg/syn-1.0.0")
         );
+        assert!(reg_result.regressed_members.is_empty());
+        assert!(reg_result.target_only);
 
         assert_eq!(
             writer.get("retry-regressed-list.txt", &mime::TEXT_PLAIN_UTF_8),
@@ -4,10 +4,12 @@ use crate::db::{Database, QueryUtils};
 use crate::experiments::{Experiment, Status};
 use crate::prelude::*;
 use crate::results::{
-    DeleteResults, EncodedLog, EncodingType, ReadResults, TestResult, WriteResults,
+    DeleteResults, EncodedLog, EncodingType, ReadResults, ResolvedDependency, TestResult,
+    WriteResults,
 };
 use crate::toolchain::Toolchain;
 use rustwide::logging::{self, LogStorage};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 #[derive(Deserialize)]
 pub struct TaskResult {
@@ -16,6 +18,15 @@ pub struct TaskResult {
     pub toolchain: Toolchain,
     pub result: TestResult,
     pub log: String,
+    /// Results of the members of a workspace, sent only by agents recording them.
+    #[serde(default)]
+    pub members: BTreeMap<String, TestResult>,
+    /// Resolved dependencies of the crate, sent only by agents recording them.
+    #[serde(default)]
+    pub dependencies: Vec<ResolvedDependency>,
+    /// Result of building the crate for the host, sent only by agents recording it.
+    #[serde(default)]
+    pub host_result: Option<TestResult>,
 }
 
 #[derive(Deserialize)]
@@ -49,25 +60,88 @@ impl<'a> DatabaseDB<'a> {
         // one crater run in storage at any time, as old ones get purged
         // quite quickly after they finish.
         //
-        // We also only purge from results here rather than cleaning up other
-        // tables as this is just simpler and the results dominate the storage
-        // size anyway. In the future we might expand this to other tables, but
-        // for now that wouldn't really add enough value to be worth it.
+        // Besides the results themselves, the per-crate tables recorded alongside
+        // them (workspace members, resolved dependencies and host results) are
+        // purged the same way, as they are just as large for a full run.
         //
         // The query here would be simpler if rusqlite came with delete .. limit
         // support compiled in, but that's not likely to happen (see
         // https://github.com/rusqlite/rusqlite/issues/1111).
-        self.db.execute(
-            "delete from results where rowid in (
-                select rowid from results where \
-                    experiment in (select name from experiments where status = 'completed') \
-                    limit 100
-                )",
-            &[],
-        )?;
+        for table in &[
+            "results",
+            "member_results",
+            "resolved_dependencies",
+            "host_results",
+        ] {
+            self.db.execute(
+                &format!(
+                    "delete from {table} where rowid in (
+                        select rowid from {table} where \
+                            experiment in (select name from experiments where status = 'completed') \
+                            limit 100
+                        )"
+                ),
+                &[],
+            )?;
+        }
         Ok(())
     }
 
+    /// Results of every crate of the experiment with each of its toolchains, keyed by the id of
+    /// the crate. They are loaded with a single query, unlike `load_test_result`.
+    pub fn load_all_results(
+        &self,
+        ex: &Experiment,
+    ) -> Fallible<HashMap<String, [Option<TestResult>; 2]>> {
+        let rows = self.db.query(
+            "SELECT crate, toolchain, result FROM results WHERE experiment = ?1;",
+            &[&ex.name],
+            |row| {
+                let krate: String = row.get("crate")?;
+                let toolchain: String = row.get("toolchain")?;
+                let result: String = row.get("result")?;
+                Ok((krate, toolchain, result))
+            },
+        )?;
+
+        let toolchains = [ex.toolchains[0].to_string(), ex.toolchains[1].to_string()];
+        let mut results = HashMap::new();
+        for (krate, toolchain, result) in rows {
+            if let Some(idx) = toolchains.iter().position(|tc| *tc == toolchain) {
+                results.entry(krate).or_insert([None, None])[idx] = Some(result.parse()?);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Crates of the experiment whose dependency graph contains the named package, along with
+    /// the versions of the package they depend on.
+    pub fn find_dependents(&self, ex: &Experiment, name: &str) -> Fallible<Vec<(Crate, String)>> {
+        let graphs = self.db.query(
+            "SELECT crate, dependencies FROM resolved_dependencies WHERE experiment = ?1;",
+            &[&ex.name],
+            |row| {
+                let krate: String = row.get("crate")?;
+                let dependencies: Vec<u8> = row.get("dependencies")?;
+                Ok((krate, dependencies))
+            },
+        )?;
+
+        let mut dependents = BTreeSet::new();
+        for (krate, dependencies) in graphs {
+            for dep in ResolvedDependency::decompress(&dependencies)? {
+                if dep.name == name {
+                    dependents.insert((krate.clone(), dep.version));
+                }
+            }
+        }
+
+        dependents
+            .into_iter()
+            .map(|(krate, version)| Ok((krate.parse()?, version)))
+            .collect()
+    }
+
     pub fn store(
         &self,
         ex: &Experiment,
@@ -83,6 +157,21 @@ impl<'a> DatabaseDB<'a> {
                 &base64::decode(&result.log).with_context(|_| "invalid base64 log provided")?,
                 encoding_type,
             )?;
+            self.record_member_results(
+                ex,
+                &result.toolchain,
+                &result.krate,
+                result.members.clone(),
+            )?;
+            self.record_resolved_dependencies(
+                ex,
+                &result.toolchain,
+                &result.krate,
+                result.dependencies.clone(),
+            )?;
+            if let Some(ref host_result) = result.host_result {
+                self.record_host_result(ex, &result.toolchain, &result.krate, host_result.clone())?;
+            }
 
             if let Some((old, new)) = &data.version {
                 self.update_crate_version(ex, old, new)?;
@@ -187,6 +276,71 @@ impl<'a> ReadResults for DatabaseDB<'a> {
             Ok(None)
         }
     }
+
+    fn load_member_results(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<BTreeMap<String, TestResult>> {
+        let members = self.db.query(
+            "SELECT member, result FROM member_results \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+            &[&ex.name, &toolchain.to_string(), &krate.id()],
+            |row| {
+                Ok((
+                    row.get::<_, String>("member")?,
+                    row.get::<_, String>("result")?,
+                ))
+            },
+        )?;
+
+        members
+            .into_iter()
+            .map(|(member, res)| Ok((member, res.parse()?)))
+            .collect()
+    }
+
+    fn load_resolved_dependencies(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Vec<ResolvedDependency>> {
+        let dependencies: Option<Vec<u8>> = self
+            .db
+            .query(
+                "SELECT dependencies FROM resolved_dependencies \
+                 WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+                &[&ex.name, &toolchain.to_string(), &krate.id()],
+                |row| row.get("dependencies"),
+            )?
+            .pop();
+
+        match dependencies {
+            Some(dependencies) => ResolvedDependency::decompress(&dependencies),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn load_host_result(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<TestResult>> {
+        let result: Option<String> = self
+            .db
+            .query(
+                "SELECT result FROM host_results \
+                 WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+                &[&ex.name, &toolchain.to_string(), &krate.id()],
+                |row| row.get("result"),
+            )?
+            .pop();
+
+        Ok(result.map(|res| res.parse()).transpose()?)
+    }
 }
 
 impl<'a> WriteResults for DatabaseDB<'a> {
@@ -233,12 +387,100 @@ impl<'a> WriteResults for DatabaseDB<'a> {
         )?;
         Ok(result)
     }
+
+    fn record_member_results(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        members: BTreeMap<String, TestResult>,
+    ) -> Fallible<()> {
+        self.db.transaction(|t| {
+            // Members removed from the workspace since a previous run must not linger
+            t.execute(
+                "DELETE FROM member_results \
+                 WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+                &[&ex.name, &toolchain.to_string(), &krate.id()],
+            )?;
+            for (member, res) in &members {
+                t.execute(
+                    "INSERT INTO member_results (experiment, crate, toolchain, member, result) \
+                     VALUES (?1, ?2, ?3, ?4, ?5);",
+                    &[
+                        &ex.name,
+                        &krate.id(),
+                        &toolchain.to_string(),
+                        member,
+                        &res.to_string(),
+                    ],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    fn record_resolved_dependencies(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        mut dependencies: Vec<ResolvedDependency>,
+    ) -> Fallible<()> {
+        if dependencies.is_empty() {
+            return Ok(());
+        }
+
+        dependencies.sort();
+        self.db.execute(
+            "INSERT INTO resolved_dependencies (experiment, crate, toolchain, dependencies) \
+             VALUES (?1, ?2, ?3, ?4);",
+            &[
+                &ex.name,
+                &krate.id(),
+                &toolchain.to_string(),
+                &ResolvedDependency::compress(&dependencies)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn record_host_result(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        result: TestResult,
+    ) -> Fallible<()> {
+        self.db.execute(
+            "INSERT INTO host_results (experiment, crate, toolchain, result) \
+             VALUES (?1, ?2, ?3, ?4);",
+            &[
+                &ex.name,
+                &krate.id(),
+                &toolchain.to_string(),
+                &result.to_string(),
+            ],
+        )?;
+        Ok(())
+    }
 }
 
 impl<'a> DeleteResults for DatabaseDB<'a> {
     fn delete_all_results(&self, ex: &Experiment) -> Fallible<()> {
         self.db
             .execute("DELETE FROM results WHERE experiment = ?1;", &[&ex.name])?;
+        self.db.execute(
+            "DELETE FROM member_results WHERE experiment = ?1;",
+            &[&ex.name],
+        )?;
+        self.db.execute(
+            "DELETE FROM resolved_dependencies WHERE experiment = ?1;",
+            &[&ex.name],
+        )?;
+        self.db.execute(
+            "DELETE FROM host_results WHERE experiment = ?1;",
+            &[&ex.name],
+        )?;
         Ok(())
     }
 
@@ -247,6 +489,21 @@ impl<'a> DeleteResults for DatabaseDB<'a> {
             "DELETE FROM results WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
             &[&ex.name, &tc.to_string(), &krate.id()],
         )?;
+        self.db.execute(
+            "DELETE FROM member_results \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+            &[&ex.name, &tc.to_string(), &krate.id()],
+        )?;
+        self.db.execute(
+            "DELETE FROM resolved_dependencies \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+            &[&ex.name, &tc.to_string(), &krate.id()],
+        )?;
+        self.db.execute(
+            "DELETE FROM host_results \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+            &[&ex.name, &tc.to_string(), &krate.id()],
+        )?;
         Ok(())
     }
 }
@@ -258,15 +515,15 @@ mod tests {
     use crate::config::Config;
     use crate::crates::{Crate, RegistryCrate};
     use crate::db::Database;
-    use crate::experiments::Experiment;
+    use crate::experiments::{Experiment, Status};
     use crate::prelude::*;
     use crate::results::{
-        DeleteResults, EncodedLog, EncodingType, FailureReason, ReadResults, TestResult,
-        WriteResults,
+        DeleteResults, DiagnosticCode, EncodedLog, EncodingType, FailureReason, ReadResults,
+        ResolvedDependency, TestResult, WriteResults,
     };
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
 
-    use std::collections::BTreeSet;
+    use std::collections::{BTreeMap, BTreeSet};
 
     #[test]
     fn test_versions() {
@@ -409,6 +666,17 @@ mod tests {
             Some(TestResult::TestFail(FailureReason::Unknown))
         );
 
+        // Load the results of the whole experiment at once
+        let all = results.load_all_results(&ex).unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(
+            all[&krate.id()],
+            [
+                Some(TestResult::TestPass),
+                Some(TestResult::TestFail(FailureReason::Unknown))
+            ]
+        );
+
         // Test deleting the newly-added result
         results.delete_result(&ex, &TEST_TOOLCHAIN, &krate).unwrap();
         assert!(results
@@ -460,6 +728,9 @@ mod tests {
                         toolchain: MAIN_TOOLCHAIN.clone(),
                         result: TestResult::TestPass,
                         log: base64::encode("foo"),
+                        members: BTreeMap::new(),
+                        dependencies: Vec::new(),
+                        host_result: None,
                     }],
                     version: Some((krate.clone(), updated.clone())),
                 },
@@ -489,4 +760,258 @@ mod tests {
             None
         );
     }
+
+    #[test]
+    fn test_member_results() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let krate = Crate::Local("build-pass".into());
+
+        let mut members = BTreeMap::new();
+        members.insert(
+            "core".to_string(),
+            TestResult::BuildFail(FailureReason::CompilerError(
+                vec![DiagnosticCode::from("E0001".into())]
+                    .into_iter()
+                    .collect(),
+            )),
+        );
+        members.insert("cli".to_string(), TestResult::TestPass);
+
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![TaskResult {
+                        krate: krate.clone(),
+                        toolchain: MAIN_TOOLCHAIN.clone(),
+                        result: TestResult::BuildFail(FailureReason::Unknown),
+                        log: base64::encode("foo"),
+                        members: members.clone(),
+                        dependencies: Vec::new(),
+                        host_result: None,
+                    }],
+                    version: None,
+                },
+                EncodingType::Plain,
+            )
+            .unwrap();
+
+        assert_eq!(
+            results
+                .load_member_results(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap(),
+            members
+        );
+        assert!(results
+            .load_member_results(&ex, &TEST_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_empty());
+
+        // Members removed from the workspace are dropped when recording the members again
+        members.remove("core");
+        results
+            .record_member_results(&ex, &MAIN_TOOLCHAIN, &krate, members.clone())
+            .unwrap();
+        assert_eq!(
+            results
+                .load_member_results(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap(),
+            members
+        );
+
+        // Retrying a crate discards the results of its members too
+        results.delete_result(&ex, &MAIN_TOOLCHAIN, &krate).unwrap();
+        assert!(results
+            .load_member_results(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_resolved_dependencies() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let krate = Crate::Local("build-pass".into());
+
+        let dep = |name: &str, version: &str, features: &[&str]| ResolvedDependency {
+            name: name.into(),
+            version: version.into(),
+            features: features.iter().map(|f| f.to_string()).collect(),
+        };
+        let dependencies = vec![
+            dep("log", "0.4.0", &[]),
+            dep("serde", "0.9.5", &["std"]),
+            dep("serde", "1.0.0", &["default", "derive"]),
+        ];
+
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![TaskResult {
+                        krate: krate.clone(),
+                        toolchain: MAIN_TOOLCHAIN.clone(),
+                        result: TestResult::TestPass,
+                        log: base64::encode("foo"),
+                        members: BTreeMap::new(),
+                        dependencies: dependencies.clone(),
+                        host_result: None,
+                    }],
+                    version: None,
+                },
+                EncodingType::Plain,
+            )
+            .unwrap();
+
+        assert_eq!(
+            results
+                .load_resolved_dependencies(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap(),
+            dependencies
+        );
+        assert!(results
+            .load_resolved_dependencies(&ex, &TEST_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            results.find_dependents(&ex, "serde").unwrap(),
+            vec![
+                (krate.clone(), "0.9.5".to_string()),
+                (krate.clone(), "1.0.0".to_string())
+            ]
+        );
+        assert!(results.find_dependents(&ex, "rand").unwrap().is_empty());
+
+        results.delete_all_results(&ex).unwrap();
+        assert!(results
+            .load_resolved_dependencies(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_host_results() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let krate = Crate::Local("build-pass".into());
+
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![TaskResult {
+                        krate: krate.clone(),
+                        toolchain: TEST_TOOLCHAIN.clone(),
+                        result: TestResult::BuildFail(FailureReason::Unknown),
+                        log: base64::encode("foo"),
+                        members: BTreeMap::new(),
+                        dependencies: Vec::new(),
+                        host_result: Some(TestResult::TestPass),
+                    }],
+                    version: None,
+                },
+                EncodingType::Plain,
+            )
+            .unwrap();
+
+        assert_eq!(
+            results
+                .load_host_result(&ex, &TEST_TOOLCHAIN, &krate)
+                .unwrap(),
+            Some(TestResult::TestPass)
+        );
+        assert!(results
+            .load_host_result(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_none());
+
+        results.delete_all_results(&ex).unwrap();
+        assert!(results
+            .load_host_result(&ex, &TEST_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_clear_stale_records() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let krate = Crate::Local("build-pass".into());
+
+        let mut members = BTreeMap::new();
+        members.insert("cli".to_string(), TestResult::TestPass);
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![TaskResult {
+                        krate: krate.clone(),
+                        toolchain: MAIN_TOOLCHAIN.clone(),
+                        result: TestResult::TestPass,
+                        log: base64::encode("foo"),
+                        members,
+                        dependencies: vec![ResolvedDependency {
+                            name: "log".into(),
+                            version: "0.4.0".into(),
+                            features: Vec::new(),
+                        }],
+                        host_result: Some(TestResult::TestPass),
+                    }],
+                    version: None,
+                },
+                EncodingType::Plain,
+            )
+            .unwrap();
+
+        // Records of running experiments are kept
+        results.clear_stale_records().unwrap();
+        assert!(results
+            .load_test_result(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_some());
+
+        ex.set_status(&db, Status::Completed).unwrap();
+        results.clear_stale_records().unwrap();
+        assert!(results
+            .load_test_result(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_none());
+        assert!(results
+            .load_member_results(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_empty());
+        assert!(results
+            .load_resolved_dependencies(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_empty());
+        assert!(results
+            .load_host_result(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_none());
+    }
 }
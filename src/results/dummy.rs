@@ -1,14 +1,16 @@
 use crate::crates::Crate;
 use crate::experiments::Experiment;
 use crate::prelude::*;
-use crate::results::{EncodedLog, ReadResults, TestResult};
+use crate::results::{EncodedLog, ReadResults, ResolvedDependency, TestResult};
 use crate::toolchain::Toolchain;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Default)]
 struct DummyData {
     logs: HashMap<(Crate, Toolchain), EncodedLog>,
     results: HashMap<(Crate, Toolchain), TestResult>,
+    members: HashMap<(Crate, Toolchain), BTreeMap<String, TestResult>>,
+    host_results: HashMap<(Crate, Toolchain), TestResult>,
 }
 
 #[derive(Default)]
@@ -45,6 +47,37 @@ impl DummyDB {
             .results
             .insert((krate, tc), res);
     }
+
+    pub fn add_dummy_member_result(
+        &mut self,
+        ex: &Experiment,
+        krate: Crate,
+        tc: Toolchain,
+        member: &str,
+        res: TestResult,
+    ) {
+        self.experiments
+            .entry(ex.name.to_string())
+            .or_insert_with(DummyData::default)
+            .members
+            .entry((krate, tc))
+            .or_default()
+            .insert(member.to_string(), res);
+    }
+
+    pub fn add_dummy_host_result(
+        &mut self,
+        ex: &Experiment,
+        krate: Crate,
+        tc: Toolchain,
+        res: TestResult,
+    ) {
+        self.experiments
+            .entry(ex.name.to_string())
+            .or_insert_with(DummyData::default)
+            .host_results
+            .insert((krate, tc), res);
+    }
 }
 
 impl ReadResults for DummyDB {
@@ -73,4 +106,40 @@ impl ReadResults for DummyDB {
             .get(&(krate.clone(), toolchain.clone()))
             .cloned())
     }
+
+    fn load_member_results(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<BTreeMap<String, TestResult>> {
+        Ok(self
+            .get_data(ex)?
+            .members
+            .get(&(krate.clone(), toolchain.clone()))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn load_resolved_dependencies(
+        &self,
+        _ex: &Experiment,
+        _toolchain: &Toolchain,
+        _krate: &Crate,
+    ) -> Fallible<Vec<ResolvedDependency>> {
+        Ok(Vec::new())
+    }
+
+    fn load_host_result(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<TestResult>> {
+        Ok(self
+            .get_data(ex)?
+            .host_results
+            .get(&(krate.clone(), toolchain.clone()))
+            .cloned())
+    }
 }
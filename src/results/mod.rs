@@ -14,7 +14,7 @@ use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use rustwide::logging::LogStorage;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::{fmt, io::Read, io::Write, str::FromStr};
 
 pub trait ReadResults {
@@ -30,6 +30,30 @@ pub trait ReadResults {
         toolchain: &Toolchain,
         krate: &Crate,
     ) -> Fallible<Option<TestResult>>;
+    /// Results of the members of a workspace, keyed by package name. Crates with a single
+    /// package have none.
+    fn load_member_results(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<BTreeMap<String, TestResult>>;
+    /// Packages Cargo resolved the dependencies of a crate to, for the experiments recording
+    /// them.
+    fn load_resolved_dependencies(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Vec<ResolvedDependency>>;
+    /// Result of building a crate for the host, in experiments with a target where it failed to
+    /// build for the target.
+    fn load_host_result(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<TestResult>>;
 }
 
 pub trait WriteResults {
@@ -52,6 +76,34 @@ pub trait WriteResults {
     ) -> Fallible<TestResult>
     where
         F: FnOnce() -> Fallible<TestResult>;
+    /// Record the results of the members of a workspace. This must be called from inside the
+    /// closure passed to `record_result` for the same crate and toolchain.
+    fn record_member_results(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        members: BTreeMap<String, TestResult>,
+    ) -> Fallible<()>;
+    /// Record the packages Cargo resolved the dependencies of a crate to. This must be called
+    /// from inside the closure passed to `record_result` for the same crate and toolchain.
+    fn record_resolved_dependencies(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        dependencies: Vec<ResolvedDependency>,
+    ) -> Fallible<()>;
+    /// Record the result of building a crate for the host instead of the target of the
+    /// experiment. This must be called from inside the closure passed to `record_result` for the
+    /// same crate and toolchain.
+    fn record_host_result(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        result: TestResult,
+    ) -> Fallible<()>;
 }
 
 pub trait DeleteResults {
@@ -164,6 +216,31 @@ pub enum TestResultParseError {
     UnexpectedFailureReason,
 }
 
+/// A package of the dependency graph Cargo resolved for a crate.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ResolvedDependency {
+    pub name: String,
+    pub version: String,
+    /// Features enabled on the package, only known when the graph was resolved by `cargo
+    /// metadata`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+}
+
+impl ResolvedDependency {
+    /// Compress the dependency graph of a crate, which is stored as a single gzipped JSON blob
+    /// instead of one row per package.
+    pub(crate) fn compress(dependencies: &[ResolvedDependency]) -> std::io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, dependencies)?;
+        encoder.finish()
+    }
+
+    pub(crate) fn decompress(data: &[u8]) -> Fallible<Vec<ResolvedDependency>> {
+        Ok(serde_json::from_reader(GzDecoder::new(data))?)
+    }
+}
+
 // simplified and lighter version of cargo-metadata::diagnostic::DiagnosticCode
 #[derive(Debug, PartialEq, Serialize, Deserialize, Eq, Clone, Hash, PartialOrd, Ord)]
 pub struct DiagnosticCode {
@@ -289,6 +366,7 @@ string_enum!(pub enum BrokenReason {
     Yanked => "yanked",
     MissingDependencies => "missing-deps",
     MissingGitRepository => "missing-git-repository",
+    MinimalVersions => "minimal-versions",
 });
 
 test_result_enum!(pub enum TestResult {
@@ -0,0 +1,318 @@
+use crate::crates::Crate;
+use crate::prelude::*;
+use crate::results::{ResolvedDependency, WriteResults};
+use crate::runner::tasks::TaskCtx;
+use cargo_metadata::PackageId;
+use remove_dir_all::remove_dir_all;
+use rustwide::cmd::Command;
+use rustwide::{Build, Workspace};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(Deserialize)]
+struct LockfileContents {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    /// Missing for the packages of the crate itself and its path dependencies.
+    source: Option<String>,
+}
+
+/// Packages from outside of the crate that Cargo resolved its dependencies to, as recorded in
+/// the lockfile of the build.
+pub(super) fn resolved_dependencies(build_env: &Build) -> Fallible<Vec<ResolvedDependency>> {
+    let path = build_env.host_source_dir().join("Cargo.lock");
+    Ok(parse_lockfile(&fs::read_to_string(&path)?)
+        .with_context(|_| format!("invalid lockfile {}", path.display()))?)
+}
+
+/// The dependency cargo named when failing to build it, as locked for the build. `None` when the
+/// lockfile contains several matching packages.
+pub(super) fn locked_dependency(
+    build_env: &Build,
+    name: &str,
+    version: Option<&str>,
+) -> Fallible<Option<Crate>> {
+    let path = build_env.host_source_dir().join("Cargo.lock");
+    find_locked(&fs::read_to_string(&path)?, name, version)
+}
+
+fn find_locked(contents: &str, name: &str, version: Option<&str>) -> Fallible<Option<Crate>> {
+    let contents: LockfileContents = ::toml::from_str(contents)?;
+    let mut matching = contents.package.into_iter().filter(|package| {
+        package.name == name && version.map_or(true, |version| package.version == version)
+    });
+
+    match (matching.next(), matching.next()) {
+        (Some(package), None) => match package.source {
+            Some(source) => Ok(Some(Crate::try_from(&PackageId {
+                repr: format!("{} {} ({})", package.name, package.version, source),
+            })?)),
+            // Path dependencies are part of the crate
+            None => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Write the lockfile resolving the dependencies of the checked out crate to the lowest versions
+/// their requirements allow, with the given `-Z` flag of the toolchain's cargo.
+pub(super) fn generate_minimal_lockfile<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    workspace: &Workspace,
+    flag: &str,
+) -> Fallible<()> {
+    // The flag is unstable, and the end toolchain isn't necessarily a nightly one. As
+    // `RUSTC_BOOTSTRAP` would also let the manifests enable unstable Cargo features while cargo
+    // runs outside of the sandbox, crates using them aren't resolved.
+    if let Some(manifest) = find_cargo_features(&ctx.krate.checkout_dir())? {
+        bail!("{} enables unstable Cargo features", manifest.display());
+    }
+    cargo(
+        ctx,
+        workspace,
+        &ctx.krate.checkout_manifest().0,
+        &["generate-lockfile", "-Z", flag],
+        &[("RUSTC_BOOTSTRAP", "1")],
+    )?;
+    Ok(())
+}
+
+/// Run cargo outside of the sandbox, as resolving dependencies needs to access the registry.
+/// Neither `generate-lockfile` nor `metadata` run any code of the crate, as long as the Cargo
+/// configuration files were removed from the checkout with [`remove_cargo_configs`]. For the same
+/// reason the `cargoflags` of the toolchain aren't passed, and only the variables of its `env`
+/// configuring the resolver are set: the others could point cargo to any program (like
+/// `RUSTC_WRAPPER` or `--config build.rustc-wrapper=...`).
+pub(super) fn cargo<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    workspace: &Workspace,
+    source: &Path,
+    args: &[&str],
+    env: &[(&str, &str)],
+) -> Fallible<String> {
+    let manifest = ctx.krate.checkout_manifest().1;
+    let mut args = args.to_vec();
+    args.extend(&["--manifest-path", manifest.to_str().unwrap()]);
+
+    let mut command = Command::new(workspace, ctx.toolchain.cargo())
+        .args(&args)
+        .cd(source);
+    for (var, data) in &ctx.toolchain.env {
+        if var.starts_with("CARGO_RESOLVER_") {
+            command = command.env(var, data);
+        }
+    }
+    for (var, data) in env {
+        command = command.env(var, data);
+    }
+    Ok(command
+        .log_output(false)
+        .run_capture()?
+        .stdout_lines()
+        .join("\n"))
+}
+
+/// Remove the Cargo configuration files of a checkout, which could otherwise run any program
+/// (like a `build.rustc-wrapper`) when cargo runs outside of the sandbox. Cargo reads them from
+/// the directory it runs in and all its parents, so every directory of the checkout is cleaned.
+pub(super) fn remove_cargo_configs(checkout: &Path) -> Fallible<()> {
+    for entry in WalkDir::new(checkout) {
+        let entry = entry?;
+        if entry.file_name() != ".cargo" {
+            continue;
+        }
+        if entry.path_is_symlink() {
+            // Cargo would follow the link to a directory outside of the checkout
+            fs::remove_file(entry.path())?;
+        } else if entry.file_type().is_dir() {
+            for name in &["config", "config.toml"] {
+                let path = entry.path().join(name);
+                match fs::symlink_metadata(&path) {
+                    Ok(metadata) if metadata.is_dir() => remove_dir_all(&path)?,
+                    Ok(_) => fs::remove_file(&path)?,
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The first manifest of the checkout enabling unstable Cargo features with `cargo-features`.
+fn find_cargo_features(checkout: &Path) -> Fallible<Option<PathBuf>> {
+    for entry in WalkDir::new(checkout) {
+        let entry = entry?;
+        if entry.file_name() != "Cargo.toml" || !entry.file_type().is_file() {
+            continue;
+        }
+        let manifest = match ::toml::from_str::<::toml::Value>(&fs::read_to_string(entry.path())?) {
+            Ok(manifest) => manifest,
+            // Cargo fails on invalid manifests without getting to the features
+            Err(_) => continue,
+        };
+        if manifest.get("cargo-features").is_some() {
+            return Ok(Some(entry.into_path()));
+        }
+    }
+    Ok(None)
+}
+
+fn parse_lockfile(contents: &str) -> Fallible<Vec<ResolvedDependency>> {
+    let contents: LockfileContents = ::toml::from_str(contents)?;
+    let mut dependencies: Vec<_> = contents
+        .package
+        .into_iter()
+        .filter(|package| package.source.is_some())
+        .map(|package| ResolvedDependency {
+            name: package.name,
+            version: package.version,
+            features: Vec::new(),
+        })
+        .collect();
+    dependencies.sort();
+    dependencies.dedup();
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_cargo_features, find_locked, parse_lockfile, remove_cargo_configs};
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::results::ResolvedDependency;
+    use std::fs;
+
+    #[test]
+    fn test_remove_cargo_configs() {
+        let checkout = tempfile::tempdir().unwrap();
+        let root = checkout.path();
+        for dir in &[".cargo", "crates/foo/.cargo"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("config.toml"), "[build]\n").unwrap();
+        }
+        fs::write(root.join(".cargo/config"), "[build]\n").unwrap();
+        fs::write(root.join(".cargo/credentials.toml"), "").unwrap();
+        fs::create_dir_all(root.join("crates/foo/src")).unwrap();
+        fs::write(root.join("crates/foo/src/config.toml"), "").unwrap();
+
+        remove_cargo_configs(root).unwrap();
+        assert!(!root.join(".cargo/config").exists());
+        assert!(!root.join(".cargo/config.toml").exists());
+        assert!(!root.join("crates/foo/.cargo/config.toml").exists());
+        // Other files are left alone
+        assert!(root.join(".cargo/credentials.toml").exists());
+        assert!(root.join("crates/foo/src/config.toml").exists());
+    }
+
+    #[test]
+    fn test_find_cargo_features() {
+        let checkout = tempfile::tempdir().unwrap();
+        let root = checkout.path();
+        fs::create_dir_all(root.join("crates/foo")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\n").unwrap();
+        fs::write(
+            root.join("crates/foo/Cargo.toml"),
+            "[package]\nname = \"foo\"\n",
+        )
+        .unwrap();
+        assert_eq!(find_cargo_features(root).unwrap(), None);
+
+        fs::write(
+            root.join("crates/foo/Cargo.toml"),
+            "cargo-features = [\"edition2024\"]\n[package]\nname = \"foo\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            find_cargo_features(root).unwrap(),
+            Some(root.join("crates/foo/Cargo.toml"))
+        );
+    }
+
+    #[test]
+    fn test_parse_lockfile() {
+        let lockfile = r#"
+            version = 3
+
+            [[package]]
+            name = "app"
+            version = "0.1.0"
+            dependencies = ["serde 1.0.0", "serde 0.9.5", "utils"]
+
+            [[package]]
+            name = "serde"
+            version = "1.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+            [[package]]
+            name = "serde"
+            version = "0.9.5"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "utils"
+            version = "0.1.0"
+        "#;
+
+        let dep = |name: &str, version: &str| ResolvedDependency {
+            name: name.into(),
+            version: version.into(),
+            features: Vec::new(),
+        };
+        assert_eq!(
+            parse_lockfile(lockfile).unwrap(),
+            vec![dep("serde", "0.9.5"), dep("serde", "1.0.0")]
+        );
+        assert!(parse_lockfile("").unwrap().is_empty());
+        assert!(parse_lockfile("[[package]]\nname = 1").is_err());
+    }
+
+    #[test]
+    fn test_find_locked() {
+        let lockfile = r#"
+            [[package]]
+            name = "openssl-sys"
+            version = "0.9.60"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "serde"
+            version = "1.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "serde"
+            version = "0.9.5"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "utils"
+            version = "0.1.0"
+        "#;
+
+        let reg = |name: &str, version: &str| {
+            Some(Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: version.into(),
+            }))
+        };
+        assert_eq!(
+            find_locked(lockfile, "openssl-sys", None).unwrap(),
+            reg("openssl-sys", "0.9.60")
+        );
+        assert_eq!(
+            find_locked(lockfile, "serde", Some("0.9.5")).unwrap(),
+            reg("serde", "0.9.5")
+        );
+        assert_eq!(find_locked(lockfile, "serde", None).unwrap(), None);
+        assert_eq!(find_locked(lockfile, "utils", None).unwrap(), None);
+        assert_eq!(find_locked(lockfile, "rand", None).unwrap(), None);
+    }
+}
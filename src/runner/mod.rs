@@ -1,10 +1,14 @@
+mod lockfile;
+mod patch;
+mod resolve;
 mod tasks;
 mod test;
 mod unstable_features;
 mod worker;
 
 use crate::config::Config;
-use crate::crates::Crate;
+use crate::crates::{update_alt_registries, Crate};
+use crate::dirs::WORK_DIR;
 use crate::experiments::{Experiment, Mode};
 use crate::prelude::*;
 use crate::results::{TestResult, WriteResults};
@@ -13,6 +17,9 @@ use crossbeam_utils::thread::{scope, ScopedJoinHandle};
 use rustwide::logging::LogStorage;
 use rustwide::Workspace;
 use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
 use std::sync::Mutex;
 use std::time::Duration;
 
@@ -25,6 +32,8 @@ pub struct OverrideResult(TestResult);
 
 struct RunnerStateInner {
     prepare_logs: HashMap<Crate, LogStorage>,
+    /// Results of the start toolchain, to find out whether the end toolchain regressed.
+    start_results: HashMap<Crate, TestResult>,
 }
 
 struct RunnerState {
@@ -36,6 +45,7 @@ impl RunnerState {
         RunnerState {
             inner: Mutex::new(RunnerStateInner {
                 prepare_logs: HashMap::new(),
+                start_results: HashMap::new(),
             }),
         }
     }
@@ -45,6 +55,109 @@ impl RunnerState {
     }
 }
 
+/// Sources defined by Crater in the Cargo configuration are named with this prefix.
+const CARGO_SOURCE_PREFIX: &str = "crater-";
+
+/// Write the Cargo configuration shared by every build: the alternate registries crates can
+/// depend on, and the replacement of crates.io with the vendored sources or the mirror of the
+/// index if they're configured. Keys not owned by Crater are left untouched.
+fn setup_cargo_config(config: &Config) -> Fallible<()> {
+    // rustwide keeps the Cargo home shared by every build inside the workspace
+    let cargo_config = WORK_DIR.join("cargo-home").join("config.toml");
+
+    let mut content = if cargo_config.exists() {
+        fs::read_to_string(&cargo_config)?
+            .parse::<toml::Value>()
+            .with_context(|_| format!("invalid Cargo configuration {}", cargo_config.display()))?
+    } else {
+        toml::Value::Table(toml::value::Table::new())
+    };
+    let table = content
+        .as_table_mut()
+        .ok_or_else(|| err_msg("the Cargo configuration is not a table"))?;
+    update_cargo_config(table, config, &env::current_dir()?)?;
+
+    if !table.is_empty() {
+        fs::write(&cargo_config, toml::to_string(&content)?)?;
+    } else if cargo_config.exists() {
+        fs::remove_file(&cargo_config)?;
+    }
+
+    Ok(())
+}
+
+/// Crater owns the `index` of its alternate registries, the sources named with
+/// `CARGO_SOURCE_PREFIX`, and the replacement of crates.io when it points to one of them.
+fn update_cargo_config(
+    table: &mut toml::value::Table,
+    config: &Config,
+    current_dir: &Path,
+) -> Fallible<()> {
+    fn subtable<'a>(
+        table: &'a mut toml::value::Table,
+        key: &str,
+    ) -> Fallible<&'a mut toml::value::Table> {
+        table
+            .entry(key.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::value::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| {
+                err_msg(format!(
+                    "`{}` in the Cargo configuration is not a table",
+                    key
+                ))
+            })
+    }
+
+    let registries = subtable(table, "registries")?;
+    for (name, registry) in &config.alt_registries {
+        subtable(registries, name)?.insert("index".into(), registry.index.as_str().into());
+    }
+
+    let sources = subtable(table, "source")?;
+    sources.retain(|name, _| !name.starts_with(CARGO_SOURCE_PREFIX));
+    let crates_io = subtable(sources, "crates-io")?;
+    let replaced_by_crater = matches!(
+        crates_io.get("replace-with").and_then(|source| source.as_str()),
+        Some(source) if source.starts_with(CARGO_SOURCE_PREFIX)
+    );
+    if replaced_by_crater {
+        crates_io.remove("replace-with");
+    }
+
+    let replacement = if let Some(ref vendor) = config.registry.vendor {
+        let vendor = current_dir.join(vendor);
+        Some(("vendor", "directory", vendor.to_string_lossy().into_owned()))
+    } else if let Some(mirror) = config.registry.mirror() {
+        let url = if mirror.contains("://") {
+            mirror.to_string()
+        } else {
+            // Local git mirrors are configured with their path
+            format!("file://{}", current_dir.join(mirror).display())
+        };
+        Some(("mirror", "registry", url))
+    } else {
+        None
+    };
+    if let Some((name, key, value)) = replacement {
+        let name = format!("{}{}", CARGO_SOURCE_PREFIX, name);
+        crates_io.insert("replace-with".into(), name.as_str().into());
+        subtable(sources, &name)?.insert(key.into(), value.into());
+    }
+
+    // Don't leave empty tables behind
+    for key in &["registries", "source"] {
+        if let Some(subtable) = table.get_mut(*key).and_then(|value| value.as_table_mut()) {
+            subtable.retain(|_, value| !matches!(value.as_table(), Some(t) if t.is_empty()));
+            if subtable.is_empty() {
+                table.remove(*key);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub fn run_ex<DB: WriteResults + Sync>(
     ex: &Experiment,
     workspace: &Workspace,
@@ -96,9 +209,36 @@ pub fn run_ex<DB: WriteResults + Sync>(
     info!("preparing the execution...");
     for tc in &ex.toolchains {
         tc.install(workspace)?;
+
+        // Components and targets can't be added to local toolchains, they must already
+        // include everything the experiment needs
+        if tc.local_path.is_some() {
+            if !tc.components.is_empty() || !tc.targets.is_empty() || ex.target.is_some() {
+                warn!(
+                    "not installing extra components or targets on local toolchain {}",
+                    tc
+                );
+            }
+            continue;
+        }
+
         if ex.mode == Mode::Clippy {
             tc.add_component(workspace, "clippy")?;
         }
+        for component in &tc.components {
+            tc.add_component(workspace, component)?;
+        }
+        for target in tc.targets.iter().chain(&ex.target) {
+            tc.add_target(workspace, target)?;
+        }
+    }
+
+    setup_cargo_config(config)?;
+    update_alt_registries(config)?;
+
+    let toolchain_patches = ex.toolchains.iter().flat_map(|tc| &tc.patches);
+    for patch in ex.patches.iter().chain(toolchain_patches) {
+        patch::prepare(workspace, patch)?;
     }
 
     info!("running tasks in {} threads...", threads_count);
@@ -190,3 +330,78 @@ where
     }
     clean_exit
 }
+
+#[cfg(test)]
+mod tests {
+    use super::update_cargo_config;
+    use crate::config::Config;
+    use std::path::Path;
+
+    #[test]
+    fn test_update_cargo_config() {
+        let mut config = Config::default();
+        config.registry.vendor = Some("vendor".into());
+        let mut table = "
+            [build]
+            jobs = 2
+
+            [source.crates-io]
+            replace-with = 'crater-old'
+
+            [source.crater-old]
+            directory = '/old'
+
+            [source.custom]
+            registry = 'https://example.com/index'
+        "
+        .parse::<toml::Value>()
+        .unwrap()
+        .as_table()
+        .unwrap()
+        .clone();
+
+        update_cargo_config(&mut table, &config, Path::new("/crater")).unwrap();
+        let expected = "
+            [build]
+            jobs = 2
+
+            [source.crates-io]
+            replace-with = 'crater-vendor'
+
+            [source.crater-vendor]
+            directory = '/crater/vendor'
+
+            [source.custom]
+            registry = 'https://example.com/index'
+        "
+        .parse::<toml::Value>()
+        .unwrap();
+        assert_eq!(toml::Value::Table(table.clone()), expected);
+
+        // Only the keys owned by Crater are removed
+        config.registry.vendor = None;
+        update_cargo_config(&mut table, &config, Path::new("/crater")).unwrap();
+        let expected = "
+            [build]
+            jobs = 2
+
+            [source.custom]
+            registry = 'https://example.com/index'
+        "
+        .parse::<toml::Value>()
+        .unwrap();
+        assert_eq!(toml::Value::Table(table.clone()), expected);
+
+        config.registry.index = "sparse+https://mirror.example.com/index/".into();
+        update_cargo_config(&mut table, &config, Path::new("/crater")).unwrap();
+        let sources = table["source"].as_table().unwrap();
+        assert_eq!(
+            sources["crates-io"]["replace-with"].as_str(),
+            Some("crater-mirror")
+        );
+        assert_eq!(
+            sources["crater-mirror"]["registry"].as_str(),
+            Some("sparse+https://mirror.example.com/index/")
+        );
+    }
+}
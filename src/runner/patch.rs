@@ -0,0 +1,79 @@
+use crate::dirs::WORK_DIR;
+use crate::prelude::*;
+use crate::toolchain::{CratePatch, GitReference, PatchSource};
+use remove_dir_all::remove_dir_all;
+use rustwide::cmd::Command;
+use rustwide::{Crate as RustwideCrate, Workspace};
+use std::env;
+use std::path::PathBuf;
+
+/// How a patch is passed to rustwide.
+pub(super) enum ResolvedPatch<'a> {
+    Git { repo: &'a str, branch: &'a str },
+    Path(PathBuf),
+}
+
+/// Directory the patches Cargo can't fetch on its own are downloaded to. The path is absolute,
+/// as it's mounted at the same location inside the sandbox.
+fn download_dir(patch: &CratePatch) -> Fallible<PathBuf> {
+    Ok(env::current_dir()?
+        .join(&*WORK_DIR)
+        .join("patches")
+        .join(patch.to_path_component()))
+}
+
+/// Download the source of the patch, if Cargo can't fetch it by itself. This needs to be done
+/// before any build starts, as builds have no network access.
+pub(super) fn prepare(workspace: &Workspace, patch: &CratePatch) -> Fallible<()> {
+    let fetched_by_cargo = matches!(
+        patch.source,
+        PatchSource::Git {
+            reference: GitReference::Branch(_),
+            ..
+        } | PatchSource::Path { .. }
+    );
+    if fetched_by_cargo {
+        return Ok(());
+    }
+
+    let dest = download_dir(patch)?;
+    if dest.exists() {
+        remove_dir_all(&dest)?;
+    }
+
+    info!("downloading patch {}", patch);
+    match patch.source {
+        PatchSource::Git {
+            ref repo,
+            ref reference,
+        } => {
+            Command::new(workspace, "git")
+                .args(&["clone", repo.as_str()])
+                .args(&[&dest])
+                .run()?;
+            Command::new(workspace, "git")
+                .args(&["checkout", reference.name()])
+                .cd(&dest)
+                .run()?;
+        }
+        PatchSource::Registry { ref version } => {
+            let krate = RustwideCrate::crates_io(&patch.name, version);
+            krate.fetch(workspace)?;
+            krate.copy_source_to(workspace, &dest)?;
+        }
+        PatchSource::Path { .. } => unreachable!(),
+    }
+
+    Ok(())
+}
+
+pub(super) fn resolve(patch: &CratePatch) -> Fallible<ResolvedPatch> {
+    Ok(match patch.source {
+        PatchSource::Git {
+            ref repo,
+            reference: GitReference::Branch(ref branch),
+        } => ResolvedPatch::Git { repo, branch },
+        PatchSource::Path { ref path } => ResolvedPatch::Path(env::current_dir()?.join(path)),
+        _ => ResolvedPatch::Path(download_dir(patch)?),
+    })
+}
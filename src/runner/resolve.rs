@@ -0,0 +1,230 @@
+use crate::prelude::*;
+use crate::results::{EncodingType, FailureReason, ResolvedDependency, TestResult, WriteResults};
+use crate::runner::lockfile;
+use crate::runner::tasks::TaskCtx;
+use rustwide::{Crate as RustwideCrate, Workspace};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+    resolve: Option<MetadataResolve>,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    id: String,
+    name: String,
+    version: String,
+    /// Missing for the packages of the crate itself and its path dependencies.
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MetadataResolve {
+    nodes: Vec<MetadataNode>,
+}
+
+#[derive(Deserialize)]
+struct MetadataNode {
+    id: String,
+    #[serde(default)]
+    features: Vec<String>,
+}
+
+/// Resolve the dependencies of the crate with the cargo of the toolchain, without building it.
+/// Resolution failures are recorded as build failures, so that crates Cargo can't resolve
+/// anymore show up as regressions.
+pub(super) fn run_resolve<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    workspace: &Workspace,
+) -> Fallible<()> {
+    if let Some(res) = ctx
+        .db
+        .get_result(ctx.experiment, ctx.toolchain, ctx.krate)?
+    {
+        info!("skipping crate {}. existing result: {}", ctx.krate, res);
+        return Ok(());
+    }
+
+    let log_storage = ctx
+        .state
+        .lock()
+        .prepare_logs
+        .get(ctx.krate)
+        .map(|s| s.duplicate());
+    ctx.db.record_result(
+        ctx.experiment,
+        ctx.toolchain,
+        ctx.krate,
+        log_storage,
+        ctx.config,
+        EncodingType::Plain,
+        || {
+            info!(
+                "resolving {} against {} for {}",
+                ctx.krate,
+                ctx.toolchain.to_string(),
+                ctx.experiment.name
+            );
+
+            // Every toolchain writes its own lockfile, so they can't share the checkout
+            let tmp = tempfile::tempdir()?;
+            let source = copy_checkout(ctx, workspace, tmp.path())?;
+
+            // The index was updated when preparing the crate, so that both toolchains resolve
+            // against the same versions even if crates are published between their runs
+            let generated = lockfile::cargo(
+                ctx,
+                workspace,
+                &source,
+                &["generate-lockfile", "--offline"],
+                &[],
+            );
+            if let Err(err) = generated {
+                error!("failed to resolve the dependencies: {}", err);
+                return Ok(TestResult::BuildFail(FailureReason::Unknown));
+            }
+            let metadata = match lockfile::cargo(
+                ctx,
+                workspace,
+                &source,
+                &["metadata", "--format-version=1", "--offline"],
+                &[],
+            ) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    error!("failed to load the dependency graph: {}", err);
+                    return Ok(TestResult::BuildFail(FailureReason::Unknown));
+                }
+            };
+            ctx.db.record_resolved_dependencies(
+                ctx.experiment,
+                ctx.toolchain,
+                ctx.krate,
+                parse_metadata(&metadata)?,
+            )?;
+
+            Ok(TestResult::TestPass)
+        },
+    )?;
+    Ok(())
+}
+
+/// Update the registry index with the packages the toolchain needs to resolve the dependencies of
+/// the crate, before both toolchains resolve them offline. Failures are only logged, as resolving
+/// offline fails as well and is recorded as the result of the toolchain.
+pub(super) fn update_index<DB: WriteResults>(ctx: &TaskCtx<DB>, workspace: &Workspace) {
+    let updated = tempfile::tempdir()
+        .map_err(failure::Error::from)
+        .and_then(|tmp| {
+            let source = copy_checkout(ctx, workspace, tmp.path())?;
+            lockfile::cargo(ctx, workspace, &source, &["generate-lockfile"], &[])
+        });
+    if let Err(err) = updated {
+        warn!(
+            "failed to update the index for {} with {}: {}",
+            ctx.krate, ctx.toolchain, err
+        );
+    }
+}
+
+fn copy_checkout<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    workspace: &Workspace,
+    tmp: &Path,
+) -> Fallible<PathBuf> {
+    let source = tmp.join("source");
+    RustwideCrate::local(&ctx.krate.checkout_manifest().0).copy_source_to(workspace, &source)?;
+    Ok(source)
+}
+
+/// Packages from outside of the crate in the dependency graph, along with the features Cargo
+/// enabled on them.
+fn parse_metadata(metadata: &str) -> Fallible<Vec<ResolvedDependency>> {
+    let metadata: Metadata = serde_json::from_str(metadata)?;
+    let packages: HashMap<_, _> = metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_some())
+        .map(|package| (package.id.as_str(), package))
+        .collect();
+
+    let mut dependencies: Vec<_> = metadata
+        .resolve
+        .ok_or_else(|| err_msg("missing dependency graph in cargo metadata"))?
+        .nodes
+        .into_iter()
+        .filter_map(|node| {
+            let package = packages.get(node.id.as_str())?;
+            let mut features = node.features;
+            features.sort();
+            Some(ResolvedDependency {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                features,
+            })
+        })
+        .collect();
+    dependencies.sort();
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_metadata;
+    use crate::results::ResolvedDependency;
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata = r#"{
+            "packages": [
+                {"id": "app 0.1.0 (path+file:///app)", "name": "app", "version": "0.1.0", "source": null},
+                {
+                    "id": "serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)",
+                    "name": "serde",
+                    "version": "1.0.0",
+                    "source": "registry+https://github.com/rust-lang/crates.io-index"
+                },
+                {
+                    "id": "log 0.4.0 (registry+https://github.com/rust-lang/crates.io-index)",
+                    "name": "log",
+                    "version": "0.4.0",
+                    "source": "registry+https://github.com/rust-lang/crates.io-index"
+                }
+            ],
+            "resolve": {
+                "nodes": [
+                    {"id": "app 0.1.0 (path+file:///app)", "features": ["default"]},
+                    {
+                        "id": "serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)",
+                        "features": ["std", "derive", "default"]
+                    },
+                    {
+                        "id": "log 0.4.0 (registry+https://github.com/rust-lang/crates.io-index)",
+                        "features": []
+                    }
+                ]
+            }
+        }"#;
+
+        assert_eq!(
+            parse_metadata(metadata).unwrap(),
+            vec![
+                ResolvedDependency {
+                    name: "log".into(),
+                    version: "0.4.0".into(),
+                    features: Vec::new(),
+                },
+                ResolvedDependency {
+                    name: "serde".into(),
+                    version: "1.0.0".into(),
+                    features: vec!["default".into(), "derive".into(), "std".into()],
+                },
+            ]
+        );
+
+        assert!(parse_metadata(r#"{"packages": [], "resolve": null}"#).is_err());
+    }
+}
@@ -1,13 +1,15 @@
 use crate::config::Config;
 use crate::crates::{Crate, GitHubRepo};
-use crate::experiments::Experiment;
+use crate::experiments::{Experiment, Lockfile, Mode};
 use crate::prelude::*;
-use crate::results::{EncodingType, TestResult, WriteResults};
+use crate::results::{EncodingType, FailureReason, TestResult, WriteResults};
 use crate::runner::test::detect_broken;
-use crate::runner::{test, RunnerState};
+use crate::runner::{lockfile, resolve, test, RunnerState};
 use crate::toolchain::Toolchain;
 use crate::utils;
+use remove_dir_all::remove_dir_all;
 use rustwide::{Build, BuildDirectory, Workspace};
+use std::collections::BTreeMap;
 use std::sync::Mutex;
 
 use rustwide::logging::{self, LogStorage};
@@ -22,6 +24,10 @@ pub(super) struct TaskCtx<'ctx, DB: WriteResults + 'ctx> {
     pub(super) krate: &'ctx Crate,
     pub(super) state: &'ctx RunnerState,
     pub(super) quiet: bool,
+    /// Workspace members whose own code failed to compile, keyed by package name.
+    pub(super) member_failures: Mutex<BTreeMap<String, FailureReason>>,
+    /// Workspace member cargo is restricted to, when testing the members on their own.
+    pub(super) member: Option<&'ctx str>,
 }
 
 impl<'ctx, DB: WriteResults + 'ctx> TaskCtx<'ctx, DB> {
@@ -44,6 +50,8 @@ impl<'ctx, DB: WriteResults + 'ctx> TaskCtx<'ctx, DB> {
             krate,
             state,
             quiet,
+            member_failures: Mutex::new(BTreeMap::new()),
+            member: None,
         }
     }
 }
@@ -58,6 +66,7 @@ pub(super) enum TaskStep {
     Clippy { tc: Toolchain, quiet: bool },
     Rustdoc { tc: Toolchain, quiet: bool },
     UnstableFeatures { tc: Toolchain },
+    Resolve { tc: Toolchain },
 }
 
 impl fmt::Debug for TaskStep {
@@ -72,6 +81,7 @@ impl fmt::Debug for TaskStep {
             TaskStep::Clippy { ref tc, quiet } => ("clippy", quiet, Some(tc)),
             TaskStep::Rustdoc { ref tc, quiet } => ("doc", quiet, Some(tc)),
             TaskStep::UnstableFeatures { ref tc } => ("find unstable features on", false, Some(tc)),
+            TaskStep::Resolve { ref tc } => ("resolve", false, Some(tc)),
         };
 
         write!(f, "{}", name)?;
@@ -114,7 +124,8 @@ impl Task {
             | TaskStep::CheckOnly { ref tc, .. }
             | TaskStep::Clippy { ref tc, .. }
             | TaskStep::Rustdoc { ref tc, .. }
-            | TaskStep::UnstableFeatures { ref tc } => {
+            | TaskStep::UnstableFeatures { ref tc }
+            | TaskStep::Resolve { ref tc } => {
                 let log_storage = state
                     .lock()
                     .prepare_logs
@@ -171,9 +182,23 @@ impl Task {
                     tc,
                     false,
                 ),
+                TaskStep::Resolve { ref tc } => {
+                    let ctx =
+                        TaskCtx::new(build_dir, config, db, ex, tc, &self.krate, state, false);
+                    resolve::run_resolve(&ctx, workspace)?;
+                    return Ok(());
+                }
                 TaskStep::Cleanup => {
-                    // Remove stored logs
-                    state.lock().prepare_logs.remove(&self.krate);
+                    // Remove stored logs and results
+                    {
+                        let mut state = state.lock();
+                        state.prepare_logs.remove(&self.krate);
+                        state.start_results.remove(&self.krate);
+                    }
+                    let checkout = self.krate.checkout_dir();
+                    if checkout.exists() {
+                        remove_dir_all(&checkout)?;
+                    }
                     return Ok(());
                 }
                 TaskStep::Prepare => {
@@ -183,7 +208,11 @@ impl Task {
                         .prepare_logs
                         .insert(self.krate.clone(), storage.clone());
                     logging::capture(&storage, || {
-                        let rustwide_crate = self.krate.to_rustwide();
+                        if let Crate::AltRegistry(krate) = &self.krate {
+                            krate.download(config)?;
+                        }
+
+                        let rustwide_crate = self.krate.to_rustwide_repo(config);
                         for attempt in 1..=15 {
                             match detect_broken(rustwide_crate.fetch(workspace)) {
                                 Ok(()) => break,
@@ -240,6 +269,42 @@ impl Task {
                                 bail!("unable to capture sha for {}", repo.slug());
                             }
                         }
+
+                        if self.krate.needs_checkout(ex) {
+                            // The builds copy the crate out of this checkout
+                            let checkout = self.krate.checkout_dir();
+                            if checkout.exists() {
+                                remove_dir_all(&checkout)?;
+                            }
+                            std::fs::create_dir_all(checkout.parent().unwrap())?;
+                            rustwide_crate.copy_source_to(workspace, &checkout)?;
+                            // Cargo runs on the checkout outside of the sandbox to resolve the
+                            // dependencies
+                            lockfile::remove_cargo_configs(&checkout)?;
+
+                            // rustwide resolves the dependencies of crates without a lockfile
+                            let lockfile = self.krate.checkout_manifest().0.join("Cargo.lock");
+                            if ex.lockfile != Lockfile::Keep && lockfile.exists() {
+                                std::fs::remove_file(&lockfile)?;
+                            }
+
+                            // Both toolchains resolve against the index as updated here
+                            if ex.mode == Mode::Resolve {
+                                for tc in &ex.toolchains {
+                                    let ctx = TaskCtx::new(
+                                        build_dir,
+                                        config,
+                                        db,
+                                        ex,
+                                        tc,
+                                        &self.krate,
+                                        state,
+                                        false,
+                                    );
+                                    resolve::update_index(&ctx, workspace);
+                                }
+                            }
+                        }
                         Ok(())
                     })?;
                     return Ok(());
@@ -274,7 +339,7 @@ impl Task {
             state,
             quiet,
         );
-        test::run_test(action, &ctx, test)?;
+        test::run_test(action, &ctx, workspace, test)?;
 
         Ok(())
     }
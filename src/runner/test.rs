@@ -1,7 +1,11 @@
 use crate::crates::Crate;
+use crate::experiments::Experiment;
 use crate::prelude::*;
+use crate::report::{compare, Comparison};
 use crate::results::DiagnosticCode;
 use crate::results::{BrokenReason, EncodingType, FailureReason, TestResult, WriteResults};
+use crate::runner::lockfile;
+use crate::runner::patch::{self, ResolvedPatch};
 use crate::runner::tasks::TaskCtx;
 use crate::runner::OverrideResult;
 use cargo_metadata::diagnostic::DiagnosticLevel;
@@ -9,10 +13,11 @@ use cargo_metadata::{Message, Metadata, Package, Target};
 use docsrs_metadata::Metadata as DocsrsMetadata;
 use failure::Error;
 use remove_dir_all::remove_dir_all;
-use rustwide::cmd::{CommandError, ProcessLinesActions, SandboxBuilder};
-use rustwide::{Build, PrepareError};
-use std::collections::{BTreeSet, HashMap, HashSet};
+use rustwide::cmd::{CommandError, MountKind, ProcessLinesActions, SandboxBuilder};
+use rustwide::{Build, PrepareError, Workspace};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::TryFrom;
+use std::sync::Mutex;
 
 fn failure_reason(err: &Error) -> FailureReason {
     for cause in err.iter_chain() {
@@ -65,19 +70,49 @@ pub(super) fn detect_broken<T>(res: Result<T, Error>) -> Result<T, Error> {
     }
 }
 
-fn get_local_packages(build_env: &Build) -> Fallible<Vec<Package>> {
+/// Packages of the crate, leaving out the other members of the workspace it belongs to.
+fn get_local_packages<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    build_env: &Build,
+) -> Fallible<Vec<Package>> {
+    let manifest = ctx.krate.checkout_manifest().1;
     Ok(build_env
         .cargo()
-        .args(&["metadata", "--no-deps", "--format-version=1"])
+        .args(&[
+            "metadata",
+            "--no-deps",
+            "--format-version=1",
+            "--manifest-path",
+        ])
+        .args(&[&manifest])
         .log_output(false)
         .run_capture()?
         .stdout_lines()
         .iter()
         .filter_map(|line| serde_json::from_str::<Metadata>(line).ok())
-        .flat_map(|metadata| metadata.packages)
+        .flat_map(|metadata| {
+            let crate_dir = metadata.workspace_root.join(manifest.parent().unwrap());
+            metadata
+                .packages
+                .into_iter()
+                .filter(move |package| package.manifest_path.starts_with(&crate_dir))
+        })
         .collect())
 }
 
+/// Package cargo failed to build, with its version when cargo mentions it.
+fn failed_package(line: &str) -> Option<(&str, Option<&str>)> {
+    let package = [
+        "error: could not compile `",
+        "error: failed to run custom build command for `",
+    ]
+    .iter()
+    .find_map(|prefix| line.strip_prefix(prefix))?;
+    let mut parts = package.split('`').next()?.split(' ');
+    let name = parts.next()?;
+    Some((name, parts.next().map(|v| v.trim_start_matches('v'))))
+}
+
 fn run_cargo<DB: WriteResults>(
     ctx: &TaskCtx<DB>,
     build_env: &Build,
@@ -86,29 +121,47 @@ fn run_cargo<DB: WriteResults>(
     local_packages: &[Package],
     env: HashMap<&'static str, String>,
 ) -> Fallible<()> {
-    let local_packages_id: HashSet<_> = local_packages.iter().map(|p| &p.id).collect();
+    let local_packages_names: HashMap<_, _> =
+        local_packages.iter().map(|p| (&p.id, &p.name)).collect();
 
+    let manifest = ctx.krate.checkout_manifest().1;
     let mut args = args.to_vec();
+    // Crates in a subdirectory are built from the root of their workspace
+    args.extend(&["--manifest-path", manifest.to_str().unwrap()]);
     if let Some(ref tc_cargoflags) = ctx.toolchain.cargoflags {
         args.extend(tc_cargoflags.split(' '));
     }
-
-    let mut rustflags = format!("--cap-lints={}", ctx.experiment.cap_lints.to_str());
-    if let Some(ref tc_rustflags) = ctx.toolchain.rustflags {
-        rustflags.push(' ');
-        rustflags.push_str(tc_rustflags);
+    if let Some(ref target) = ctx.experiment.target {
+        args.extend(&["--target", target.as_str()]);
     }
-
-    let mut rustdocflags = format!("--cap-lints={}", ctx.experiment.cap_lints.to_str());
-    if let Some(ref tc_rustdocflags) = ctx.toolchain.rustdocflags {
-        rustdocflags.push(' ');
-        rustdocflags.push_str(tc_rustdocflags);
+    if let Some(member) = ctx.member {
+        // `--all` would take precedence over the package
+        args.retain(|arg| *arg != "--all");
+        args.extend(&["-p", member]);
     }
 
+    // Flags set through the environment are merged with the others, as they would otherwise
+    // replace the lints cap
+    let merge_flags = |var: &str, tc_flags: &Option<String>| {
+        let mut flags = format!("--cap-lints={}", ctx.experiment.cap_lints.to_str());
+        let extra = [ctx.toolchain.env.get(var), tc_flags.as_ref(), env.get(var)];
+        for extra in extra.iter().flatten() {
+            flags.push(' ');
+            flags.push_str(extra);
+        }
+        flags
+    };
+    let rustflags = merge_flags("RUSTFLAGS", &ctx.toolchain.rustflags);
+    let rustdocflags = merge_flags("RUSTDOCFLAGS", &ctx.toolchain.rustdocflags);
+
     let mut did_ice = false;
     let mut did_network = false;
     let mut error_codes = BTreeSet::new();
     let mut deps = BTreeSet::new();
+    let mut failed_packages = BTreeSet::new();
+    // Errors of every workspace member, to find out which members failed
+    let mut member_codes: BTreeMap<String, BTreeSet<DiagnosticCode>> = BTreeMap::new();
+    let mut member_ices = BTreeSet::new();
 
     let mut detect_error = |line: &str, actions: &mut ProcessLinesActions| {
         if line.contains("urlopen error") && line.contains("Temporary failure in name resolution") {
@@ -121,6 +174,12 @@ fn run_cargo<DB: WriteResults>(
             did_network = true;
         }
 
+        if let Some((name, version)) = failed_package(line) {
+            if !local_packages.iter().any(|p| p.name == name) {
+                failed_packages.insert((name.to_string(), version.map(|v| v.to_string())));
+            }
+        }
+
         // Avoid trying to deserialize non JSON output
         if !line.starts_with('{') {
             return;
@@ -136,12 +195,17 @@ fn run_cargo<DB: WriteResults>(
                 let inner_message = compiler_message.message;
                 match (inner_message.level, &compiler_message.package_id) {
                     // the only local crate in a well defined job is the crate currently being tested
-                    (DiagnosticLevel::Error, pkgid) if local_packages_id.contains(pkgid) => {
+                    (DiagnosticLevel::Error, pkgid) if local_packages_names.contains_key(pkgid) => {
+                        let codes = member_codes
+                            .entry(local_packages_names[pkgid].clone())
+                            .or_default();
                         if let Some(code) = inner_message.code {
-                            error_codes.insert(DiagnosticCode::from(code.code));
+                            error_codes.insert(DiagnosticCode::from(code.code.clone()));
+                            codes.insert(DiagnosticCode::from(code.code));
                         }
                     }
-                    (DiagnosticLevel::Ice, pkgid) if local_packages_id.contains(pkgid) => {
+                    (DiagnosticLevel::Ice, pkgid) if local_packages_names.contains_key(pkgid) => {
+                        member_ices.insert(local_packages_names[pkgid].clone());
                         did_ice = true
                     }
                     // If the error is in a crate that is not local then it's referred to a dependency
@@ -172,8 +236,16 @@ fn run_cargo<DB: WriteResults>(
         .env("RUST_BACKTRACE", "full")
         .env("RUSTFLAGS", rustflags)
         .env("RUSTDOCFLAGS", rustdocflags);
+    let is_flags = |var: &str| var == "RUSTFLAGS" || var == "RUSTDOCFLAGS";
+    for (var, data) in &ctx.toolchain.env {
+        if !is_flags(var) {
+            command = command.env(var, data);
+        }
+    }
     for (var, data) in env {
-        command = command.env(var, data);
+        if !is_flags(var) {
+            command = command.env(var, data);
+        }
     }
 
     if check_errors {
@@ -184,9 +256,40 @@ fn run_cargo<DB: WriteResults>(
         command = command.no_output_timeout(None);
     }
 
-    match command.run() {
+    let res = command.run();
+
+    let mut member_failures = ctx.member_failures.lock().unwrap();
+    for (member, codes) in member_codes {
+        let reason = if codes.is_empty() {
+            FailureReason::Unknown
+        } else {
+            FailureReason::CompilerError(codes)
+        };
+        member_failures.insert(member, reason);
+    }
+    for member in member_ices {
+        // Compiler crashes take precedence over the errors emitted before them
+        member_failures.insert(member, FailureReason::ICE);
+    }
+
+    match res {
         Ok(()) => Ok(()),
         Err(e) => {
+            // Dependencies failing without a diagnostic, like when their build script fails, are
+            // only named in cargo's own errors
+            for (name, version) in &failed_packages {
+                match lockfile::locked_dependency(build_env, name, version.as_deref()) {
+                    Ok(Some(krate)) => {
+                        deps.insert(krate);
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(
+                        "failed to find dependency {} in the lockfile: {}",
+                        name, err
+                    ),
+                }
+            }
+
             if did_ice {
                 Err(e.context(FailureReason::ICE).into())
             } else if !deps.is_empty() {
@@ -205,6 +308,7 @@ fn run_cargo<DB: WriteResults>(
 pub(super) fn run_test<DB: WriteResults>(
     action: &str,
     ctx: &TaskCtx<DB>,
+    workspace: &Workspace,
     test_fn: fn(&TaskCtx<DB>, &Build, &[Package]) -> Fallible<TestResult>,
 ) -> Fallible<()> {
     if let Some(res) = ctx
@@ -212,6 +316,7 @@ pub(super) fn run_test<DB: WriteResults>(
         .get_result(ctx.experiment, ctx.toolchain, ctx.krate)?
     {
         info!("skipping crate {}. existing result: {}", ctx.krate, res);
+        record_start_result(ctx, &res);
     } else {
         let log_storage = ctx
             .state
@@ -234,21 +339,95 @@ pub(super) fn run_test<DB: WriteResults>(
                     ctx.toolchain.to_string(),
                     ctx.experiment.name
                 );
-                let sandbox = SandboxBuilder::new()
+                // Patches of the toolchain take precedence over the experiment-wide ones
+                let patches = ctx
+                    .experiment
+                    .patches
+                    .iter()
+                    .filter(|p| !ctx.toolchain.patches.iter().any(|tp| tp.name == p.name))
+                    .chain(ctx.toolchain.patches.iter())
+                    .map(|p| Ok((p.name.as_str(), patch::resolve(p)?)))
+                    .collect::<Fallible<Vec<_>>>()?;
+
+                let mut sandbox = SandboxBuilder::new()
                     .memory_limit(Some(ctx.config.sandbox.memory_limit.to_bytes()))
                     .enable_networking(false);
+                for (_, resolved) in &patches {
+                    if let ResolvedPatch::Path(path) = resolved {
+                        // Cargo needs to find the patch at the same path inside the sandbox
+                        sandbox = sandbox.mount(path, path, MountKind::ReadOnly);
+                    }
+                }
+                if let Some(ref vendor) = ctx.config.registry.vendor {
+                    // The Cargo configuration points to the vendored sources with their host path
+                    let vendor = std::env::current_dir()?.join(vendor);
+                    sandbox = sandbox.mount(&vendor, &vendor, MountKind::ReadOnly);
+                }
+
+                // The start toolchain builds the crate with the newest dependencies as a baseline,
+                // and rustwide uses the lockfile written to the checkout for the end toolchain
+                let minimal_versions = ctx
+                    .experiment
+                    .lockfile
+                    .minimal_versions_flag()
+                    .filter(|_| ctx.toolchain == &ctx.experiment.toolchains[1]);
+                if let Some(flag) = minimal_versions {
+                    if let Err(err) = lockfile::generate_minimal_lockfile(ctx, workspace, flag) {
+                        // Failing to resolve isn't caused by the toolchain, so it's not reported
+                        // as a regression
+                        error!("failed to resolve the minimal versions: {}", err);
+                        return Ok(TestResult::BrokenCrate(BrokenReason::MinimalVersions));
+                    }
+                }
 
-                let krate = &ctx.krate.to_rustwide();
+                let krate = &ctx.krate.to_rustwide(ctx.config, ctx.experiment);
                 let mut build_dir = ctx.build_dir.lock().unwrap();
                 let mut build = build_dir.build(ctx.toolchain, krate, sandbox);
 
-                for patch in ctx.toolchain.patches.iter() {
-                    build = build.patch_with_git(&patch.name, &patch.repo, &patch.branch);
+                for (name, resolved) in &patches {
+                    build = match resolved {
+                        ResolvedPatch::Git { repo, branch } => {
+                            build.patch_with_git(name, repo, branch)
+                        }
+                        ResolvedPatch::Path(path) => {
+                            build.patch_with_path(name, &path.to_string_lossy())
+                        }
+                    };
                 }
 
                 detect_broken(build.run(|build| {
-                    let local_packages = get_local_packages(build)?;
-                    test_fn(ctx, build, &local_packages)
+                    // rustwide generated the lockfile of crates without one while preparing them
+                    match lockfile::resolved_dependencies(build) {
+                        Ok(dependencies) => ctx.db.record_resolved_dependencies(
+                            ctx.experiment,
+                            ctx.toolchain,
+                            ctx.krate,
+                            dependencies,
+                        )?,
+                        Err(err) => warn!("failed to record the resolved dependencies: {}", err),
+                    }
+
+                    let local_packages = get_local_packages(ctx, build)?;
+                    let result = test_fn(ctx, build, &local_packages)?;
+                    record_start_result(ctx, &result);
+                    if let Some(host) = host_result(ctx, build, &local_packages, &result, test_fn) {
+                        ctx.db.record_host_result(
+                            ctx.experiment,
+                            ctx.toolchain,
+                            ctx.krate,
+                            host,
+                        )?;
+                    }
+                    if local_packages.len() > 1 {
+                        let members = member_results(ctx, build, &local_packages, &result, test_fn);
+                        ctx.db.record_member_results(
+                            ctx.experiment,
+                            ctx.toolchain,
+                            ctx.krate,
+                            members,
+                        )?;
+                    }
+                    Ok(result)
                 }))
             },
         )?;
@@ -256,6 +435,95 @@ pub(super) fn run_test<DB: WriteResults>(
     Ok(())
 }
 
+/// Result of building the crate for the host when the end toolchain failed to build it for the
+/// target of the experiment, to tell target-specific regressions apart from the others.
+fn host_result<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    build: &Build,
+    local_packages: &[Package],
+    result: &TestResult,
+    test_fn: fn(&TaskCtx<DB>, &Build, &[Package]) -> Fallible<TestResult>,
+) -> Option<TestResult> {
+    if ctx.experiment.target.is_none()
+        || ctx.toolchain != &ctx.experiment.toolchains[1]
+        || !matches!(result, TestResult::BuildFail(_))
+    {
+        return None;
+    }
+
+    info!("checking whether {} builds for the host", ctx.krate);
+    let experiment = Experiment {
+        target: None,
+        ..ctx.experiment.clone()
+    };
+    let host_ctx = TaskCtx {
+        experiment: &experiment,
+        member_failures: Mutex::new(BTreeMap::new()),
+        ..*ctx
+    };
+    match test_fn(&host_ctx, build, local_packages) {
+        Ok(result) => Some(result),
+        Err(err) => {
+            warn!("failed to build {} for the host: {}", ctx.krate, err);
+            None
+        }
+    }
+}
+
+fn record_start_result<DB: WriteResults>(ctx: &TaskCtx<DB>, result: &TestResult) {
+    if ctx.toolchain == &ctx.experiment.toolchains[0] {
+        ctx.state
+            .lock()
+            .start_results
+            .insert(ctx.krate.clone(), result.clone());
+    }
+}
+
+/// Results of the members of a workspace. Members with errors in their own code failed to build,
+/// and the others share the result of the workspace, unless it regressed with the end toolchain.
+/// They are then tested on their own with `-p`, as Cargo stops at the first failure and test
+/// failures can't be attributed to a member.
+fn member_results<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    build: &Build,
+    local_packages: &[Package],
+    result: &TestResult,
+    test_fn: fn(&TaskCtx<DB>, &Build, &[Package]) -> Fallible<TestResult>,
+) -> BTreeMap<String, TestResult> {
+    let failures = ctx.member_failures.lock().unwrap().clone();
+    let regressed = ctx.toolchain == &ctx.experiment.toolchains[1]
+        && ctx
+            .state
+            .lock()
+            .start_results
+            .get(ctx.krate)
+            .map(|start| compare(ctx.config, ctx.krate, Some(start), Some(result)))
+            == Some(Comparison::Regressed);
+
+    local_packages
+        .iter()
+        .filter_map(|package| match failures.get(&package.name) {
+            Some(reason) => Some((package.name.clone(), TestResult::BuildFail(reason.clone()))),
+            None if !regressed => Some((package.name.clone(), result.clone())),
+            None => {
+                info!("testing workspace member {} of {}", package.name, ctx.krate);
+                let member_ctx = TaskCtx {
+                    member_failures: Mutex::new(BTreeMap::new()),
+                    member: Some(&package.name),
+                    ..*ctx
+                };
+                match test_fn(&member_ctx, build, local_packages) {
+                    Ok(result) => Some((package.name.clone(), result)),
+                    Err(err) => {
+                        warn!("failed to test workspace member {}: {}", package.name, err);
+                        None
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
 fn build<DB: WriteResults>(
     ctx: &TaskCtx<DB>,
     build_env: &Build,
@@ -408,7 +676,8 @@ pub(super) fn test_rustdoc<DB: WriteResults>(
         .iter()
         .any(|p| p.targets.iter().any(is_library))
     {
-        let src = build_env.host_source_dir();
+        let manifest = ctx.krate.checkout_manifest().1;
+        let src = build_env.host_source_dir().join(manifest.parent().unwrap());
         let metadata = DocsrsMetadata::from_crate_root(src)?;
         let cargo_args = metadata.cargo_args(
             &["--frozen".into(), "--message-format=json".into()],
@@ -151,6 +151,7 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
                                 quiet,
                             },
                             Mode::UnstableFeatures => TaskStep::UnstableFeatures { tc: tc.clone() },
+                            Mode::Resolve => TaskStep::Resolve { tc: tc.clone() },
                         },
                     });
                 }
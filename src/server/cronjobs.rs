@@ -47,6 +47,7 @@ fn update_crates(data: Arc<Data>) -> Fallible<()> {
     UpdateLists {
         github: true,
         registry: true,
+        alt_registries: true,
         local: false,
     }
     .apply(&ctx)
@@ -0,0 +1,140 @@
+use crate::experiments::Status;
+use crate::prelude::*;
+use crate::report::{self, ExperimentsDiff};
+use crate::server::Data;
+use crate::utils;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+// Number of diffs kept in memory, the oldest ones are evicted first
+const CACHED_DIFFS: usize = 50;
+// Diffs of experiments that aren't completed yet are recomputed after 10 minutes, as their
+// results keep changing
+const INCOMPLETE_DIFF_TTL: Duration = Duration::from_secs(600);
+
+type DiffKey = (String, String);
+
+pub enum DiffStatus {
+    Pending,
+    Ready(Arc<ExperimentsDiff>),
+    Failed(String),
+}
+
+struct CachedDiff {
+    result: Result<Arc<ExperimentsDiff>, String>,
+    computed_at: Instant,
+    /// Whether both experiments were completed, and the diff can't change anymore.
+    complete: bool,
+}
+
+impl CachedDiff {
+    fn is_fresh(&self) -> bool {
+        self.complete || self.computed_at.elapsed() < INCOMPLETE_DIFF_TTL
+    }
+}
+
+#[derive(Default)]
+struct DiffsState {
+    queue: VecDeque<DiffKey>,
+    cache: HashMap<DiffKey, CachedDiff>,
+    thread: Option<Thread>,
+}
+
+/// Compares experiments in a background thread, as loading their results is too slow to be done
+/// while handling a request, and might require downloading their published reports.
+#[derive(Clone, Default)]
+pub struct DiffsWorker(Arc<Mutex<DiffsState>>);
+
+impl DiffsWorker {
+    pub fn new() -> Self {
+        DiffsWorker::default()
+    }
+
+    pub fn spawn(&self, data: Data) {
+        let worker = self.clone();
+        let joiner = thread::spawn(move || loop {
+            worker.diffs_thread(&data);
+        });
+        self.lock().thread = Some(joiner.thread().clone());
+    }
+
+    /// The diff between two experiments, which is queued to be computed if it's not cached yet.
+    pub fn get(&self, a: &str, b: &str) -> DiffStatus {
+        let key = (a.to_string(), b.to_string());
+        let mut state = self.lock();
+        if let Some(cached) = state.cache.get(&key).filter(|cached| cached.is_fresh()) {
+            return match cached.result {
+                Ok(ref diff) => DiffStatus::Ready(diff.clone()),
+                Err(ref err) => DiffStatus::Failed(err.clone()),
+            };
+        }
+
+        if !state.queue.contains(&key) {
+            state.queue.push_back(key);
+            if let Some(ref thread) = state.thread {
+                thread.unpark();
+            } else {
+                warn!("no diffs worker to wake up!");
+            }
+        }
+        DiffStatus::Pending
+    }
+
+    fn diffs_thread(&self, data: &Data) {
+        let key = match self.lock().queue.front().cloned() {
+            Some(key) => key,
+            None => {
+                thread::park();
+                return;
+            }
+        };
+
+        let (result, complete) = match compute_diff(data, &key) {
+            Ok((diff, complete)) => (Ok(Arc::new(diff)), complete),
+            Err(err) => {
+                utils::report_failure(&err);
+                (Err(err.to_string()), false)
+            }
+        };
+
+        let mut state = self.lock();
+        state.queue.retain(|queued| *queued != key);
+        if state.cache.len() >= CACHED_DIFFS && !state.cache.contains_key(&key) {
+            let oldest = state
+                .cache
+                .iter()
+                .min_by_key(|(_, cached)| cached.computed_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.cache.remove(&oldest);
+            }
+        }
+        state.cache.insert(
+            key,
+            CachedDiff {
+                result,
+                computed_at: Instant::now(),
+                complete,
+            },
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DiffsState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn compute_diff(data: &Data, (a, b): &DiffKey) -> Fallible<(ExperimentsDiff, bool)> {
+    let load = |name: &str| {
+        report::load_experiment(&data.db, name)?
+            .ok_or_else(|| err_msg(format!("experiment {} doesn't exist", name)))
+    };
+    let (a, a_crates) = load(a)?;
+    let (b, b_crates) = load(b)?;
+
+    let diff = report::diff_experiments(&data.config, [&a_crates[..], &b_crates[..]]);
+    let complete = a.status == Status::Completed && b.status == Status::Completed;
+    Ok((diff, complete))
+}
@@ -2,6 +2,7 @@ pub mod agents;
 pub mod api_types;
 mod auth;
 mod cronjobs;
+mod diffs;
 mod github;
 mod messages;
 mod metrics;
@@ -44,6 +45,7 @@ pub struct Data {
     pub agents: Agents,
     pub db: Database,
     pub reports_worker: reports::ReportsWorker,
+    pub diffs_worker: diffs::DiffsWorker,
     pub record_progress_worker: routes::agent::RecordProgressThread,
     pub acl: ACL,
     pub metrics: Metrics,
@@ -90,6 +92,7 @@ pub fn run(config: Config, bind: SocketAddr) -> Fallible<()> {
         agents,
         db,
         reports_worker: reports::ReportsWorker::new(),
+        diffs_worker: diffs::DiffsWorker::new(),
         acl,
         metrics,
     };
@@ -98,6 +101,8 @@ pub fn run(config: Config, bind: SocketAddr) -> Fallible<()> {
 
     data.reports_worker.spawn(data.clone(), github_data.clone());
     info!("spawned reports worker...");
+    data.diffs_worker.spawn(data.clone());
+    info!("spawned diffs worker...");
     cronjobs::spawn(data.clone());
 
     info!("running server on {}...", bind);
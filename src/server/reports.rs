@@ -42,7 +42,16 @@ fn generate_report(data: &Data, ex: &Experiment, results: &DatabaseDB) -> Fallib
     )?;
 
     let crates = ex.get_crates(&data.db)?;
-    let res = report::gen(results, ex, &crates, &writer, &data.config, false)?;
+    let popularity = report::Popularity::load(&data.db)?;
+    let res = report::gen(
+        results,
+        ex,
+        &crates,
+        &writer,
+        &data.config,
+        &popularity,
+        false,
+    )?;
 
     //remove metrics about completed experiments
     data.metrics.on_complete_experiment(&ex.name)?;
@@ -1,5 +1,7 @@
-use crate::experiments::{Experiment, Mode, Status};
+use crate::experiments::{Experiment, Lockfile, Mode, Status};
 use crate::prelude::*;
+use crate::report::{Comparison, CrateDiff};
+use crate::server::diffs::DiffStatus;
 use crate::server::routes::ui::{render_template, LayoutContext};
 use crate::server::{Data, HttpError};
 use chrono::{Duration, SecondsFormat, Utc};
@@ -15,6 +17,10 @@ struct ExperimentData {
     mode: &'static str,
     assigned_to: Option<String>,
     requirement: Option<String>,
+    target: Option<String>,
+    patches: Vec<String>,
+    lockfile: Option<&'static str>,
+    crate_select: Option<String>,
     progress: u8,
     priority: i32,
 }
@@ -41,10 +47,20 @@ impl ExperimentData {
                 Mode::Clippy => "cargo clippy",
                 Mode::Rustdoc => "cargo doc",
                 Mode::UnstableFeatures => "unstable features",
+                Mode::Resolve => "cargo generate-lockfile",
             },
             assigned_to: experiment.assigned_to.as_ref().map(|a| a.to_string()),
             priority: experiment.priority,
             requirement: experiment.requirement.clone(),
+            target: experiment.target.clone(),
+            patches: experiment.patches.iter().map(|p| p.to_string()).collect(),
+            lockfile: match experiment.lockfile {
+                Lockfile::Keep => None,
+                Lockfile::Regenerate => Some("regenerated"),
+                Lockfile::MinimalVersions => Some("minimal versions"),
+                Lockfile::DirectMinimalVersions => Some("direct minimal versions"),
+            },
+            crate_select: experiment.crate_select.as_ref().map(|c| c.to_string()),
             progress: if show_progress {
                 experiment.progress(&data.db)?
             } else {
@@ -109,6 +125,11 @@ struct ExperimentExt {
     github_url: Option<String>,
     report_url: Option<String>,
 
+    toolchain_start: String,
+    toolchain_end: String,
+    requested_toolchain_start: Option<String>,
+    requested_toolchain_end: Option<String>,
+
     created_at: String,
     started_at: Option<String>,
     completed_at: Option<String>,
@@ -173,6 +194,11 @@ pub fn endpoint_experiment(name: String, data: Arc<Data>) -> Fallible<Response<B
             github_url: ex.github_issue.map(|i| i.html_url),
             report_url: ex.report_url.clone(),
 
+            toolchain_start: ex.toolchains[0].to_string(),
+            toolchain_end: ex.toolchains[1].to_string(),
+            requested_toolchain_start: ex.requested_toolchains[0].as_ref().map(|tc| tc.to_string()),
+            requested_toolchain_end: ex.requested_toolchains[1].as_ref().map(|tc| tc.to_string()),
+
             created_at: ex.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
             started_at: ex
                 .started_at
@@ -199,3 +225,76 @@ pub fn endpoint_experiment(name: String, data: Arc<Data>) -> Fallible<Response<B
         Err(HttpError::NotFound.into())
     }
 }
+
+#[derive(Serialize)]
+struct DiffCrate {
+    name: String,
+    url: String,
+    res: [&'static str; 2],
+}
+
+impl DiffCrate {
+    fn new(krate: &CrateDiff) -> Self {
+        let res = |res: Option<Comparison>| res.map_or("not tested", |res| res.to_str());
+        DiffCrate {
+            name: krate.name.clone(),
+            url: krate.url.clone(),
+            res: [res(krate.res[0]), res(krate.res[1])],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DiffSection {
+    title: String,
+    crates: Vec<DiffCrate>,
+}
+
+#[derive(Serialize)]
+struct DiffContext {
+    experiments: [String; 2],
+    /// Whether the diff is still being computed in the background.
+    pending: bool,
+    sections: Vec<DiffSection>,
+    layout: LayoutContext,
+}
+
+pub fn endpoint_diff(name_a: String, name_b: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let (a, b) = match (
+        Experiment::get(&data.db, &name_a)?,
+        Experiment::get(&data.db, &name_b)?,
+    ) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(HttpError::NotFound.into()),
+    };
+
+    let section = |title: String, crates: &[CrateDiff]| DiffSection {
+        title,
+        crates: crates.iter().map(DiffCrate::new).collect(),
+    };
+    let sections = match data.diffs_worker.get(&a.name, &b.name) {
+        DiffStatus::Pending => None,
+        DiffStatus::Ready(diff) => Some(vec![
+            section(
+                format!("Regressed in {} only", b.name),
+                &diff.new_regressions,
+            ),
+            section(
+                format!("Regressed in {} only", a.name),
+                &diff.gone_regressions,
+            ),
+            section("With a changed result".into(), &diff.changed),
+        ]),
+        DiffStatus::Failed(err) => bail!("failed to compare {} and {}: {}", a.name, b.name, err),
+    };
+
+    render_template(
+        "ui/diff.html",
+        &DiffContext {
+            pending: sections.is_none(),
+            sections: sections.unwrap_or_default(),
+            experiments: [a.name, b.name],
+            layout: LayoutContext::new(),
+        },
+    )
+}
@@ -41,6 +41,14 @@ pub fn routes(
         .and(data_filter.clone())
         .map(experiments::endpoint_experiment);
 
+    let diff = warp::get2()
+        .and(warp::path("diff"))
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .map(experiments::endpoint_diff);
+
     let agents = warp::get2()
         .and(warp::path("agents"))
         .and(warp::path::end())
@@ -58,6 +66,8 @@ pub fn routes(
             queue
                 .or(experiment)
                 .unify()
+                .or(diff)
+                .unify()
                 .or(agents)
                 .unify()
                 .or(assets)
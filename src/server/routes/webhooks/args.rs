@@ -1,5 +1,5 @@
-use crate::experiments::{Assignee, CapLints, DeferredCrateSelect, Mode};
-use crate::toolchain::Toolchain;
+use crate::experiments::{Assignee, CapLints, DeferredCrateSelect, Lockfile, Mode};
+use crate::toolchain::{CratePatch, Toolchain};
 use failure::{self, Fallible};
 
 #[derive(Debug, Fail)]
@@ -15,6 +15,26 @@ pub enum CommandParseError {
     UnknownKey(String),
 }
 
+/// Comma-separated list of crate patches, empty to remove every patch.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct CratePatches(pub Vec<CratePatch>);
+
+impl std::str::FromStr for CratePatches {
+    type Err = crate::toolchain::ToolchainParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if input.is_empty() {
+            return Ok(CratePatches(Vec::new()));
+        }
+        Ok(CratePatches(
+            input
+                .split(',')
+                .map(|patch| patch.parse())
+                .collect::<Result<_, _>>()?,
+        ))
+    }
+}
+
 macro_rules! generate_parser {
     (pub enum $enum:ident {
         $($command:expr => $variant:ident($var_struct:ident {
@@ -114,6 +134,9 @@ generate_parser!(pub enum Command {
         ignore_blacklist: Option<bool> = "ignore-blacklist",
         assign: Option<Assignee> = "assign",
         requirement: Option<String> = "requirement",
+        target: Option<String> = "target",
+        lockfile: Option<Lockfile> = "lockfile",
+        patch: Option<CratePatches> = "patch",
     })
 
     "check" => Check(CheckArgs {
@@ -126,6 +149,9 @@ generate_parser!(pub enum Command {
         ignore_blacklist: Option<bool> = "ignore-blacklist",
         assign: Option<Assignee> = "assign",
         requirement: Option<String> = "requirement",
+        target: Option<String> = "target",
+        lockfile: Option<Lockfile> = "lockfile",
+        patch: Option<CratePatches> = "patch",
     })
 
     "abort" => Abort(AbortArgs {
@@ -155,12 +181,15 @@ generate_parser!(pub enum Command {
         ignore_blacklist: Option<bool> = "ignore-blacklist",
         assign: Option<Assignee> = "assign",
         requirement: Option<String> = "requirement",
+        target: Option<String> = "target",
+        lockfile: Option<Lockfile> = "lockfile",
+        patch: Option<CratePatches> = "patch",
     })
 });
 
 #[cfg(test)]
 mod tests {
-    use super::CommandParseError;
+    use super::{CommandParseError, CratePatches};
 
     // Use a simpler parser for tests
     generate_parser!(pub enum TestCommand {
@@ -238,4 +267,25 @@ mod tests {
         test!(fail "foo arg4=42", CommandParseError::UnknownKey("arg4".into()));
         test!(fail "foo bar", CommandParseError::InvalidArgument("bar".into()));
     }
+
+    #[test]
+    fn test_crate_patches() {
+        let patches: CratePatches =
+            "libc=version:0.2.200,log=https://github.com/rust-lang/log=main"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            patches,
+            CratePatches(vec![
+                "libc=version:0.2.200".parse().unwrap(),
+                "log=https://github.com/rust-lang/log=main".parse().unwrap(),
+            ])
+        );
+
+        assert_eq!(
+            "".parse::<CratePatches>().unwrap(),
+            CratePatches(Vec::new())
+        );
+        assert!("libc=version:0.2.200,".parse::<CratePatches>().is_err());
+    }
 }
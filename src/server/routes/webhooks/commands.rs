@@ -8,8 +8,9 @@ use crate::server::routes::webhooks::args::{
     AbortArgs, CheckArgs, EditArgs, RetryArgs, RetryReportArgs, RunArgs,
 };
 use crate::server::{Data, GithubData};
-use crate::toolchain::Toolchain;
+use crate::toolchain::{CratePatch, PatchSource, Toolchain};
 use rustwide::Toolchain as RustwideToolchain;
+use std::collections::BTreeMap;
 
 pub fn ping(data: &Data, github_data: &GithubData, issue: &Issue) -> Fallible<()> {
     Message::new()
@@ -44,6 +45,9 @@ pub fn check(
             ignore_blacklist: args.ignore_blacklist,
             assign: args.assign,
             requirement: args.requirement,
+            target: args.target,
+            lockfile: args.lockfile,
+            patch: args.patch,
         },
     )
 }
@@ -56,6 +60,11 @@ pub fn run(
     issue: &Issue,
     args: RunArgs,
 ) -> Fallible<()> {
+    ensure_not_local(&args.start)?;
+    ensure_not_local(&args.end)?;
+    if let Some(ref patches) = args.patch {
+        ensure_no_path_patches(&patches.0)?;
+    }
     let name = setup_run_name(&data.db, issue, args.name)?;
 
     let mut message = Message::new().line(
@@ -75,6 +84,10 @@ pub fn run(
                 rustdocflags: None,
                 cargoflags: None,
                 ci_try: false,
+                local_path: None,
+                components: Vec::new(),
+                targets: Vec::new(),
+                env: BTreeMap::new(),
                 patches: Vec::new(),
             });
             detected_end = Some(Toolchain {
@@ -83,6 +96,10 @@ pub fn run(
                 rustdocflags: None,
                 cargoflags: None,
                 ci_try: true,
+                local_path: None,
+                components: Vec::new(),
+                targets: Vec::new(),
+                env: BTreeMap::new(),
                 patches: Vec::new(),
             });
             message = message.line(
@@ -147,6 +164,10 @@ pub fn run(
         ignore_blacklist: args.ignore_blacklist.unwrap_or(false),
         assign: args.assign,
         requirement: Some(requirement),
+        target: args.target,
+        patches: args.patch.map(|patches| patches.0).unwrap_or_default(),
+        lockfile: args.lockfile.unwrap_or_default(),
+        pin_toolchains: true,
     }
     .apply(&ActionsCtx::new(&data.db, &data.config))?;
 
@@ -163,6 +184,11 @@ pub fn run(
 }
 
 pub fn edit(data: &Data, github_data: &GithubData, issue: &Issue, args: EditArgs) -> Fallible<()> {
+    ensure_not_local(&args.start)?;
+    ensure_not_local(&args.end)?;
+    if let Some(ref patches) = args.patch {
+        ensure_no_path_patches(&patches.0)?;
+    }
     let name = get_name(&data.db, issue, args.name)?;
 
     let crates = args
@@ -181,6 +207,9 @@ pub fn edit(data: &Data, github_data: &GithubData, issue: &Issue, args: EditArgs
         ignore_blacklist: args.ignore_blacklist,
         assign: args.assign,
         requirement: args.requirement,
+        target: args.target,
+        lockfile: args.lockfile,
+        patches: args.patch.map(|patches| patches.0),
     }
     .apply(&ActionsCtx::new(&data.db, &data.config))?;
 
@@ -284,6 +313,34 @@ pub fn reload_acl(data: &Data, github_data: &GithubData, issue: &Issue) -> Falli
     Ok(())
 }
 
+/// Local toolchains are copied from any path of the agents, so they're only available from the CLI.
+fn ensure_not_local(toolchain: &Option<Toolchain>) -> Fallible<()> {
+    if let Some(toolchain) = toolchain {
+        if toolchain.local_path.is_some() {
+            bail!(
+                "local toolchains like `{}` can only be used from the CLI",
+                toolchain
+            );
+        }
+        ensure_no_path_patches(&toolchain.patches)?;
+    }
+    Ok(())
+}
+
+/// Path patches mount any directory of the agents in the sandbox, so they're only available from
+/// the CLI.
+fn ensure_no_path_patches(patches: &[CratePatch]) -> Fallible<()> {
+    for patch in patches {
+        if let PatchSource::Path { .. } = patch.source {
+            bail!(
+                "path patches like `{}` can only be used from the CLI",
+                patch
+            );
+        }
+    }
+    Ok(())
+}
+
 fn get_name(db: &Database, issue: &Issue, name: Option<String>) -> Fallible<String> {
     if let Some(name) = name {
         store_experiment_name(db, issue, &name)?;
@@ -352,8 +409,8 @@ fn generate_new_experiment_name(db: &Database, issue: &Issue) -> Fallible<String
 #[cfg(test)]
 mod tests {
     use super::{
-        default_experiment_name, generate_new_experiment_name, get_name, setup_run_name,
-        store_experiment_name,
+        default_experiment_name, ensure_no_path_patches, ensure_not_local,
+        generate_new_experiment_name, get_name, setup_run_name, store_experiment_name,
     };
     use crate::actions::{self, Action, ActionsCtx};
     use crate::config::Config;
@@ -379,6 +436,24 @@ mod tests {
         Ok(name)
     }
 
+    #[test]
+    fn test_ensure_not_local() {
+        assert!(ensure_not_local(&None).is_ok());
+        assert!(ensure_not_local(&Some("stable".parse().unwrap())).is_ok());
+        assert!(ensure_not_local(&Some("path:/opt/rust".parse().unwrap())).is_err());
+    }
+
+    #[test]
+    fn test_ensure_no_path_patches() {
+        let patch = |repr: &str| vec![repr.parse().unwrap()];
+        assert!(ensure_no_path_patches(&patch("libc=version:0.2.200")).is_ok());
+        assert!(ensure_no_path_patches(&patch("libc=path:/opt/libc")).is_err());
+        // Patches of the toolchains are checked too
+        assert!(
+            ensure_not_local(&Some("stable+patch=libc=path:/opt/libc".parse().unwrap())).is_err()
+        );
+    }
+
     #[test]
     fn test_default_experiment_name() {
         let db = Database::temp().unwrap();
@@ -1,8 +1,20 @@
+use crate::dirs::WORK_DIR;
 use crate::prelude::*;
 use crate::utils;
-use rustwide::Toolchain as RustwideToolchain;
+use flate2::read::GzDecoder;
+use remove_dir_all::remove_dir_all;
+use rustwide::{Toolchain as RustwideToolchain, Workspace};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use tar::Archive;
+use walkdir::WalkDir;
+
+static DEFAULT_DIST_SERVER: &str = "https://static.rust-lang.org";
+static FLOATING_CHANNELS: &[&str] = &["stable", "beta", "nightly"];
 
 #[cfg(test)]
 lazy_static! {
@@ -13,6 +25,10 @@ lazy_static! {
         rustdocflags: None,
         cargoflags: None,
         ci_try: false,
+        local_path: None,
+        components: Vec::new(),
+        targets: Vec::new(),
+        env: BTreeMap::new(),
         patches: Vec::new(),
     };
 
@@ -23,6 +39,10 @@ lazy_static! {
         rustdocflags: None,
         cargoflags: None,
         ci_try: false,
+        local_path: None,
+        components: Vec::new(),
+        targets: Vec::new(),
+        env: BTreeMap::new(),
         patches: Vec::new(),
     };
 }
@@ -34,6 +54,19 @@ pub struct Toolchain {
     pub rustdocflags: Option<String>,
     pub cargoflags: Option<String>,
     pub ci_try: bool,
+    /// Path to a locally built toolchain (either a directory or a `.tar.gz` of its sysroot or of
+    /// its dist installer), installed in the workspace under the name of the `source` dist toolchain.
+    #[serde(default)]
+    pub local_path: Option<PathBuf>,
+    /// Extra rustup components to install, like `rust-src`.
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// Extra targets to install the standard library of.
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// Extra environment variables set on every cargo invocation.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
     pub patches: Vec<CratePatch>,
 }
 
@@ -43,6 +76,240 @@ impl Toolchain {
 
         encode(&self.to_string(), &utils::FILENAME_ENCODE_SET).to_string()
     }
+
+    /// Install the toolchain in the workspace. Local toolchains are copied inside the rustup home
+    /// of the workspace, as the sandbox can't access arbitrary paths of the host.
+    pub fn install(&self, workspace: &Workspace) -> Fallible<()> {
+        let path = match self.local_path {
+            Some(ref path) => path,
+            None => return self.source.install(workspace),
+        };
+
+        let name = self
+            .source
+            .as_dist()
+            .expect("local toolchains are installed as dist toolchains")
+            .name();
+        let dest = WORK_DIR.join("rustup-home").join("toolchains").join(name);
+
+        // Always start from a fresh copy, as local builds change without changing their path
+        if dest.exists() {
+            remove_dir_all(&dest)?;
+        }
+
+        info!(
+            "installing local toolchain {} from {}",
+            name,
+            path.display()
+        );
+        if path.is_dir() {
+            copy_dir(path, &dest)?;
+        } else if path.is_file() {
+            let parent = dest.parent().unwrap();
+            fs::create_dir_all(parent)?;
+            let tmp = tempfile::tempdir_in(parent)?;
+
+            let tarball = File::open(path)?;
+            Archive::new(GzDecoder::new(tarball))
+                .unpack(tmp.path())
+                .with_context(|_| format!("failed to extract {}", path.display()))?;
+
+            // Tarballs of the dist server contain an installer instead of the sysroot itself
+            match find_installer(tmp.path())? {
+                Some(installer) => install_components(&installer, &dest)?,
+                None => fs::rename(tmp.into_path(), &dest)?,
+            }
+        } else {
+            bail!("missing local toolchain: {}", path.display());
+        }
+
+        Ok(())
+    }
+
+    /// Whether this toolchain is a release channel (`stable`, `beta` or `nightly`) that changes
+    /// over time, instead of a specific release.
+    pub fn is_floating(&self) -> bool {
+        self.source
+            .as_dist()
+            .map(|dist| FLOATING_CHANNELS.contains(&dist.name()))
+            .unwrap_or(false)
+    }
+
+    /// Resolve a floating channel to the release it currently points to, loading the channel's
+    /// dist manifest with `fetch_manifest`. Toolchains which are not floating are returned as-is.
+    pub fn pinned<F>(&self, fetch_manifest: F) -> Fallible<Toolchain>
+    where
+        F: Fn(&str) -> Fallible<String>,
+    {
+        let channel = match self.source.as_dist() {
+            Some(dist) if self.is_floating() => dist.name(),
+            _ => return Ok(self.clone()),
+        };
+
+        let manifest: DistManifest = ::toml::from_str(&fetch_manifest(channel)?)
+            .with_context(|_| format!("invalid dist manifest for the {} channel", channel))?;
+
+        // The rustc version looks like `1.72.0-beta.3 (0de5e2f4a 2023-07-13)`
+        let version = manifest
+            .pkg
+            .rustc
+            .version
+            .split(' ')
+            .next()
+            .unwrap_or_default();
+        let name = match channel {
+            "nightly" => format!("nightly-{}", manifest.date),
+            "beta" if version.contains("-beta.") => version.to_string(),
+            "stable" if !version.is_empty() && !version.contains('-') => version.to_string(),
+            _ => bail!(
+                "unexpected rustc version in the {} dist manifest: {}",
+                channel,
+                manifest.pkg.rustc.version
+            ),
+        };
+
+        Ok(Toolchain {
+            source: RustwideToolchain::dist(&name),
+            ..self.clone()
+        })
+    }
+}
+
+fn copy_dir(src: &Path, dest: &Path) -> Fallible<()> {
+    for entry in WalkDir::new(src) {
+        let entry = entry?;
+        let target = dest.join(entry.path().strip_prefix(src)?);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Root of the rust-installer layout of an extracted tarball, which contains a `components` file
+/// either at the top or in the only top-level directory.
+fn find_installer(extracted: &Path) -> Fallible<Option<PathBuf>> {
+    if extracted.join("components").is_file() {
+        return Ok(Some(extracted.to_path_buf()));
+    }
+
+    let entries = fs::read_dir(extracted)?.collect::<Result<Vec<_>, _>>()?;
+    match entries.as_slice() {
+        [entry] if entry.path().join("components").is_file() => Ok(Some(entry.path())),
+        _ => Ok(None),
+    }
+}
+
+/// Install the components of a rust-installer tarball in the sysroot, copying the files listed in
+/// each component's manifest like its `install.sh` would, without running the script.
+fn install_components(installer: &Path, dest: &Path) -> Fallible<()> {
+    let components = fs::read_to_string(installer.join("components"))?;
+    for component in components.lines().filter(|line| !line.is_empty()) {
+        let root = installer.join(component);
+        let manifest = fs::read_to_string(root.join("manifest.in"))
+            .with_context(|_| format!("missing manifest of component {}", component))?;
+
+        for line in manifest.lines().filter(|line| !line.is_empty()) {
+            let (kind, file) = line
+                .split_once(':')
+                .ok_or_else(|| err_msg(format!("invalid manifest line: {}", line)))?;
+            if Path::new(file).is_absolute() || file.split('/').any(|part| part == "..") {
+                bail!(
+                    "component {} installs files outside of the sysroot",
+                    component
+                );
+            }
+
+            let target = dest.join(file);
+            match kind {
+                "file" => {
+                    fs::create_dir_all(target.parent().unwrap())?;
+                    fs::copy(root.join(file), &target)?;
+                }
+                "dir" => copy_dir(&root.join(file), &target)?,
+                _ => bail!("invalid manifest line: {}", line),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Toolchain flags are separated by `+`, so it's written as `%2B` inside values. Percent signs
+/// aren't escaped, so that the names of existing toolchains stored in the database don't change.
+fn escape_value(value: &str) -> String {
+    value.replace('+', "%2B")
+}
+
+fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = value;
+    while let Some(idx) = rest.find('%') {
+        unescaped.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        match rest.get(..3) {
+            Some(code) if code.eq_ignore_ascii_case("%2B") => {
+                unescaped.push('+');
+                rest = &rest[3..];
+            }
+            // Other percent signs are kept as-is
+            _ => {
+                unescaped.push('%');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Name of the rustup toolchain a local toolchain is installed as. The sanitized path is only
+/// there to be readable, and the hash of the path keeps paths sanitized the same way apart.
+fn local_toolchain_name(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let sanitized: String = path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let hash = format!("{:x}", Sha1::digest(path.as_bytes()));
+
+    format!(
+        "crater-local-{}-{}",
+        sanitized.trim_matches('-'),
+        &hash[..12]
+    )
+}
+
+#[derive(Deserialize)]
+struct DistManifest {
+    date: String,
+    pkg: DistManifestPackages,
+}
+
+#[derive(Deserialize)]
+struct DistManifestPackages {
+    rustc: DistManifestPackage,
+}
+
+#[derive(Deserialize)]
+struct DistManifestPackage {
+    version: String,
+}
+
+/// Download the dist manifest of a release channel, honoring `RUSTUP_DIST_SERVER` like rustup does.
+pub(crate) fn fetch_dist_manifest(channel: &str) -> Fallible<String> {
+    let server =
+        std::env::var("RUSTUP_DIST_SERVER").unwrap_or_else(|_| DEFAULT_DIST_SERVER.to_string());
+    let url = format!(
+        "{}/dist/channel-rust-{}.toml",
+        server.trim_end_matches('/'),
+        channel
+    );
+
+    Ok(utils::http::get_sync(&url)
+        .with_context(|_| format!("failed to fetch the dist manifest of {}", channel))?
+        .text()?)
 }
 
 impl std::ops::Deref for Toolchain {
@@ -55,32 +322,47 @@ impl std::ops::Deref for Toolchain {
 
 impl fmt::Display for Toolchain {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if let Some(dist) = self.source.as_dist() {
+        if let Some(ref path) = self.local_path {
+            write!(f, "path:{}", escape_value(&path.to_string_lossy()))?;
+        } else if let Some(dist) = self.source.as_dist() {
             write!(f, "{}", dist.name())?;
         } else if let Some(ci) = self.source.as_ci() {
-            if self.ci_try {
-                write!(f, "try#{}", ci.sha())?;
+            let branch = if self.ci_try { "try" } else { "master" };
+            if ci.alt() {
+                write!(f, "{}-alt#{}", branch, ci.sha())?;
             } else {
-                write!(f, "master#{}", ci.sha())?;
+                write!(f, "{}#{}", branch, ci.sha())?;
             }
         } else {
             panic!("unsupported rustwide toolchain");
         }
 
         if let Some(ref flag) = self.rustflags {
-            write!(f, "+rustflags={}", flag)?;
+            write!(f, "+rustflags={}", escape_value(flag))?;
         }
 
         if let Some(ref flag) = self.rustdocflags {
-            write!(f, "+rustdocflags={}", flag)?;
+            write!(f, "+rustdocflags={}", escape_value(flag))?;
         }
 
         if let Some(ref flag) = self.cargoflags {
-            write!(f, "+cargoflags={}", flag)?;
+            write!(f, "+cargoflags={}", escape_value(flag))?;
+        }
+
+        if !self.components.is_empty() {
+            write!(f, "+components={}", self.components.join(","))?;
+        }
+
+        if !self.targets.is_empty() {
+            write!(f, "+targets={}", self.targets.join(","))?;
+        }
+
+        for (name, value) in &self.env {
+            write!(f, "+env={}={}", name, escape_value(value))?;
         }
 
         for patch in self.patches.iter() {
-            write!(f, "+patch={}", patch)?;
+            write!(f, "+patch={}", escape_value(&patch.to_string()))?;
         }
 
         Ok(())
@@ -105,7 +387,17 @@ impl FromStr for Toolchain {
 
         let raw_source = parts.next().ok_or(ToolchainParseError::EmptyName)?;
         let mut ci_try = false;
-        let source = if let Some(hash_idx) = raw_source.find('#') {
+        let mut local_path = None;
+        let source = if let Some(path) = raw_source.strip_prefix("path:") {
+            if path.is_empty() {
+                return Err(ToolchainParseError::EmptyName);
+            }
+
+            let path = PathBuf::from(unescape_value(path));
+            let source = RustwideToolchain::dist(&local_toolchain_name(&path));
+            local_path = Some(path);
+            source
+        } else if let Some(hash_idx) = raw_source.find('#') {
             let (source_name, sha_with_hash) = raw_source.split_at(hash_idx);
 
             let sha = &sha_with_hash[1..];
@@ -113,13 +405,23 @@ impl FromStr for Toolchain {
                 return Err(ToolchainParseError::EmptyName);
             }
 
-            match source_name {
+            // Alt builds are built with debug assertions and extra LLVM checks enabled
+            let (branch, alt) = match source_name.strip_suffix("-alt") {
+                Some(branch) => (branch, true),
+                None => (source_name, false),
+            };
+
+            match branch {
                 "try" => {
                     ci_try = true;
-                    RustwideToolchain::ci(sha, false)
+                    RustwideToolchain::ci(sha, alt)
+                }
+                "master" => RustwideToolchain::ci(sha, alt),
+                _ => {
+                    return Err(ToolchainParseError::InvalidSourceName(
+                        source_name.to_string(),
+                    ))
                 }
-                "master" => RustwideToolchain::ci(sha, false),
-                name => return Err(ToolchainParseError::InvalidSourceName(name.to_string())),
             }
         } else if raw_source.is_empty() {
             return Err(ToolchainParseError::EmptyName);
@@ -130,11 +432,14 @@ impl FromStr for Toolchain {
         let mut rustflags = None;
         let mut rustdocflags = None;
         let mut cargoflags = None;
+        let mut components = Vec::new();
+        let mut targets = Vec::new();
+        let mut env = BTreeMap::new();
         let mut patches: Vec<CratePatch> = vec![];
         for part in parts {
             if let Some(equal_idx) = part.find('=') {
                 let (flag, value_with_equal) = part.split_at(equal_idx);
-                let value = value_with_equal[1..].to_string();
+                let value = unescape_value(&value_with_equal[1..]);
 
                 if value.is_empty() {
                     return Err(ToolchainParseError::InvalidFlag(flag.to_string()));
@@ -144,6 +449,12 @@ impl FromStr for Toolchain {
                     "rustflags" => rustflags = Some(value),
                     "rustdocflags" => rustdocflags = Some(value),
                     "cargoflags" => cargoflags = Some(value),
+                    "components" => components = parse_list(flag, &value)?,
+                    "targets" => targets = parse_list(flag, &value)?,
+                    "env" => {
+                        let (name, value) = parse_env(&value)?;
+                        env.insert(name, value);
+                    }
                     "patch" => patches.push(value.parse()?),
                     unknown => return Err(ToolchainParseError::InvalidFlag(unknown.to_string())),
                 }
@@ -158,52 +469,245 @@ impl FromStr for Toolchain {
             rustdocflags,
             cargoflags,
             ci_try,
+            local_path,
+            components,
+            targets,
+            env,
             patches,
         })
     }
 }
 
+fn parse_list(flag: &str, value: &str) -> Result<Vec<String>, ToolchainParseError> {
+    let list: Vec<String> = value.split(',').map(|item| item.to_string()).collect();
+    if list.iter().any(|item| item.is_empty()) {
+        return Err(ToolchainParseError::InvalidFlag(flag.to_string()));
+    }
+
+    Ok(list)
+}
+
+fn parse_env(value: &str) -> Result<(String, String), ToolchainParseError> {
+    match value.find('=') {
+        Some(equal_idx) if equal_idx > 0 => Ok((
+            value[..equal_idx].to_string(),
+            value[equal_idx + 1..].to_string(),
+        )),
+        _ => Err(ToolchainParseError::InvalidFlag("env".to_string())),
+    }
+}
+
+/// Replacement of a crate in the dependency graph, applied through Cargo's `[patch]` section.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
 pub struct CratePatch {
     pub name: String,
-    pub repo: String,
-    pub branch: String,
+    pub source: PatchSource,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PatchSource {
+    /// A git repository, checked out at the given reference.
+    Git {
+        repo: String,
+        reference: GitReference,
+    },
+    /// A specific version published on crates.io, like a pre-release.
+    Registry { version: String },
+    /// A directory on the agent, mounted read-only in the sandbox.
+    Path { path: PathBuf },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum GitReference {
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+impl GitReference {
+    pub fn name(&self) -> &str {
+        match self {
+            GitReference::Branch(name) | GitReference::Tag(name) | GitReference::Rev(name) => name,
+        }
+    }
+}
+
+impl CratePatch {
+    pub fn to_path_component(&self) -> String {
+        use percent_encoding::utf8_percent_encode as encode;
+
+        encode(&self.to_string(), &utils::FILENAME_ENCODE_SET).to_string()
+    }
 }
 
 impl FromStr for CratePatch {
     type Err = ToolchainParseError;
 
+    // The accepted formats are:
+    //
+    // - `{name}={repo}={branch}`, `{name}={repo}=tag:{tag}` and `{name}={repo}=rev:{rev}`
+    // - `{name}=version:{version}`
+    // - `{name}=path:{path}`
+    //
+    // Git references can't contain `:`, so the prefixes are unambiguous. The first `=` after the
+    // repository URL separates it from the reference, allowing both of them to contain `=`.
     fn from_str(input: &str) -> Result<Self, ToolchainParseError> {
-        let params: Vec<&str> = input.split('=').collect();
+        let invalid = || ToolchainParseError::InvalidFlag(input.to_string());
+
+        let equal_idx = input.find('=').ok_or_else(invalid)?;
+        let (name, spec) = (&input[..equal_idx], &input[equal_idx + 1..]);
+        if name.is_empty() {
+            return Err(invalid());
+        }
 
-        if params.len() != 3 {
-            Err(ToolchainParseError::InvalidFlag(input.to_string()))
+        let source = if let Some(version) = spec.strip_prefix("version:") {
+            if version.is_empty() {
+                return Err(invalid());
+            }
+            PatchSource::Registry {
+                version: version.to_string(),
+            }
+        } else if let Some(path) = spec.strip_prefix("path:") {
+            if path.is_empty() {
+                return Err(invalid());
+            }
+            PatchSource::Path { path: path.into() }
         } else {
-            Ok(CratePatch {
-                name: params[0].into(),
-                repo: params[1].into(),
-                branch: params[2].into(),
-            })
+            let equal_idx = git_reference_separator(spec).ok_or_else(invalid)?;
+            let (repo, reference) = (&spec[..equal_idx], &spec[equal_idx + 1..]);
+
+            let reference = if let Some(tag) = reference.strip_prefix("tag:") {
+                GitReference::Tag(tag.to_string())
+            } else if let Some(rev) = reference.strip_prefix("rev:") {
+                GitReference::Rev(rev.to_string())
+            } else {
+                GitReference::Branch(reference.to_string())
+            };
+
+            if repo.is_empty() || reference.name().is_empty() {
+                return Err(invalid());
+            }
+
+            PatchSource::Git {
+                repo: repo.to_string(),
+                reference,
+            }
+        };
+
+        Ok(CratePatch {
+            name: name.to_string(),
+            source,
+        })
+    }
+}
+
+/// Position of the `=` ending the repository URL of a git patch. Inside the URL, `=` can only
+/// separate the key and the value of the query parameters.
+fn git_reference_separator(spec: &str) -> Option<usize> {
+    let mut in_query = false;
+    let mut in_value = false;
+    for (idx, c) in spec.char_indices() {
+        match c {
+            '?' if !in_query => in_query = true,
+            '&' if in_query => in_value = false,
+            '=' if in_query && !in_value => in_value = true,
+            '=' => return Some(idx),
+            _ => {}
         }
     }
+    None
 }
 
 impl fmt::Display for CratePatch {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}={}={}", self.name, self.repo, self.branch)
+        write!(f, "{}=", self.name)?;
+        match self.source {
+            PatchSource::Git {
+                ref repo,
+                ref reference,
+            } => match reference {
+                GitReference::Branch(branch) => write!(f, "{}={}", repo, branch),
+                GitReference::Tag(tag) => write!(f, "{}=tag:{}", repo, tag),
+                GitReference::Rev(rev) => write!(f, "{}=rev:{}", repo, rev),
+            },
+            PatchSource::Registry { ref version } => write!(f, "version:{}", version),
+            PatchSource::Path { ref path } => write!(f, "path:{}", path.display()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{CratePatch, Toolchain};
+    use super::{
+        install_components, local_toolchain_name, unescape_value, CratePatch, GitReference,
+        PatchSource, Toolchain,
+    };
+    use crate::prelude::*;
     use rustwide::Toolchain as RustwideToolchain;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
     use std::str::FromStr;
 
+    fn dummy_manifest(channel: &str) -> Fallible<String> {
+        let (date, version) = match channel {
+            "stable" => ("2026-09-18", "1.90.0 (1159e78c4 2026-09-14)"),
+            "beta" => ("2026-10-16", "1.91.0-beta.5 (5d2f0f2c1 2026-10-15)"),
+            "nightly" => ("2026-10-17", "1.92.0-nightly (9e1ab2d7c 2026-10-16)"),
+            other => bail!("unknown channel {}", other),
+        };
+
+        Ok(format!(
+            "manifest-version = \"2\"\n\
+             date = \"{}\"\n\
+             [pkg.cargo]\n\
+             version = \"0.0.0\"\n\
+             [pkg.rustc]\n\
+             version = \"{}\"\n",
+            date, version
+        ))
+    }
+
+    #[test]
+    fn test_pinned() {
+        macro_rules! test_pinned {
+            ($($input:expr => $output:expr,)*) => {
+                $(
+                    let tc = Toolchain::from_str($input).unwrap();
+                    assert_eq!(tc.pinned(dummy_manifest).unwrap().to_string(), $output);
+                )*
+            };
+        }
+
+        test_pinned! {
+            "stable" => "1.90.0",
+            "beta" => "1.91.0-beta.5",
+            "nightly" => "nightly-2026-10-17",
+            "nightly+rustflags=-Zfoo" => "nightly-2026-10-17+rustflags=-Zfoo",
+            "nightly-1970-01-01" => "nightly-1970-01-01",
+            "1.50.0" => "1.50.0",
+            "try#0000000000000000000000000000000000000000" => "try#0000000000000000000000000000000000000000",
+        };
+
+        assert!(Toolchain::from_str("nightly").unwrap().is_floating());
+        assert!(!Toolchain::from_str("nightly-1970-01-01")
+            .unwrap()
+            .is_floating());
+        assert!(Toolchain::from_str("nightly")
+            .unwrap()
+            .pinned(|_| Ok("date = \"2026-10-17\"".into()))
+            .is_err());
+    }
+
     #[test]
     fn test_string_repr() {
         macro_rules! test_from_str {
-            ($($str:expr => { source: $source:expr, ci_try: $ci_try:expr, },)*) => {
+            ($($str:expr => {
+                source: $source:expr,
+                ci_try: $ci_try:expr,
+                local_path: $local_path:expr,
+            },)*) => {
                 $(
                     // Test parsing without flags
                     test_from_str!($str => Toolchain {
@@ -212,6 +716,10 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: Vec::new(),
+                        targets: Vec::new(),
+                        env: BTreeMap::new(),
                         patches: Vec::new(),
                     });
 
@@ -222,6 +730,10 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: Vec::new(),
+                        targets: Vec::new(),
+                        env: BTreeMap::new(),
                         patches: Vec::new(),
                     });
 
@@ -232,6 +744,10 @@ mod tests {
                         rustdocflags: Some("-Zunstable-options -wjson".to_string()),
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: Vec::new(),
+                        targets: Vec::new(),
+                        env: BTreeMap::new(),
                         patches: Vec::new(),
                     });
 
@@ -242,6 +758,55 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: Some("foo bar".to_string()),
                         ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: Vec::new(),
+                        targets: Vec::new(),
+                        env: BTreeMap::new(),
+                        patches: Vec::new(),
+                    });
+
+                    // Test parsing with components
+                    test_from_str!(concat!($str, "+components=rust-src,llvm-tools") => Toolchain {
+                        source: $source,
+                        rustflags: None,
+                        rustdocflags: None,
+                        cargoflags: None,
+                        ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: vec!["rust-src".to_string(), "llvm-tools".to_string()],
+                        targets: Vec::new(),
+                        env: BTreeMap::new(),
+                        patches: Vec::new(),
+                    });
+
+                    // Test parsing with targets
+                    test_from_str!(concat!($str, "+targets=wasm32-unknown-unknown") => Toolchain {
+                        source: $source,
+                        rustflags: None,
+                        rustdocflags: None,
+                        cargoflags: None,
+                        ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: Vec::new(),
+                        targets: vec!["wasm32-unknown-unknown".to_string()],
+                        env: BTreeMap::new(),
+                        patches: Vec::new(),
+                    });
+
+                    // Test parsing with environment variables
+                    test_from_str!(concat!($str, "+env=CARGO_UNSTABLE_SPARSE=true+env=RUSTC_BOOTSTRAP=1") => Toolchain {
+                        source: $source,
+                        rustflags: None,
+                        rustdocflags: None,
+                        cargoflags: None,
+                        ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: Vec::new(),
+                        targets: Vec::new(),
+                        env: vec![
+                            ("CARGO_UNSTABLE_SPARSE".to_string(), "true".to_string()),
+                            ("RUSTC_BOOTSTRAP".to_string(), "1".to_string()),
+                        ].into_iter().collect(),
                         patches: Vec::new(),
                     });
 
@@ -252,10 +817,16 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: Vec::new(),
+                        targets: Vec::new(),
+                        env: BTreeMap::new(),
                         patches: vec![CratePatch {
                             name: "example".to_string(),
-                            repo: "https://git.example.com/some/repo".to_string(),
-                            branch: "master".to_string()
+                            source: PatchSource::Git {
+                                repo: "https://git.example.com/some/repo".to_string(),
+                                reference: GitReference::Branch("master".to_string()),
+                            },
                         }]
                     });
 
@@ -266,10 +837,16 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local_path: $local_path,
+                        components: Vec::new(),
+                        targets: Vec::new(),
+                        env: BTreeMap::new(),
                         patches: vec![CratePatch {
                             name: "example".to_string(),
-                            repo: "https://git.example.com/some/repo".to_string(),
-                            branch: "master".to_string()
+                            source: PatchSource::Git {
+                                repo: "https://git.example.com/some/repo".to_string(),
+                                reference: GitReference::Branch("master".to_string()),
+                            },
                         }]
                     });
                 )*
@@ -291,22 +868,47 @@ mod tests {
             "stable" => {
                 source: RustwideToolchain::dist("stable"),
                 ci_try: false,
+                local_path: None,
             },
             "beta-1970-01-01" => {
                 source: RustwideToolchain::dist("beta-1970-01-01"),
                 ci_try: false,
+                local_path: None,
             },
             "nightly-1970-01-01" => {
                 source: RustwideToolchain::dist("nightly-1970-01-01"),
                 ci_try: false,
+                local_path: None,
             },
             "master#0000000000000000000000000000000000000000" => {
                 source: RustwideToolchain::ci("0000000000000000000000000000000000000000", false),
                 ci_try: false,
+                local_path: None,
             },
             "try#0000000000000000000000000000000000000000" => {
                 source: RustwideToolchain::ci("0000000000000000000000000000000000000000", false),
                 ci_try: true,
+                local_path: None,
+            },
+            "master-alt#0000000000000000000000000000000000000000" => {
+                source: RustwideToolchain::ci("0000000000000000000000000000000000000000", true),
+                ci_try: false,
+                local_path: None,
+            },
+            "try-alt#0000000000000000000000000000000000000000" => {
+                source: RustwideToolchain::ci("0000000000000000000000000000000000000000", true),
+                ci_try: true,
+                local_path: None,
+            },
+            "path:/opt/rust-stage2" => {
+                source: RustwideToolchain::dist("crater-local-opt-rust-stage2-115254c29703"),
+                ci_try: false,
+                local_path: Some(PathBuf::from("/opt/rust-stage2")),
+            },
+            "path:/opt/rust%2Bstage2%" => {
+                source: RustwideToolchain::dist("crater-local-opt-rust-stage2-4b3c988186c9"),
+                ci_try: false,
+                local_path: Some(PathBuf::from("/opt/rust+stage2%")),
             },
         };
 
@@ -314,11 +916,137 @@ mod tests {
         assert!(Toolchain::from_str("").is_err());
         assert!(Toolchain::from_str("master#").is_err());
         assert!(Toolchain::from_str("foo#0000000000000000000000000000000000000000").is_err());
+        assert!(Toolchain::from_str("foo-alt#0000000000000000000000000000000000000000").is_err());
+        assert!(Toolchain::from_str("try-alt#").is_err());
+        assert!(Toolchain::from_str("path:").is_err());
         assert!(Toolchain::from_str("stable+rustflags").is_err());
         assert!(Toolchain::from_str("stable+rustflags=").is_err());
         assert!(Toolchain::from_str("stable+rustdocflags").is_err());
         assert!(Toolchain::from_str("stable+rustdocflags=").is_err());
         assert!(Toolchain::from_str("stable+donotusethisflag=ever").is_err());
-        assert!(Toolchain::from_str("stable+patch=").is_err())
+        assert!(Toolchain::from_str("stable+patch=").is_err());
+        assert!(Toolchain::from_str("stable+components=").is_err());
+        assert!(Toolchain::from_str("stable+targets=wasm32-unknown-unknown,").is_err());
+        assert!(Toolchain::from_str("stable+env=RUSTC_BOOTSTRAP").is_err());
+        assert!(Toolchain::from_str("stable+env==1").is_err())
+    }
+
+    #[test]
+    fn test_escaped_flags() {
+        let repr = "stable+rustflags=-Ctarget-feature=%2Bavx2+env=FEATURES=%2Bsse4.1%";
+        let tc = Toolchain::from_str(repr).unwrap();
+        assert_eq!(tc.rustflags.as_deref(), Some("-Ctarget-feature=+avx2"));
+        assert_eq!(tc.env["FEATURES"], "+sse4.1%");
+        assert_eq!(tc.to_string(), repr);
+
+        // Other percent-encoded characters are kept as-is
+        let repr = "stable+cargoflags=--config=a=%25b";
+        let tc = Toolchain::from_str(repr).unwrap();
+        assert_eq!(tc.cargoflags.as_deref(), Some("--config=a=%25b"));
+        assert_eq!(tc.to_string(), repr);
+    }
+
+    #[test]
+    fn test_local_toolchain_name() {
+        // Paths sanitized the same way are installed as different toolchains
+        let name = |path: &str| local_toolchain_name(&PathBuf::from(path));
+        assert_ne!(name("/opt/a.b"), name("/opt/a-b"));
+        assert_ne!(name("/x/y"), name("/x-y"));
+        assert_eq!(name("/x/y"), name("/x/y"));
+    }
+
+    #[test]
+    fn test_unescape_value() {
+        assert_eq!(
+            unescape_value("-Ctarget-feature=%2Bavx2"),
+            "-Ctarget-feature=+avx2"
+        );
+        assert_eq!(unescape_value("%2b%25%2B"), "+%25+");
+        assert_eq!(unescape_value("%252B"), "%252B");
+        assert_eq!(unescape_value("100%"), "100%");
+        assert_eq!(unescape_value("%zz"), "%zz");
+    }
+
+    #[test]
+    fn test_install_components() {
+        let installer = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = installer.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write("components", "rustc\ncargo\n");
+        write("install.sh", "exit 1");
+        write("rustc/manifest.in", "file:bin/rustc\ndir:lib/rustlib/etc\n");
+        write("rustc/bin/rustc", "rustc");
+        write(
+            "rustc/lib/rustlib/etc/gdb_load_rust_pretty_printers.py",
+            "gdb",
+        );
+        write("cargo/manifest.in", "file:bin/cargo\n");
+        write("cargo/bin/cargo", "cargo");
+
+        let dest = tempfile::tempdir().unwrap();
+        install_components(installer.path(), dest.path()).unwrap();
+        let read = |path: &str| std::fs::read_to_string(dest.path().join(path)).unwrap();
+        assert_eq!(read("bin/rustc"), "rustc");
+        assert_eq!(read("bin/cargo"), "cargo");
+        assert_eq!(
+            read("lib/rustlib/etc/gdb_load_rust_pretty_printers.py"),
+            "gdb"
+        );
+        assert!(!dest.path().join("install.sh").exists());
+
+        write("cargo/manifest.in", "file:../escape\n");
+        assert!(install_components(installer.path(), dest.path()).is_err());
+    }
+
+    #[test]
+    fn test_patch_repr() {
+        macro_rules! test_patch {
+            ($str:expr => $source:expr) => {
+                let patch = CratePatch {
+                    name: "example".to_string(),
+                    source: $source,
+                };
+                assert_eq!(CratePatch::from_str($str).unwrap(), patch);
+                assert_eq!(&patch.to_string(), $str);
+            };
+        }
+
+        test_patch!("example=https://git.example.com/repo=master" => PatchSource::Git {
+            repo: "https://git.example.com/repo".to_string(),
+            reference: GitReference::Branch("master".to_string()),
+        });
+        test_patch!("example=https://git.example.com/repo?a=b=tag:v1.0.0" => PatchSource::Git {
+            repo: "https://git.example.com/repo?a=b".to_string(),
+            reference: GitReference::Tag("v1.0.0".to_string()),
+        });
+        test_patch!("example=https://git.example.com/repo?a=b&c=d=feature=x" => PatchSource::Git {
+            repo: "https://git.example.com/repo?a=b&c=d".to_string(),
+            reference: GitReference::Branch("feature=x".to_string()),
+        });
+        test_patch!("example=https://git.example.com/repo=topic/a=b" => PatchSource::Git {
+            repo: "https://git.example.com/repo".to_string(),
+            reference: GitReference::Branch("topic/a=b".to_string()),
+        });
+        test_patch!("example=https://git.example.com/repo=rev:0123abc" => PatchSource::Git {
+            repo: "https://git.example.com/repo".to_string(),
+            reference: GitReference::Rev("0123abc".to_string()),
+        });
+        test_patch!("example=version:1.0.0-beta.1" => PatchSource::Registry {
+            version: "1.0.0-beta.1".to_string(),
+        });
+        test_patch!("example=path:/opt/patches/example" => PatchSource::Path {
+            path: PathBuf::from("/opt/patches/example"),
+        });
+
+        assert!(CratePatch::from_str("example").is_err());
+        assert!(CratePatch::from_str("=version:1.0.0").is_err());
+        assert!(CratePatch::from_str("example=https://git.example.com/repo").is_err());
+        assert!(CratePatch::from_str("example=https://git.example.com/repo=").is_err());
+        assert!(CratePatch::from_str("example=https://git.example.com/repo=tag:").is_err());
+        assert!(CratePatch::from_str("example=version:").is_err());
+        assert!(CratePatch::from_str("example=path:").is_err());
     }
 }
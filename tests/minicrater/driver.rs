@@ -160,7 +160,14 @@ impl MinicraterRun {
         // Define the experiment
         let mode = format!("--mode={}", self.mode);
         let crate_select = format!("--crate-select={}", self.crate_select);
-        let mut define_args = vec!["define-ex", &ex_arg, &crate_select, &mode];
+        // Toolchains are not pinned, to keep the log paths in the reports stable
+        let mut define_args = vec![
+            "define-ex",
+            &ex_arg,
+            &crate_select,
+            &mode,
+            "--no-pin-toolchains",
+        ];
         define_args.extend(self.toolchains);
         if self.ignore_blacklist {
             define_args.push("--ignore-blacklist");